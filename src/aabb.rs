@@ -0,0 +1,197 @@
+use crate::interval::EMPTY_INTERVAL;
+use crate::Interval;
+use crate::Point3;
+use crate::Ray;
+
+// axis-aligned bounding box, one interval per axis
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Aabb {
+    pub x: Interval,
+    pub y: Interval,
+    pub z: Interval,
+}
+
+pub const EMPTY_AABB: Aabb = Aabb {
+    x: EMPTY_INTERVAL,
+    y: EMPTY_INTERVAL,
+    z: EMPTY_INTERVAL,
+};
+
+impl Aabb {
+    // treat the two points a and b as extrema for the bounding box,
+    // so no particular minimum/maximum coordinate order is required
+    pub fn from_points(a: &Point3, b: &Point3) -> Self {
+        Aabb {
+            x: Interval::new(a.x().min(b.x()), a.x().max(b.x())),
+            y: Interval::new(a.y().min(b.y()), a.y().max(b.y())),
+            z: Interval::new(a.z().min(b.z()), a.z().max(b.z())),
+        }
+    }
+
+    // the tightest box enclosing both given boxes
+    pub fn enclosing(a: &Aabb, b: &Aabb) -> Self {
+        Aabb {
+            x: Interval::enclosing(&a.x, &b.x),
+            y: Interval::enclosing(&a.y, &b.y),
+            z: Interval::enclosing(&a.z, &b.z),
+        }
+    }
+
+    // the box widened so that no side is thinner than a small delta, planar objects
+    // have flat boxes which rays travelling along the plane would miss
+    pub fn pad_to_minimums(&self) -> Self {
+        let delta = 0.0001;
+        let pad = |interval: &Interval| {
+            if interval.size() < delta {
+                interval.expand(delta)
+            } else {
+                *interval
+            }
+        };
+        Aabb {
+            x: pad(&self.x),
+            y: pad(&self.y),
+            z: pad(&self.z),
+        }
+    }
+
+    pub fn axis_interval(&self, axis: usize) -> &Interval {
+        match axis {
+            1 => &self.y,
+            2 => &self.z,
+            _ => &self.x,
+        }
+    }
+
+    // index of the axis along which the box is the widest
+    pub fn longest_axis(&self) -> usize {
+        if self.x.size() > self.y.size() {
+            if self.x.size() > self.z.size() {
+                0
+            } else {
+                2
+            }
+        } else if self.y.size() > self.z.size() {
+            1
+        } else {
+            2
+        }
+    }
+
+    pub fn centroid(&self) -> Point3 {
+        Point3::new(
+            (self.x.min + self.x.max) / 2.0,
+            (self.y.min + self.y.max) / 2.0,
+            (self.z.min + self.z.max) / 2.0,
+        )
+    }
+
+    // total area of the six faces, used by the surface area heuristic
+    pub fn surface_area(&self) -> f64 {
+        let (dx, dy, dz) = (self.x.size(), self.y.size(), self.z.size());
+        if dx < 0.0 || dy < 0.0 || dz < 0.0 {
+            return 0.0;
+        }
+        2.0 * (dx * dy + dy * dz + dz * dx)
+    }
+
+    // slab test, true if the ray passes through the box within ray_t
+    pub fn hit(&self, ray: &Ray, ray_t: &Interval) -> bool {
+        let mut t_min = ray_t.min;
+        let mut t_max = ray_t.max;
+        for axis in 0..3 {
+            let ax = self.axis_interval(axis);
+            let inv_d = 1.0 / ray.dir[axis];
+            let origin = ray.orig[axis];
+
+            let mut t0 = (ax.min - origin) * inv_d;
+            let mut t1 = (ax.max - origin) * inv_d;
+            if inv_d < 0.0 {
+                std::mem::swap(&mut t0, &mut t1);
+            }
+            if t0 > t_min {
+                t_min = t0;
+            }
+            if t1 < t_max {
+                t_max = t1;
+            }
+            if t_max <= t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+impl Default for Aabb {
+    fn default() -> Self {
+        EMPTY_AABB
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Vec3;
+
+    #[test]
+    fn test_from_points() {
+        let bbox = Aabb::from_points(&Point3::new(1.0, -1.0, 2.0), &Point3::new(-1.0, 1.0, 0.0));
+        assert_eq!(bbox.x, Interval::new(-1.0, 1.0));
+        assert_eq!(bbox.y, Interval::new(-1.0, 1.0));
+        assert_eq!(bbox.z, Interval::new(0.0, 2.0));
+        assert_eq!(bbox.surface_area(), 24.0);
+    }
+
+    #[test]
+    fn test_enclosing() {
+        let a = Aabb::from_points(&Point3::new(0.0, 0.0, 0.0), &Point3::new(1.0, 1.0, 1.0));
+        let b = Aabb::from_points(&Point3::new(2.0, -1.0, 0.5), &Point3::new(3.0, 0.0, 0.5));
+        let bbox = Aabb::enclosing(&a, &b);
+        assert_eq!(bbox.x, Interval::new(0.0, 3.0));
+        assert_eq!(bbox.y, Interval::new(-1.0, 1.0));
+        assert_eq!(bbox.longest_axis(), 0);
+        assert_eq!(Aabb::enclosing(&EMPTY_AABB, &a), a);
+    }
+
+    #[test]
+    fn test_hit() {
+        let bbox = Aabb::from_points(&Point3::new(-1.0, -1.0, -1.0), &Point3::new(1.0, 1.0, 1.0));
+        let toward = Ray {
+            orig: Point3::new(0.0, 0.0, 5.0),
+            dir: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        let away = Ray {
+            orig: Point3::new(0.0, 0.0, 5.0),
+            dir: Vec3::new(0.0, 0.0, 1.0),
+            time: 0.0,
+        };
+        let beside = Ray {
+            orig: Point3::new(2.0, 0.0, 5.0),
+            dir: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        let ray_t = Interval::new(0.001, 100.0);
+        assert!(bbox.hit(&toward, &ray_t));
+        assert!(!bbox.hit(&away, &ray_t));
+        assert!(!bbox.hit(&beside, &ray_t));
+        assert!(!bbox.hit(&toward, &Interval::new(0.001, 3.0)));
+    }
+
+    #[test]
+    fn test_pad_to_minimums() {
+        let flat = Aabb::from_points(&Point3::new(-1.0, -1.0, 0.0), &Point3::new(1.0, 1.0, 0.0));
+        let ray = Ray {
+            orig: Point3::new(0.0, 0.0, 5.0),
+            dir: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        let ray_t = Interval::new(0.001, 100.0);
+        assert!(!flat.hit(&ray, &ray_t));
+        let padded = flat.pad_to_minimums();
+        assert!(padded.hit(&ray, &ray_t));
+        assert_eq!(padded.x, flat.x);
+        assert!(padded.z.size() > 0.0);
+    }
+}
@@ -0,0 +1,268 @@
+use crate::aabb::Aabb;
+use crate::interval::Interval;
+use crate::sampler::Sampler;
+use crate::HitRecord;
+use crate::Hittable;
+use crate::Hittables;
+use crate::Point3;
+use crate::Ray;
+use std::cmp::Ordering;
+use std::str::FromStr;
+use std::sync::Arc;
+
+// how the objects of a bvh node are divided between its two children
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum SplitHeuristic {
+    // split at the middle of the centroid bounds along the longest axis
+    Midpoint,
+    // split into two halves with the same number of objects along the longest axis
+    EqualCounts,
+    // pick the split with the lowest surface area heuristic cost over all three axes
+    #[default]
+    Sah,
+}
+
+impl FromStr for SplitHeuristic {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "midpoint" => Ok(SplitHeuristic::Midpoint),
+            "equal-counts" | "equal_counts" => Ok(SplitHeuristic::EqualCounts),
+            "sah" => Ok(SplitHeuristic::Sah),
+            _ => Err(format!(
+                "unknown split heuristic '{}', expected midpoint, equal-counts or sah",
+                s
+            )),
+        }
+    }
+}
+
+// an object together with its bounding box, cached while the tree is built
+struct BuildItem {
+    bbox: Aabb,
+    centroid: Point3,
+    object: Arc<dyn Hittable>,
+}
+
+// a node of the bounding volume hierarchy, the children are either other nodes or
+// the scene objects themselves
+pub struct BvhNode {
+    left: Arc<dyn Hittable>,
+    right: Arc<dyn Hittable>,
+    bbox: Aabb,
+}
+
+impl BvhNode {
+    pub fn new(list: Hittables, heuristic: SplitHeuristic) -> Self {
+        Self::from_objects(list.objects, heuristic)
+    }
+
+    pub fn from_objects(objects: Vec<Arc<dyn Hittable>>, heuristic: SplitHeuristic) -> Self {
+        let mut items: Vec<BuildItem> = objects
+            .into_iter()
+            .map(|object| {
+                let bbox = object.bounding_box();
+                BuildItem {
+                    bbox,
+                    centroid: bbox.centroid(),
+                    object,
+                }
+            })
+            .collect();
+        match items.len() {
+            0 => {
+                let empty: Arc<dyn Hittable> = Arc::new(Hittables::default());
+                BvhNode {
+                    left: empty.clone(),
+                    right: empty,
+                    bbox: Aabb::default(),
+                }
+            }
+            1 => {
+                let item = items.pop().unwrap();
+                BvhNode {
+                    left: item.object.clone(),
+                    right: item.object,
+                    bbox: item.bbox,
+                }
+            }
+            _ => Self::build(&mut items, heuristic),
+        }
+    }
+
+    // recursively build the node for the given items, which must hold at least two objects
+    fn build(items: &mut [BuildItem], heuristic: SplitHeuristic) -> Self {
+        let bbox = items.iter().fold(Aabb::default(), |bbox, item| {
+            Aabb::enclosing(&bbox, &item.bbox)
+        });
+        let mid = match heuristic {
+            SplitHeuristic::Midpoint => Self::split_midpoint(items),
+            SplitHeuristic::EqualCounts => Self::split_equal_counts(items),
+            SplitHeuristic::Sah => Self::split_sah(items),
+        };
+        let (left_items, right_items) = items.split_at_mut(mid);
+        BvhNode {
+            left: Self::build_child(left_items, heuristic),
+            right: Self::build_child(right_items, heuristic),
+            bbox,
+        }
+    }
+
+    fn build_child(items: &mut [BuildItem], heuristic: SplitHeuristic) -> Arc<dyn Hittable> {
+        if items.len() == 1 {
+            items[0].object.clone()
+        } else {
+            Arc::new(Self::build(items, heuristic))
+        }
+    }
+
+    // box enclosing the centroids of the items, the split axis is chosen from it
+    fn centroid_bounds(items: &[BuildItem]) -> Aabb {
+        items.iter().fold(Aabb::default(), |bbox, item| {
+            Aabb::enclosing(&bbox, &Aabb::from_points(&item.centroid, &item.centroid))
+        })
+    }
+
+    fn compare_centroids(a: &BuildItem, b: &BuildItem, axis: usize) -> Ordering {
+        a.centroid[axis].total_cmp(&b.centroid[axis])
+    }
+
+    // partition the items around the middle of the centroid bounds and return the
+    // number of items on the left side
+    fn split_midpoint(items: &mut [BuildItem]) -> usize {
+        let bounds = Self::centroid_bounds(items);
+        let axis = bounds.longest_axis();
+        let interval = bounds.axis_interval(axis);
+        let middle = (interval.min + interval.max) / 2.0;
+
+        let mut mid = 0;
+        for i in 0..items.len() {
+            if items[i].centroid[axis] < middle {
+                items.swap(i, mid);
+                mid += 1;
+            }
+        }
+        if mid == 0 || mid == items.len() {
+            // all the centroids are at the same spot along the axis
+            return Self::split_equal_counts(items);
+        }
+        mid
+    }
+
+    fn split_equal_counts(items: &mut [BuildItem]) -> usize {
+        let axis = Self::centroid_bounds(items).longest_axis();
+        let mid = items.len() / 2;
+        items.select_nth_unstable_by(mid, |a, b| Self::compare_centroids(a, b, axis));
+        mid
+    }
+
+    // sweep every axis in centroid order and choose the split minimizing
+    // left_count * left_area + right_count * right_area
+    fn split_sah(items: &mut [BuildItem]) -> usize {
+        let n = items.len();
+        let mut best: Option<(f64, usize, usize)> = None; // (cost, axis, mid)
+        let mut right_areas = vec![0.0; n];
+        for axis in 0..3 {
+            items.sort_unstable_by(|a, b| Self::compare_centroids(a, b, axis));
+
+            // right_areas[i] is the surface area of the box around items[i..]
+            let mut right_box = Aabb::default();
+            for i in (1..n).rev() {
+                right_box = Aabb::enclosing(&right_box, &items[i].bbox);
+                right_areas[i] = right_box.surface_area();
+            }
+            let mut left_box = Aabb::default();
+            for mid in 1..n {
+                left_box = Aabb::enclosing(&left_box, &items[mid - 1].bbox);
+                let cost =
+                    mid as f64 * left_box.surface_area() + (n - mid) as f64 * right_areas[mid];
+                if best.is_none_or(|(best_cost, _, _)| cost < best_cost) {
+                    best = Some((cost, axis, mid));
+                }
+            }
+        }
+        let (_, axis, mid) = best.unwrap();
+        if axis != 2 {
+            items.sort_unstable_by(|a, b| Self::compare_centroids(a, b, axis));
+        }
+        mid
+    }
+}
+
+impl Hittable for BvhNode {
+    fn hit(&self, ray: &Ray, ray_t: &mut Interval) -> Option<HitRecord> {
+        if !self.bbox.hit(ray, ray_t) {
+            return None;
+        }
+        let hit_left = self.left.hit(ray, &mut ray_t.clone());
+        let mut right_t =
+            Interval::new(ray_t.min, hit_left.as_ref().map_or(ray_t.max, |rec| rec.t));
+        let hit_right = self.right.hit(ray, &mut right_t);
+        hit_right.or(hit_left)
+    }
+
+    fn hit_surface(&self, ray: &Ray, ray_t: &mut Interval) -> Option<HitRecord> {
+        if !self.bbox.hit(ray, ray_t) {
+            return None;
+        }
+        let hit_left = self.left.hit_surface(ray, &mut ray_t.clone());
+        let mut right_t =
+            Interval::new(ray_t.min, hit_left.as_ref().map_or(ray_t.max, |rec| rec.t));
+        let hit_right = self.right.hit_surface(ray, &mut right_t);
+        hit_right.or(hit_left)
+    }
+
+    fn transmittance(&self, ray: &Ray, ray_t: &Interval, sampler: &mut Sampler) -> f64 {
+        if !self.bbox.hit(ray, ray_t) {
+            return 1.0;
+        }
+        self.left.transmittance(ray, ray_t, sampler) * self.right.transmittance(ray, ray_t, sampler)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::INFINITY;
+    use crate::{Color, Lambertian, Sphere, Vec3};
+
+    fn spheres() -> Hittables {
+        let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let mut world = Hittables::default();
+        for i in 0..10 {
+            for j in 0..10 {
+                let center = Point3::new(i as f64, j as f64 * 0.7, -(i * j) as f64 * 0.1);
+                world.add(Arc::new(Sphere::new(center, 0.3, material.clone())));
+            }
+        }
+        world
+    }
+
+    #[test]
+    fn test_matches_linear_hit() {
+        let world = spheres();
+        for heuristic in [
+            SplitHeuristic::Midpoint,
+            SplitHeuristic::EqualCounts,
+            SplitHeuristic::Sah,
+        ] {
+            let bvh = BvhNode::from_objects(world.objects.clone(), heuristic);
+            assert_eq!(bvh.bounding_box(), world.bounding_box());
+            for k in 0..50 {
+                let ray = Ray {
+                    orig: Point3::new(4.5, 3.0, 10.0),
+                    dir: Vec3::new(k as f64 * 0.03 - 0.7, (k % 7) as f64 * 0.05 - 0.2, -1.0),
+                    time: 0.0,
+                };
+                let expected = world.hit(&ray, &mut Interval::new(0.001, INFINITY));
+                let actual = bvh.hit(&ray, &mut Interval::new(0.001, INFINITY));
+                assert_eq!(expected.map(|rec| rec.t), actual.map(|rec| rec.t));
+            }
+        }
+    }
+}
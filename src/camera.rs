@@ -1,8 +1,67 @@
+use crate::image::Image;
+use crate::sampler::Sampler;
+use crate::spectrum::Wavelengths;
 use crate::utils::*;
-use crate::write_color;
 use crate::Result;
-use crate::{Color, Hittable, Hittables, Interval, Point3, Ray, Vec3};
-use std::io::Write;
+use crate::{Color, HitRecord, Hittable, Hittables, Interval, Point3, Ray, Vec3};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Mutex;
+use std::thread;
+// what a ray that leaves the scene without hitting anything sees
+#[derive(Debug, Default, Clone, PartialEq)]
+pub enum Background {
+    // white at the bottom blending into blue at the top, like a daylight sky
+    #[default]
+    Gradient,
+    // the same color in every direction
+    Solid(Color),
+    // black, so only emissive materials light the scene
+    None,
+}
+
+impl Background {
+    pub fn color(&self, ray: &Ray) -> Color {
+        match self {
+            Background::Gradient => {
+                let unit_direction = Vec3::unit_vector(&ray.dir);
+                let a = 0.5 * (unit_direction.y() + 1.0);
+
+                Color::new(1.0, 1.0, 1.0) * (1.0 - a) + Color::new(0.5, 0.7, 1.0) * a
+            }
+            Background::Solid(color) => color.clone(),
+            Background::None => Color::default(),
+        }
+    }
+}
+
+// parses gradient, none or a solid color given as r,g,b
+impl FromStr for Background {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "gradient" => Ok(Background::Gradient),
+            "none" => Ok(Background::None),
+            color => {
+                let components: Vec<f64> = color
+                    .split(',')
+                    .filter_map(|c| c.trim().parse().ok())
+                    .collect();
+                match components[..] {
+                    [r, g, b] if color.split(',').count() == 3 => {
+                        Ok(Background::Solid(Color::new(r, g, b)))
+                    }
+                    _ => Err(format!(
+                        "unknown background '{}', expected gradient, none or a color r,g,b",
+                        s
+                    )),
+                }
+            }
+        }
+    }
+}
+
 #[derive(Debug, Default)]
 pub struct Camera {
     pub aspect_ratio: f64,
@@ -15,6 +74,12 @@ pub struct Camera {
     pub vup: Vec3,              // camera-relative "up" direction
     pub defocus_angle: f64,     // variation angle of rays through each pixel
     pub focus_dist: f64,        // distance from camera look from point to plane of perfect focus
+    pub threads: usize,         // number of render threads, 0 means one per available core
+    pub seed: u64,              // seed of the random numbers, same seed gives the same image
+    pub background: Background, // color of rays that hit nothing
+    pub shutter_open: f64,      // time the exposure starts, camera rays are spread over it
+    pub shutter_close: f64,     // time the exposure ends
+    pub spectral: bool,         // trace wavelengths rather than rgb, for dispersion
     image_height: u32,
     center: Point3, // Camera center
     pixel00_loc: Point3,
@@ -35,72 +100,189 @@ impl Camera {
         max_depth: i32,
         vfof: f64,
     ) -> Self {
-        let mut camera = Camera::default();
-        camera.aspect_ratio = aspect_ratio;
-        camera.image_width = image_width;
-        camera.samples_per_pixel = samples_per_pixel;
-        camera.max_depth = max_depth;
-        camera.vfov = vfof;
-        camera.look_from = Point3::new(0.0, 0.0, 0.0);
-        camera.look_at = Point3::new(0.0, 0.0, -1.0);
-        camera.vup = Vec3::new(0.0, 1.0, 0.0);
-        camera.defocus_angle = 10.0;
-        camera.focus_dist = 3.4;
-        camera
-    }
-
-    // rdner world and write result to writter
-    pub fn render<W>(&mut self, world: &Hittables, writer: &mut W) -> Result<()>
-    where
-        W: Write,
-    {
+        Camera {
+            aspect_ratio,
+            image_width,
+            samples_per_pixel,
+            max_depth,
+            vfov: vfof,
+            look_from: Point3::new(0.0, 0.0, 0.0),
+            look_at: Point3::new(0.0, 0.0, -1.0),
+            vup: Vec3::new(0.0, 1.0, 0.0),
+            defocus_angle: 10.0,
+            focus_dist: 3.4,
+            ..Default::default()
+        }
+    }
+
+    // check that the public settings describe a usable camera, they may come from a
+    // scene file or the command line
+    pub fn validate(&self) -> Result<()> {
+        if self.image_width == 0 {
+            return Err("image width must be at least 1 pixel".into());
+        }
+        if !(self.aspect_ratio.is_finite() && self.aspect_ratio > 0.0) {
+            return Err(format!("aspect ratio must be positive, not {}", self.aspect_ratio).into());
+        }
+        if self.samples_per_pixel == 0 {
+            return Err("samples per pixel must be at least 1".into());
+        }
+        if self.max_depth <= 0 {
+            return Err(format!("max depth must be at least 1, not {}", self.max_depth).into());
+        }
+        if !(self.vfov > 0.0 && self.vfov < 180.0) {
+            return Err(
+                format!("vfov must be between 0 and 180 degrees, not {}", self.vfov).into(),
+            );
+        }
+        if !(self.focus_dist.is_finite() && self.focus_dist > 0.0) {
+            return Err(format!("focus distance must be positive, not {}", self.focus_dist).into());
+        }
+        if !(self.defocus_angle.is_finite() && self.defocus_angle >= 0.0) {
+            return Err(format!(
+                "defocus angle must not be negative, not {}",
+                self.defocus_angle
+            )
+            .into());
+        }
+        // times run from 0 at the start of the frame to 1 at its end, where moving objects
+        // stop
+        if !(0.0 <= self.shutter_open
+            && self.shutter_open <= self.shutter_close
+            && self.shutter_close <= 1.0)
+        {
+            return Err(format!(
+                "shutter must open and close between times 0 and 1, not {} and {}",
+                self.shutter_open, self.shutter_close
+            )
+            .into());
+        }
+        let view_direction = &self.look_at - &self.look_from;
+        if view_direction.near_zero() {
+            return Err("look from and look at must be different points".into());
+        }
+        if self.vup.cross(&view_direction).near_zero() {
+            return Err("vup must not be parallel to the view direction".into());
+        }
+        Ok(())
+    }
+
+    // render world into an image holding the average color and coverage of each pixel,
+    // and the part of the color the background adds.
+    // lights are the objects sampled directly for the light they give off, they must also
+    // be part of world; an empty list leaves all the lighting to the scattered rays
+    pub fn render(&mut self, world: &dyn Hittable, lights: &Hittables) -> Image {
         self.initialize();
-        write!(
-            writer,
-            "P3\n{} {}\n255\n",
-            self.image_width, self.image_height
-        )?;
-        for j in 0..self.image_height {
-            for i in 0..self.image_width {
+        let rows = self.render_rows(&RenderScene { world, lights });
+        let mut image = Image::new(self.image_width, self.image_height);
+        let samples = self.samples_per_pixel as f64;
+        for (j, row) in rows.into_iter().enumerate() {
+            for (i, (pixel_color, background, coverage)) in row.into_iter().enumerate() {
+                image.set_pixel(i as u32, j as u32, pixel_color / samples);
+                image.set_background(i as u32, j as u32, background / samples);
+                image.set_alpha(i as u32, j as u32, coverage / samples);
+            }
+        }
+        image
+    }
+
+    // number of worker threads used by render
+    fn thread_count(&self) -> usize {
+        let threads = if self.threads == 0 {
+            thread::available_parallelism().map_or(1, |n| n.get())
+        } else {
+            self.threads
+        };
+        threads.min(self.image_height as usize).max(1)
+    }
+
+    // trace every scanline of the image, spreading the scanlines over worker threads.
+    // each worker takes the next unrendered scanline until none are left, and the
+    // accumulated (not yet averaged) colors, background colors and coverage are returned
+    // in top to bottom order
+    fn render_rows(&self, scene: &RenderScene) -> Vec<Vec<(Color, Color, f64)>> {
+        let next_row = AtomicU32::new(0);
+        let rows = Mutex::new(vec![Vec::new(); self.image_height as usize]);
+        thread::scope(|scope| {
+            for _ in 0..self.thread_count() {
+                scope.spawn(|| loop {
+                    let j = next_row.fetch_add(1, Ordering::Relaxed);
+                    if j >= self.image_height {
+                        break;
+                    }
+                    let row = self.render_row(j, scene);
+                    rows.lock().unwrap()[j as usize] = row;
+                });
+            }
+        });
+        rows.into_inner().unwrap()
+    }
+
+    fn render_row(&self, j: u32, scene: &RenderScene) -> Vec<(Color, Color, f64)> {
+        (0..self.image_width)
+            .map(|i| {
+                let mut sampler = Sampler::for_pixel(self.seed, i, j);
                 let mut pixel_color = Color::default();
+                let mut background = Color::default();
+                let mut coverage = 0.0;
                 for _ in 0..self.samples_per_pixel {
-                    let r = self.get_ray(i, j);
-                    pixel_color += &self.ray_color(&r, self.max_depth, world);
+                    let r = self.get_ray(i, j, &mut sampler);
+                    let wavelengths = self
+                        .spectral
+                        .then(|| Wavelengths::sample(sampler.random_f64()));
+                    let (sample_color, hit) =
+                        self.primary_ray_color(&r, scene, &mut sampler, wavelengths);
+                    let sample_color = match wavelengths {
+                        Some(wavelengths) => wavelengths.rgb_of(&sample_color),
+                        None => sample_color,
+                    };
+                    pixel_color += &sample_color;
+                    if hit {
+                        coverage += 1.0;
+                    } else {
+                        background += &sample_color;
+                    }
                 }
-
-                write_color(writer, &pixel_color, self.samples_per_pixel)?;
-            }
-        }
-        Ok(())
+                (pixel_color, background, coverage)
+            })
+            .collect()
     }
 
-    fn get_ray(&self, i: u32, j: u32) -> Ray {
+    fn get_ray(&self, i: u32, j: u32, sampler: &mut Sampler) -> Ray {
         // get a randomly-sampled camera ray for the pixel at location i,j, originating from the
         // camera defocus disk
         let pixel_center =
             &self.pixel00_loc + (&self.pixel_delta_u * i as f64) + (&self.pixel_delta_v * j as f64);
-        let pixel_sample = &pixel_center + self.pixel_sample_square();
+        let pixel_sample = &pixel_center + self.pixel_sample_square(sampler);
         let ray_origin = if self.defocus_angle < 0.0 {
             self.center.clone()
         } else {
-            self.defocus_disk_sample()
+            self.defocus_disk_sample(sampler)
         };
         let ray_direction = &pixel_sample - &ray_origin;
-        return Ray {
+        // a shutter that opens and closes at once takes no random number, so still
+        // scenes render the same as before there was a shutter
+        let time = if self.shutter_close > self.shutter_open {
+            sampler.random_f64_range(self.shutter_open, self.shutter_close)
+        } else {
+            self.shutter_open
+        };
+        Ray {
             orig: ray_origin,
             dir: ray_direction,
-        };
+            time,
+        }
     }
 
-    fn defocus_disk_sample(&self) -> Point3 {
-        let p = Vec3::random_in_unit_disk();
+    fn defocus_disk_sample(&self, sampler: &mut Sampler) -> Point3 {
+        let p = Vec3::random_in_unit_disk(sampler);
         &self.center + (p.x() * &self.defocus_disk_u) + (p.y() * &self.defocus_disk_v)
     }
 
-    fn pixel_sample_square(&self) -> Vec3 {
-        let px = -0.5 + random_f64();
-        let py = -0.5 + random_f64();
-        return (&self.pixel_delta_u * px) + (&self.pixel_delta_v * py);
+    fn pixel_sample_square(&self, sampler: &mut Sampler) -> Vec3 {
+        let px = -0.5 + sampler.random_f64();
+        let py = -0.5 + sampler.random_f64();
+        (&self.pixel_delta_u * px) + (&self.pixel_delta_v * py)
     }
 
     // initialize some internal state
@@ -138,24 +320,537 @@ impl Camera {
         self.defocus_disk_v = &self.v * defofus_radius;
     }
 
-    fn ray_color(&self, ray: &Ray, depth: i32, hittables: &Hittables) -> Color {
-        if depth == 0 {
+    // color seen along a camera ray, together with whether the ray hit any object,
+    // which gives the alpha channel of the image
+    fn primary_ray_color(
+        &self,
+        ray: &Ray,
+        scene: &RenderScene,
+        sampler: &mut Sampler,
+        wavelengths: Option<Wavelengths>,
+    ) -> (Color, bool) {
+        if self.max_depth <= 0 {
+            return (Color::default(), false);
+        }
+        match scene.world.hit(ray, &mut Interval::new(0.001, INFINITY)) {
+            Some(rec) => (
+                self.hit_color(ray, &rec, self.max_depth, scene, sampler, None, wavelengths),
+                true,
+            ),
+            None => (spectrum(&wavelengths, self.background.color(ray)), false),
+        }
+    }
+
+    // color seen along the ray. bsdf_pdf is the density the previous bounce chose the
+    // direction of the ray with, None for specular bounces which light sampling cannot
+    // reproduce. in spectral mode the color holds the light at the wavelengths
+    fn ray_color(
+        &self,
+        ray: &Ray,
+        depth: i32,
+        scene: &RenderScene,
+        sampler: &mut Sampler,
+        bsdf_pdf: Option<f64>,
+        wavelengths: Option<Wavelengths>,
+    ) -> Color {
+        if depth <= 0 {
             return Color::default();
         }
-        if let Some(rec) = hittables.hit(&ray, &mut Interval::new(0.001, INFINITY)) {
-            let mut scattered = Ray::default();
-            let mut attenuation = Color::default();
-            if rec
-                .material
-                .scatter(&ray, &rec, &mut attenuation, &mut scattered)
-            {
-                return &attenuation * &self.ray_color(&scattered, depth - 1, &hittables);
+        match scene.world.hit(ray, &mut Interval::new(0.001, INFINITY)) {
+            Some(rec) => self.hit_color(ray, &rec, depth, scene, sampler, bsdf_pdf, wavelengths),
+            None => spectrum(&wavelengths, self.background.color(ray)),
+        }
+    }
+
+    // color of the light leaving the hit point along the ray: the light emitted by the
+    // material plus the light scattered towards the ray origin. the light arriving at
+    // the hit point is estimated twice, once by sampling the lights directly and once by
+    // following the scattered ray, and the two are blended with multiple importance
+    // sampling weights
+    #[allow(clippy::too_many_arguments)]
+    fn hit_color(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        depth: i32,
+        scene: &RenderScene,
+        sampler: &mut Sampler,
+        bsdf_pdf: Option<f64>,
+        wavelengths: Option<Wavelengths>,
+    ) -> Color {
+        let mut color = spectrum(&wavelengths, rec.material.emitted(ray, rec));
+        if let Some(bsdf_pdf) = bsdf_pdf {
+            if !color.near_zero() {
+                let light_pdf = scene.lights.pdf_value(&ray.orig, &ray.dir, ray.time);
+                color *= power_heuristic(bsdf_pdf, light_pdf);
+            }
+        }
+        let mut scattered_wavelengths = wavelengths;
+        let srec = match &mut scattered_wavelengths {
+            Some(scattered_wavelengths) => {
+                rec.material
+                    .scatter_spectral(ray, rec, scattered_wavelengths, sampler)
+            }
+            None => rec.material.scatter(ray, rec, sampler),
+        };
+        // the lights are sampled whichever lobe the material picked, also a smooth one:
+        // the rough lobes the lights are sampled for are not chosen every time
+        if !rec.material.is_emissive() && !scene.lights.objects.is_empty() {
+            color += &self.sample_lights(ray, rec, scene, sampler, &wavelengths);
+        }
+        let Some(srec) = srec else {
+            return color;
+        };
+        let mut incoming = self.ray_color(
+            &srec.scattered,
+            depth - 1,
+            scene,
+            sampler,
+            srec.pdf,
+            scattered_wavelengths,
+        );
+        if let (Some(before), Some(after)) = (wavelengths, scattered_wavelengths) {
+            // only the hero wavelength went on, and stands for all three
+            if after.secondary_terminated() && !before.secondary_terminated() {
+                incoming = Color::new(3.0 * incoming.x(), 0.0, 0.0);
             }
+        }
+        color + &srec.attenuation * &incoming
+    }
+
+    // light arriving from a direction chosen towards the lights, weighted against the
+    // chance of the material scattering into the same direction
+    fn sample_lights(
+        &self,
+        ray: &Ray,
+        rec: &HitRecord,
+        scene: &RenderScene,
+        sampler: &mut Sampler,
+        wavelengths: &Option<Wavelengths>,
+    ) -> Color {
+        let direction = scene.lights.random(&rec.point, ray.time, sampler);
+        let light_pdf = scene.lights.pdf_value(&rec.point, &direction, ray.time);
+        if light_pdf <= 0.0 {
+            return Color::default();
+        }
+        let bsdf = match wavelengths {
+            Some(wavelengths) => rec
+                .material
+                .eval_spectral(ray, rec, &direction, wavelengths),
+            None => rec.material.eval(ray, rec, &direction),
+        };
+        if bsdf.near_zero() {
+            return Color::default();
+        }
+        let light_ray = Ray {
+            orig: rec.point.clone(),
+            dir: direction,
+            time: ray.time,
+        };
+        // the light reaches the hit point through any media on the way, weakened by them
+        let Some(light_rec) = scene
+            .world
+            .hit_surface(&light_ray, &mut Interval::new(0.001, INFINITY))
+        else {
+            return Color::default();
+        };
+        let emitted = light_rec.material.emitted(&light_ray, &light_rec);
+        if emitted.near_zero() {
             return Color::default();
         }
-        let unit_direction = Vec3::unit_vector(&ray.dir);
-        let a = 0.5 * (unit_direction.y() + 1.0);
+        let transmittance =
+            scene
+                .world
+                .transmittance(&light_ray, &Interval::new(0.001, light_rec.t), sampler);
+        let bsdf_pdf = rec.material.scattering_pdf(ray, rec, &light_ray.dir);
+        let light = &bsdf * &spectrum(wavelengths, emitted);
+        light * (transmittance * power_heuristic(light_pdf, bsdf_pdf) / light_pdf)
+    }
+}
+
+// the objects a render traces rays against
+struct RenderScene<'a> {
+    world: &'a dyn Hittable,
+    lights: &'a Hittables,
+}
+
+// an rgb color as the values of its spectrum at the wavelengths in spectral mode
+fn spectrum(wavelengths: &Option<Wavelengths>, rgb: Color) -> Color {
+    match wavelengths {
+        Some(wavelengths) => wavelengths.spectrum_of(&rgb),
+        None => rgb,
+    }
+}
+
+// multiple importance sampling weight of a sample drawn with density pdf, when the
+// other strategy would have drawn it with density other_pdf
+fn power_heuristic(pdf: f64, other_pdf: f64) -> f64 {
+    let (a, b) = (pdf * pdf, other_pdf * other_pdf);
+    if a + b == 0.0 {
+        return 0.0;
+    }
+    a / (a + b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::{Exr, ExrPrecision, PpmAscii};
+    use crate::material::{DiffuseLight, HenyeyGreenstein, Principled, PrincipledParams};
+    use crate::medium::GridMedium;
+    use crate::perlin::Perlin;
+    use crate::quad::Quad;
+    use crate::texture::SolidColor;
+    use crate::traits::ImageEncoder;
+    use crate::transform::{Placement, Transform};
+    use crate::voxels::VoxelGrid;
+    use crate::{Hittables, Lambertian, Sphere};
+    use std::sync::Arc;
+
+    #[test]
+    fn test_render_ppm_layout() {
+        let mut world = Hittables::default();
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0.0, 0.0, -1.0),
+            0.5,
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        )));
+        let mut camera = Camera::new(2.0, 16, 2, 5, 90.0);
+        camera.threads = 3;
+        let image = camera.render(&world, &Hittables::default());
+        let mut output = Vec::new();
+        PpmAscii.encode(&image, &mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(&lines[..3], &["P3", "16 8", "255"]);
+        assert_eq!(lines.len(), 3 + 16 * 8);
+        assert!(lines[3..].iter().all(|line| line
+            .split(' ')
+            .filter_map(|c| c.parse::<u32>().ok())
+            .count()
+            == 3));
+    }
 
-        Color::new(1.0, 1.0, 1.0) * (1.0 - a) + Color::new(0.5, 0.7, 1.0) * a
+    #[test]
+    fn test_exr_alpha_leaves_out_the_background() {
+        // a camera ray through the corner of the image misses the sphere and sees the sky,
+        // which the straight colors keep and the premultiplied ones of an exr drop
+        let mut world = Hittables::default();
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0.0, 0.0, -1.0),
+            0.5,
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        )));
+        let mut camera = Camera::new(1.0, 3, 4, 5, 90.0);
+        let image = camera.render(&world, &Hittables::default());
+        assert_eq!(image.alpha()[0], 0.0);
+        assert!(!image.pixels()[0].near_zero());
+        assert_eq!(image.alpha()[4], 1.0);
+
+        let mut output = Vec::new();
+        Exr {
+            precision: ExrPrecision::Float,
+            alpha: true,
+        }
+        .encode(&image, &mut output)
+        .unwrap();
+        // the first scanline is the last chunk: y, data size, then the A, B, G and R
+        // floats of its three pixels
+        let line = &output[output.len() - 3 * (8 + 3 * 4 * 4)..][8..];
+        let sample = |channel: usize, i: usize| {
+            f32::from_le_bytes(line[(channel * 3 + i) * 4..][..4].try_into().unwrap())
+        };
+        assert_eq!([0, 1, 2, 3].map(|channel| sample(channel, 0)), [0.0; 4]);
+    }
+
+    #[test]
+    fn test_background_from_str() {
+        assert_eq!("gradient".parse(), Ok(Background::Gradient));
+        assert_eq!("None".parse(), Ok(Background::None));
+        assert_eq!(
+            "0.1, 0.2,3".parse(),
+            Ok(Background::Solid(Color::new(0.1, 0.2, 3.0)))
+        );
+        assert!("0.1,0.2".parse::<Background>().is_err());
+        assert!("sky".parse::<Background>().is_err());
+    }
+
+    #[test]
+    fn test_emission() {
+        let mut world = Hittables::default();
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0.0, 0.0, 0.0),
+            10.0,
+            Arc::new(DiffuseLight::new(Color::new(2.0, 3.0, 4.0))),
+        )));
+        let mut camera = Camera::new(1.0, 4, 3, 5, 90.0);
+        camera.background = Background::None;
+        let image = camera.render(&world, &Hittables::default());
+        assert!(image
+            .pixels()
+            .iter()
+            .all(|pixel| pixel == &Color::new(2.0, 3.0, 4.0)));
+    }
+
+    #[test]
+    fn test_spectral_emission() {
+        // in spectral mode the light is traced at random wavelengths, averaged over many
+        // of them white light comes back white
+        let mut world = Hittables::default();
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0.0, 0.0, 0.0),
+            10.0,
+            Arc::new(DiffuseLight::new(Color::new(2.0, 2.0, 2.0))),
+        )));
+        let mut camera = Camera::new(1.0, 4, 256, 5, 90.0);
+        camera.background = Background::None;
+        camera.spectral = true;
+        let image = camera.render(&world, &Hittables::default());
+        let average = image
+            .pixels()
+            .iter()
+            .fold(Color::default(), |sum, pixel| &sum + pixel)
+            / image.pixels().len() as f64;
+        assert!(
+            (&average - &Color::new(2.0, 2.0, 2.0)).length() < 0.1,
+            "{:?}",
+            average
+        );
+    }
+
+    #[test]
+    fn test_validate() {
+        let camera = Camera::new(16.0 / 9.0, 400, 10, 10, 90.0);
+        assert!(camera.validate().is_ok());
+        let mut bad = Camera::new(16.0 / 9.0, 0, 10, 10, 90.0);
+        assert!(bad.validate().is_err());
+        bad = Camera::new(0.0, 400, 10, 10, 90.0);
+        assert!(bad.validate().is_err());
+        bad = Camera::new(16.0 / 9.0, 400, 10, 10, 90.0);
+        bad.vup = Vec3::new(0.0, 0.0, 2.0);
+        assert!(bad.validate().is_err());
+        for (open, close) in [(0.5, 0.2), (-0.1, 0.5), (0.0, 1.5), (f64::NAN, 1.0)] {
+            bad = Camera::new(16.0 / 9.0, 400, 10, 10, 90.0);
+            (bad.shutter_open, bad.shutter_close) = (open, close);
+            assert!(bad.validate().is_err(), "{} {}", open, close);
+        }
+    }
+
+    #[test]
+    fn test_render_is_reproducible() {
+        let mut world = Hittables::default();
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0.0, 0.0, -1.0),
+            0.5,
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        )));
+        let render = |threads: usize, seed: u64| {
+            let mut camera = Camera::new(1.0, 8, 4, 5, 90.0);
+            camera.threads = threads;
+            camera.seed = seed;
+            camera
+                .render(&world, &Hittables::default())
+                .pixels()
+                .to_vec()
+        };
+        let reference = render(1, 3);
+        assert_eq!(reference, render(4, 3));
+        assert_ne!(reference, render(1, 4));
+    }
+
+    #[test]
+    fn test_light_sampling_matches_bsdf_sampling() {
+        // a diffuse floor under a small lamp, rendered with and without sampling the lamp
+        // directly. both estimate the same image, so their averages must agree
+        let mut world = Hittables::default();
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0.0, -100.0, -1.0),
+            100.0,
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        )));
+        let lamp: Arc<dyn Hittable> = Arc::new(Sphere::new(
+            Point3::new(0.0, 1.5, -1.0),
+            0.3,
+            Arc::new(DiffuseLight::new(Color::new(10.0, 10.0, 10.0))),
+        ));
+        world.add(lamp.clone());
+        let mut lights = Hittables::default();
+        lights.add(lamp);
+        let average = |lights: &Hittables| {
+            let mut camera = Camera::new(1.0, 8, 256, 4, 90.0);
+            camera.look_from = Point3::new(0.0, 1.0, 1.0);
+            camera.look_at = Point3::new(0.0, 0.0, -1.0);
+            camera.background = Background::None;
+            let image = camera.render(&world, lights);
+            let sum: f64 = image.pixels().iter().map(|pixel| pixel.y()).sum();
+            sum / image.pixels().len() as f64
+        };
+        let with_mis = average(&lights);
+        let without = average(&Hittables::default());
+        assert!(with_mis > 0.0);
+        assert!(
+            (with_mis - without).abs() < 0.05 * without,
+            "{} {}",
+            with_mis,
+            without
+        );
+    }
+
+    #[test]
+    fn test_light_sampling_of_smooth_principled() {
+        // a half metallic mirror floor under the lamp. the smooth mirror lobe is picked for
+        // some samples, the lamp must still be sampled for the diffuse part of those
+        let mut world = Hittables::default();
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0.0, -100.0, -1.0),
+            100.0,
+            Arc::new(Principled::new(
+                Color::new(0.5, 0.5, 0.5),
+                PrincipledParams {
+                    metallic: 0.5,
+                    roughness: 0.0,
+                    ..Default::default()
+                },
+            )),
+        )));
+        let lamp: Arc<dyn Hittable> = Arc::new(Sphere::new(
+            Point3::new(0.0, 1.5, -1.0),
+            0.3,
+            Arc::new(DiffuseLight::new(Color::new(10.0, 10.0, 10.0))),
+        ));
+        world.add(lamp.clone());
+        let mut lights = Hittables::default();
+        lights.add(lamp);
+        let average = |lights: &Hittables| {
+            let mut camera = Camera::new(1.0, 8, 512, 4, 90.0);
+            camera.look_from = Point3::new(0.0, 1.0, 1.0);
+            camera.look_at = Point3::new(0.0, 0.0, -1.0);
+            camera.background = Background::None;
+            let image = camera.render(&world, lights);
+            let sum: f64 = image.pixels().iter().map(|pixel| pixel.y()).sum();
+            sum / image.pixels().len() as f64
+        };
+        let with_mis = average(&lights);
+        let without = average(&Hittables::default());
+        assert!(with_mis > 0.0);
+        assert!(
+            (with_mis - without).abs() < 0.03 * without,
+            "{} {}",
+            with_mis,
+            without
+        );
+    }
+
+    #[test]
+    fn test_light_sampling_through_media() {
+        // the floor and lamp again, with a noisy cloud around the lamp that scatters the
+        // light forwards and weakens shadow rays passing through it
+        let mut world = Hittables::default();
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0.0, -100.0, -1.0),
+            100.0,
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        )));
+        let lamp: Arc<dyn Hittable> = Arc::new(Sphere::new(
+            Point3::new(0.0, 1.5, -1.0),
+            0.3,
+            Arc::new(DiffuseLight::new(Color::new(10.0, 10.0, 10.0))),
+        ));
+        world.add(lamp.clone());
+        let phase = Arc::new(HenyeyGreenstein::with_texture(
+            Arc::new(SolidColor::new(Color::new(0.9, 0.9, 0.9))),
+            0.6,
+        ));
+        let boundary = Arc::new(Sphere::new(Point3::new(0.0, 1.2, -1.0), 1.0, phase.clone()));
+        let perlin = Perlin::new(&mut Sampler::new(4));
+        let grid = VoxelGrid::from_noise([8, 8, 8], &perlin, 3.0).unwrap();
+        world.add(Arc::new(GridMedium::new(
+            boundary,
+            Arc::new(grid),
+            0.5,
+            4.0,
+            phase,
+        )));
+        let mut lights = Hittables::default();
+        lights.add(lamp);
+        let average = |lights: &Hittables| {
+            let mut camera = Camera::new(1.0, 8, 512, 6, 90.0);
+            camera.look_from = Point3::new(0.0, 1.0, 1.0);
+            camera.look_at = Point3::new(0.0, 0.0, -1.0);
+            camera.background = Background::None;
+            let image = camera.render(&world, lights);
+            let sum: f64 = image.pixels().iter().map(|pixel| pixel.y()).sum();
+            sum / image.pixels().len() as f64
+        };
+        let with_mis = average(&lights);
+        let without = average(&Hittables::default());
+        assert!(with_mis > 0.0);
+        assert!(
+            (with_mis - without).abs() < 0.05 * without,
+            "{} {}",
+            with_mis,
+            without
+        );
+    }
+
+    #[test]
+    fn test_light_sampling_of_moving_lights() {
+        // the floor under a lamp sweeping sideways and a lamp panel turning and rising
+        // during the exposure, with the lights sampled where they are at the time of each
+        // ray. the image agrees with the one found by bsdf sampling alone
+        let mut world = Hittables::default();
+        world.add(Arc::new(Sphere::new(
+            Point3::new(0.0, -100.0, -1.0),
+            100.0,
+            Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))),
+        )));
+        let emit = Arc::new(DiffuseLight::new(Color::new(10.0, 10.0, 10.0)));
+        let lamp: Arc<dyn Hittable> = Arc::new(Sphere::moving(
+            Point3::new(-0.4, 1.5, -1.0),
+            &Point3::new(0.4, 1.5, -1.0),
+            0.3,
+            emit.clone(),
+        ));
+        let panel = Arc::new(Quad::new(
+            Point3::new(-0.3, 0.0, -0.3),
+            Vec3::new(0.6, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 0.6),
+            emit,
+        ));
+        let end = Placement {
+            rotate: Vec3::new(0.0, 45.0, 30.0),
+            translate: Vec3::new(0.0, 1.6, -1.5),
+            ..Placement::default()
+        };
+        let start = Placement {
+            translate: Vec3::new(-0.3, 1.0, -1.5),
+            ..Placement::default()
+        };
+        let panel: Arc<dyn Hittable> = Arc::new(Transform::moving(panel, start, end).unwrap());
+        world.add(lamp.clone());
+        world.add(panel.clone());
+        let mut lights = Hittables::default();
+        lights.add(lamp);
+        lights.add(panel);
+        let average = |lights: &Hittables| {
+            let mut camera = Camera::new(1.0, 8, 256, 4, 90.0);
+            camera.look_from = Point3::new(0.0, 1.0, 1.0);
+            camera.look_at = Point3::new(0.0, 0.0, -1.0);
+            camera.background = Background::None;
+            camera.shutter_open = 0.0;
+            camera.shutter_close = 1.0;
+            let image = camera.render(&world, lights);
+            let sum: f64 = image.pixels().iter().map(|pixel| pixel.y()).sum();
+            sum / image.pixels().len() as f64
+        };
+        let with_mis = average(&lights);
+        let without = average(&Hittables::default());
+        assert!(with_mis > 0.0);
+        assert!(
+            (with_mis - without).abs() < 0.05 * without,
+            "{} {}",
+            with_mis,
+            without
+        );
     }
 }
@@ -0,0 +1,243 @@
+use crate::bvh::SplitHeuristic;
+use crate::camera::{Background, Camera};
+use crate::encoder::OutputFormat;
+use crate::Vec3;
+use clap::Parser;
+use std::path::PathBuf;
+
+// command line arguments of the renderer, the camera flags override the values of the
+// scene file (or of the cover scene when no scene file is given)
+
+/// Path tracer from "Ray Tracing in One Weekend". Renders the cover scene of the book,
+/// or the scene described by a toml scene file.
+#[derive(Parser, Debug)]
+#[command(version, about)]
+pub struct Args {
+    /// Output image, the format follows the extension: .ppm (ascii P3), .pnm (binary P6),
+    /// .png (8 bit, --format png16 for 16 bit), .exr, .hdr or .pfm
+    #[arg(default_value = "images/image_0.ppm")]
+    pub output: PathBuf,
+
+    /// Output format, overriding the extension of the output file: p3, p6, png8, png16,
+    /// exr-half, exr-half-rgb, exr-float, exr-float-rgb, hdr or pfm
+    #[arg(short, long)]
+    pub format: Option<OutputFormat>,
+
+    /// Toml scene file, or gltf/glb file, to render instead of the cover scene
+    #[arg(short, long)]
+    pub scene: Option<PathBuf>,
+
+    /// Give the small balls of the cover scene random principled materials instead of the
+    /// diffuse, metal and glass ones of the book
+    #[arg(long, conflicts_with = "scene")]
+    pub principled: bool,
+
+    /// Seed of the random numbers, renders with the same seed are identical
+    #[arg(long, default_value_t = 0)]
+    pub seed: u64,
+
+    /// Number of render threads, 0 uses one thread per available core
+    #[arg(short = 'j', long, default_value_t = 0)]
+    pub threads: usize,
+
+    /// How the bounding volume hierarchy splits objects: midpoint, equal-counts or sah
+    #[arg(long, default_value = "sah")]
+    pub bvh: SplitHeuristic,
+
+    /// Ratio of image width over height, either a number or width:height such as 16:9
+    #[arg(long, value_parser = parse_aspect_ratio)]
+    pub aspect_ratio: Option<f64>,
+
+    /// Rendered image width in pixels
+    #[arg(short, long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub width: Option<u32>,
+
+    /// Number of random samples for each pixel
+    #[arg(long, value_parser = clap::value_parser!(u32).range(1..))]
+    pub samples: Option<u32>,
+
+    /// Maximum number of ray bounces into the scene
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..))]
+    pub depth: Option<i32>,
+
+    /// Vertical field of view in degrees
+    #[arg(long, value_parser = parse_fov)]
+    pub vfov: Option<f64>,
+
+    /// Point the camera is looking from, as x,y,z
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    pub look_from: Option<Vec3>,
+
+    /// Point the camera is looking at, as x,y,z
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    pub look_at: Option<Vec3>,
+
+    /// Camera-relative up direction, as x,y,z
+    #[arg(long, value_parser = parse_vec3, allow_hyphen_values = true)]
+    pub vup: Option<Vec3>,
+
+    /// Variation angle of rays through each pixel in degrees, 0 disables defocus blur
+    #[arg(long, value_parser = parse_non_negative)]
+    pub defocus_angle: Option<f64>,
+
+    /// Distance from the look from point to the plane of perfect focus
+    #[arg(long, value_parser = parse_positive)]
+    pub focus_dist: Option<f64>,
+
+    /// Trace light at random wavelengths instead of red, green and blue, which lets glass
+    /// with a dispersive index of refraction split light into its colors
+    #[arg(long)]
+    pub spectral: bool,
+
+    /// What rays leaving the scene see: gradient (the sky), none (black) or a color r,g,b
+    #[arg(long)]
+    pub background: Option<Background>,
+}
+
+impl Args {
+    // override the camera settings given on the command line
+    pub fn apply(&self, camera: &mut Camera) {
+        if let Some(aspect_ratio) = self.aspect_ratio {
+            camera.aspect_ratio = aspect_ratio;
+        }
+        if let Some(width) = self.width {
+            camera.image_width = width;
+        }
+        if let Some(samples) = self.samples {
+            camera.samples_per_pixel = samples;
+        }
+        if let Some(depth) = self.depth {
+            camera.max_depth = depth;
+        }
+        if let Some(vfov) = self.vfov {
+            camera.vfov = vfov;
+        }
+        if let Some(look_from) = &self.look_from {
+            camera.look_from = look_from.clone();
+        }
+        if let Some(look_at) = &self.look_at {
+            camera.look_at = look_at.clone();
+        }
+        if let Some(vup) = &self.vup {
+            camera.vup = vup.clone();
+        }
+        if let Some(defocus_angle) = self.defocus_angle {
+            camera.defocus_angle = defocus_angle;
+        }
+        if let Some(focus_dist) = self.focus_dist {
+            camera.focus_dist = focus_dist;
+        }
+        if let Some(background) = &self.background {
+            camera.background = background.clone();
+        }
+        if self.spectral {
+            camera.spectral = true;
+        }
+        camera.threads = self.threads;
+        camera.seed = self.seed;
+    }
+}
+
+fn parse_number(s: &str) -> std::result::Result<f64, String> {
+    let x: f64 = s
+        .trim()
+        .parse()
+        .map_err(|_| format!("'{}' is not a number", s))?;
+    if !x.is_finite() {
+        return Err(format!("'{}' is not a finite number", s));
+    }
+    Ok(x)
+}
+
+fn parse_positive(s: &str) -> std::result::Result<f64, String> {
+    let x = parse_number(s)?;
+    if x <= 0.0 {
+        return Err(format!("{} is not positive", x));
+    }
+    Ok(x)
+}
+
+fn parse_non_negative(s: &str) -> std::result::Result<f64, String> {
+    let x = parse_number(s)?;
+    if x < 0.0 {
+        return Err(format!("{} is negative", x));
+    }
+    Ok(x)
+}
+
+fn parse_aspect_ratio(s: &str) -> std::result::Result<f64, String> {
+    match s.split_once(':') {
+        Some((width, height)) => Ok(parse_positive(width)? / parse_positive(height)?),
+        None => parse_positive(s),
+    }
+}
+
+fn parse_fov(s: &str) -> std::result::Result<f64, String> {
+    let x = parse_number(s)?;
+    if x <= 0.0 || x >= 180.0 {
+        return Err(format!("{} is not between 0 and 180 degrees", x));
+    }
+    Ok(x)
+}
+
+fn parse_vec3(s: &str) -> std::result::Result<Vec3, String> {
+    let components: Vec<&str> = s.split(',').collect();
+    if components.len() != 3 {
+        return Err(format!("'{}' is not three comma separated numbers", s));
+    }
+    Ok(Vec3::new(
+        parse_number(components[0])?,
+        parse_number(components[1])?,
+        parse_number(components[2])?,
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn test_command() {
+        Args::command().debug_assert();
+    }
+
+    #[test]
+    fn test_apply() {
+        let args = Args::try_parse_from([
+            "raytracing1",
+            "out.png",
+            "--width",
+            "64",
+            "--aspect-ratio",
+            "4:3",
+            "--look-from",
+            "-1,2.5,3",
+            "--seed",
+            "9",
+        ])
+        .unwrap();
+        let mut camera = Camera::new(1.0, 400, 10, 10, 90.0);
+        args.apply(&mut camera);
+        assert_eq!(camera.image_width, 64);
+        assert_eq!(camera.aspect_ratio, 4.0 / 3.0);
+        assert_eq!(camera.look_from, Vec3::new(-1.0, 2.5, 3.0));
+        assert_eq!(camera.samples_per_pixel, 10);
+        assert_eq!(camera.seed, 9);
+    }
+
+    #[test]
+    fn test_validation() {
+        for bad in [
+            vec!["--width", "0"],
+            vec!["--aspect-ratio", "-1.5"],
+            vec!["--aspect-ratio", "16:0"],
+            vec!["--vfov", "180"],
+            vec!["--look-at", "1,2"],
+            vec!["--format", "bmp"],
+        ] {
+            let args = [vec!["raytracing1"], bad.clone()].concat();
+            assert!(Args::try_parse_from(args).is_err(), "{:?}", bad);
+        }
+    }
+}
@@ -13,26 +13,62 @@ fn linear_to_gamma(linear_component: f64) -> f64 {
     linear_component.sqrt()
 }
 
+// the inverse of the gamma correction of the output, for colors given gamma corrected
+pub fn gamma_to_linear(gamma_component: f64) -> f64 {
+    gamma_component * gamma_component
+}
+
+// the srgb transfer function undone, for the channels of image files
+pub fn srgb_to_linear(srgb_component: f64) -> f64 {
+    if srgb_component <= 0.04045 {
+        srgb_component / 12.92
+    } else {
+        ((srgb_component + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+// gamma corrected channels of the color, clamped to [0, 1)
+fn gamma_components(c: &Color) -> [f64; 3] {
+    [c.x(), c.y(), c.z()].map(|linear| COLOR_INTERVAL.clamp(linear_to_gamma(linear)))
+}
+
+// gamma corrected color scaled to 8 bits per channel
+pub fn to_rgb8(c: &Color) -> [u8; 3] {
+    gamma_components(c).map(|component| (component * 256.0) as u8)
+}
+
+// gamma corrected color scaled to 16 bits per channel, rounded so that white is the
+// largest value
+pub fn to_rgb16(c: &Color) -> [u16; 3] {
+    [c.x(), c.y(), c.z()]
+        .map(|linear| (linear_to_gamma(linear).clamp(0.0, 1.0) * 65535.0).round() as u16)
+}
+
 pub fn write_color<W>(writer: &mut W, c: &Color, samples_per_pixel: u32) -> Result<()>
 where
-    W: Write,
+    W: Write + ?Sized,
 {
     // Divide the color by number of samples per pixel
-    let r = c.x() / samples_per_pixel as f64;
-    let g = c.y() / samples_per_pixel as f64;
-    let b = c.z() / samples_per_pixel as f64;
-
-    // apply linear to gamma transform
-    let r = linear_to_gamma(r);
-    let g = linear_to_gamma(g);
-    let b = linear_to_gamma(b);
-
-    write!(
-        writer,
-        "{} {} {}\n",
-        (COLOR_INTERVAL.clamp(r) * 256.0) as u32,
-        (COLOR_INTERVAL.clamp(g) * 256.0) as u32,
-        (COLOR_INTERVAL.clamp(b) * 256.0) as u32
-    )?;
+    let c = c / samples_per_pixel as f64;
+    let [r, g, b] = to_rgb8(&c);
+    writeln!(writer, "{} {} {}", r, g, b)?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_color() {
+        let mut output = Vec::new();
+        write_color(&mut output, &Color::new(1.0, 0.25, 8.0), 4).unwrap();
+        assert_eq!(String::from_utf8(output).unwrap(), "128 64 255\n");
+    }
+
+    #[test]
+    fn test_to_rgb16() {
+        assert_eq!(to_rgb16(&Color::new(0.0, 0.25, 2.0)), [0, 32768, 65535]);
+        assert_eq!(to_rgb16(&Color::new(1.0, -1.0, 1e-12)), [65535, 0, 0]);
+    }
+}
@@ -0,0 +1,336 @@
+use crate::color::srgb_to_linear;
+use crate::Result;
+use std::fs;
+use std::path::Path;
+
+// pixels of an image file as linear rgb, stored row by row from the top left corner.
+// f32 keeps large texture maps at half the size of colors
+pub struct Texels {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<[f32; 3]>,
+}
+
+// read the png, jpeg or Radiance rgbe (.hdr) image at path, telling the format
+// from the first bytes of the file
+pub fn read_image(path: &Path) -> Result<Texels> {
+    let data =
+        fs::read(path).map_err(|err| format!("cannot read image {}: {}", path.display(), err))?;
+    decode_image(&data, &path.display().to_string())
+}
+
+pub fn decode_image(data: &[u8], name: &str) -> Result<Texels> {
+    let texels = if data.starts_with(b"\x89PNG") {
+        decode_png(data)
+    } else if data.starts_with(&[0xff, 0xd8]) {
+        decode_jpeg(data)
+    } else if data.starts_with(b"#?") {
+        decode_rgbe(data)
+    } else {
+        Err("not a png, jpeg or hdr image".into())
+    }
+    .map_err(|err| format!("{}: {}", name, err))?;
+    if texels.width == 0 || texels.height == 0 {
+        return Err(format!("{}: image has no pixels", name).into());
+    }
+    Ok(texels)
+}
+
+// 8 bit srgb values to linear, looked up rather than computed for every pixel
+fn srgb8_table() -> [f32; 256] {
+    std::array::from_fn(|i| srgb_to_linear(i as f64 / 255.0) as f32)
+}
+
+// gray, gray and alpha, rgb or rgba pixels of channels values each, to linear rgb.
+// alpha is dropped
+fn to_rgb(values: &[f32], channels: usize) -> Vec<[f32; 3]> {
+    values
+        .chunks_exact(channels)
+        .map(|pixel| match channels {
+            1 | 2 => [pixel[0]; 3],
+            _ => [pixel[0], pixel[1], pixel[2]],
+        })
+        .collect()
+}
+
+fn decode_png(data: &[u8]) -> Result<Texels> {
+    let mut decoder = png::Decoder::new(data);
+    // palettes and gray below 8 bits become 8 bit rgb and gray
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let mut reader = decoder.read_info()?;
+    let mut buf = vec![0; reader.output_buffer_size()];
+    let info = reader.next_frame(&mut buf)?;
+    let buf = &buf[..info.buffer_size()];
+    let channels = info.color_type.samples();
+    // png holds srgb values unless it says otherwise, which is rare enough to ignore
+    let values: Vec<f32> = match info.bit_depth {
+        png::BitDepth::Sixteen => buf
+            .chunks_exact(2)
+            .map(|bytes| {
+                let value = u16::from_be_bytes([bytes[0], bytes[1]]) as f64 / 65535.0;
+                srgb_to_linear(value) as f32
+            })
+            .collect(),
+        _ => {
+            let table = srgb8_table();
+            buf.iter().map(|&value| table[value as usize]).collect()
+        }
+    };
+    Ok(Texels {
+        width: info.width,
+        height: info.height,
+        pixels: to_rgb(&values, channels),
+    })
+}
+
+fn decode_jpeg(data: &[u8]) -> Result<Texels> {
+    let mut decoder = jpeg_decoder::Decoder::new(data);
+    let buf = decoder.decode()?;
+    let info = decoder.info().ok_or("jpeg without a frame header")?;
+    let table = srgb8_table();
+    let values: Vec<f32> = match info.pixel_format {
+        jpeg_decoder::PixelFormat::L8 | jpeg_decoder::PixelFormat::RGB24 => {
+            buf.iter().map(|&value| table[value as usize]).collect()
+        }
+        jpeg_decoder::PixelFormat::L16 => buf
+            .chunks_exact(2)
+            .map(|bytes| {
+                let value = u16::from_be_bytes([bytes[0], bytes[1]]) as f64 / 65535.0;
+                srgb_to_linear(value) as f32
+            })
+            .collect(),
+        jpeg_decoder::PixelFormat::CMYK32 => {
+            return Err("cmyk jpeg images are not supported".into())
+        }
+    };
+    let channels = match info.pixel_format {
+        jpeg_decoder::PixelFormat::RGB24 => 3,
+        _ => 1,
+    };
+    Ok(Texels {
+        width: info.width as u32,
+        height: info.height as u32,
+        pixels: to_rgb(&values, channels),
+    })
+}
+
+// linear color of a pixel with a shared exponent, see encoder::Rgbe
+fn from_rgbe(rgbe: [u8; 4]) -> [f32; 3] {
+    if rgbe[3] == 0 {
+        return [0.0; 3];
+    }
+    // the mantissas are the lower ends of their steps, take the middle
+    let scale = 2f64.powi(rgbe[3] as i32 - 128 - 8);
+    [0, 1, 2].map(|i| ((rgbe[i] as f64 + 0.5) * scale) as f32)
+}
+
+fn decode_rgbe(data: &[u8]) -> Result<Texels> {
+    // text header lines up to an empty line, then the resolution line
+    let mut pos = 0;
+    let mut next_line = || -> Result<&str> {
+        let len = data[pos..]
+            .iter()
+            .position(|&b| b == b'\n')
+            .ok_or("hdr header ends early")?;
+        let line =
+            std::str::from_utf8(&data[pos..pos + len]).map_err(|_| "hdr header is not text")?;
+        pos += len + 1;
+        Ok(line.trim_end_matches('\r'))
+    };
+    loop {
+        let line = next_line()?;
+        if line.is_empty() {
+            break;
+        }
+        if let Some(format) = line.strip_prefix("FORMAT=") {
+            if format != "32-bit_rle_rgbe" {
+                return Err(format!("unsupported hdr format {}", format).into());
+            }
+        }
+    }
+    let resolution = next_line()?;
+    let size = match resolution.split_whitespace().collect::<Vec<_>>()[..] {
+        ["-Y", height, "+X", width] => width.parse().ok().zip(height.parse().ok()),
+        _ => None,
+    };
+    let Some((width, height)): Option<(usize, usize)> = size else {
+        return Err(format!(
+            "unsupported hdr resolution line '{}', expected -Y height +X width",
+            resolution
+        )
+        .into());
+    };
+
+    let mut data = &data[pos..];
+    // the header is not to be trusted, every scanline takes at least a pixel of 4 bytes
+    let count = width
+        .checked_mul(height)
+        .filter(|_| u32::try_from(width).is_ok() && u32::try_from(height).is_ok());
+    let Some(count) = count else {
+        return Err(format!("hdr image of {} by {} pixels is too large", width, height).into());
+    };
+    if width > 0 && height > data.len() / 4 {
+        return Err(format!("hdr pixels end early for {} scanlines", height).into());
+    }
+    let mut pixels = Vec::with_capacity(count);
+    let mut line = vec![[0u8; 4]; width];
+    for _ in 0..height {
+        data = read_rgbe_line(data, &mut line)?;
+        pixels.extend(line.iter().map(|&rgbe| from_rgbe(rgbe)));
+    }
+    Ok(Texels {
+        width: width as u32,
+        height: height as u32,
+        pixels,
+    })
+}
+
+// read a scanline into line, returning the data after it. the line is either run
+// length encoded one component after the other, or flat pixels where (1, 1, 1, n)
+// repeats the previous pixel
+fn read_rgbe_line<'a>(data: &'a [u8], line: &mut [[u8; 4]]) -> Result<&'a [u8]> {
+    // the next count bytes of data, which moves past them
+    fn take<'a>(data: &mut &'a [u8], count: usize) -> Result<&'a [u8]> {
+        if data.len() < count {
+            return Err("hdr pixels end early".into());
+        }
+        let (head, tail) = data.split_at(count);
+        *data = tail;
+        Ok(head)
+    }
+    let width = line.len();
+    let mut data = data;
+    if (8..0x8000).contains(&width) && data.len() >= 4 && data[0] == 2 && data[1] == 2 {
+        let marker = take(&mut data, 4)?;
+        if ((marker[2] as usize) << 8 | marker[3] as usize) != width {
+            return Err("hdr scanline width does not match the image".into());
+        }
+        for component in 0..4 {
+            let mut x = 0;
+            while x < width {
+                let count = take(&mut data, 1)?[0] as usize;
+                if count > 128 {
+                    let count = count - 128;
+                    let value = take(&mut data, 1)?[0];
+                    if x + count > width {
+                        return Err("hdr run goes past the end of the scanline".into());
+                    }
+                    line[x..x + count]
+                        .iter_mut()
+                        .for_each(|p| p[component] = value);
+                    x += count;
+                } else {
+                    if count == 0 || x + count > width {
+                        return Err("bad hdr run length".into());
+                    }
+                    let values = take(&mut data, count)?;
+                    for (p, &value) in line[x..x + count].iter_mut().zip(values) {
+                        p[component] = value;
+                    }
+                    x += count;
+                }
+            }
+        }
+        return Ok(data);
+    }
+    let mut x = 0;
+    let mut shift = 0;
+    while x < width {
+        let bytes = take(&mut data, 4)?;
+        let pixel = [bytes[0], bytes[1], bytes[2], bytes[3]];
+        if pixel[..3] == [1, 1, 1] && x > 0 {
+            let count = (pixel[3] as usize) << shift;
+            if x + count > width {
+                return Err("hdr run goes past the end of the scanline".into());
+            }
+            let previous = line[x - 1];
+            line[x..x + count].fill(previous);
+            x += count;
+            shift += 8;
+        } else {
+            line[x] = pixel;
+            x += 1;
+            shift = 0;
+        }
+    }
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::{Png, PngDepth, Rgbe};
+    use crate::image::Image;
+    use crate::traits::ImageEncoder;
+    use crate::Color;
+
+    fn test_image(width: u32) -> Image {
+        let mut image = Image::new(width, 2);
+        for i in 0..width {
+            image.set_pixel(i, 0, Color::new(0.25, 0.5, 1.0));
+            image.set_pixel(i, 1, Color::new(i as f64 * 3.0, 0.01, 0.0));
+        }
+        image
+    }
+
+    #[test]
+    fn test_rgbe_round_trip() {
+        // wide enough to be run length encoded, and too narrow
+        for width in [20, 3] {
+            let image = test_image(width);
+            let mut data = Vec::new();
+            Rgbe.encode(&image, &mut data).unwrap();
+            let texels = decode_image(&data, "test.hdr").unwrap();
+            assert_eq!((texels.width, texels.height), (width, 2));
+            for (texel, pixel) in texels.pixels.iter().zip(image.pixels()) {
+                // the channels share the precision of the brightest one
+                let components = [pixel.x(), pixel.y(), pixel.z()];
+                let step = components.iter().fold(0.0f64, |a, &b| a.max(b)) / 128.0;
+                for (a, b) in texel.iter().zip(components) {
+                    assert!((*a as f64 - b).abs() <= step, "{:?} {:?}", texel, pixel);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_png_is_srgb() {
+        let mut image = Image::new(1, 1);
+        image.set_pixel(0, 0, Color::new(0.0, 0.25, 1.0));
+        let mut data = Vec::new();
+        Png {
+            depth: PngDepth::Sixteen,
+        }
+        .encode(&image, &mut data)
+        .unwrap();
+        let texels = decode_image(&data, "test.png").unwrap();
+        // the png is written with a gamma of 2 but read as srgb: 0.25 is stored as 0.5
+        let expected = srgb_to_linear(0.5) as f32;
+        assert_eq!(texels.pixels[0][0], 0.0);
+        assert!((texels.pixels[0][1] - expected).abs() < 1e-4);
+        assert!((texels.pixels[0][2] - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_unknown_format() {
+        let err = decode_image(b"GIF89a", "test.gif").err().unwrap();
+        assert_eq!(err.to_string(), "test.gif: not a png, jpeg or hdr image");
+    }
+
+    #[test]
+    fn test_rgbe_size_beyond_data() {
+        let header = "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n";
+        let data = format!("{}-Y 100000000 +X 100000000\n", header);
+        let err = decode_image(data.as_bytes(), "huge.hdr").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "huge.hdr: hdr pixels end early for 100000000 scanlines"
+        );
+        let data = format!("{}-Y 18446744073709551615 +X 2\n", header);
+        let err = decode_image(data.as_bytes(), "huge.hdr").err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "huge.hdr: hdr image of 2 by 18446744073709551615 pixels is too large"
+        );
+    }
+}
@@ -0,0 +1,135 @@
+use crate::aabb::Aabb;
+use crate::interval::Interval;
+use crate::onb::Onb;
+use crate::sampler::Sampler;
+use crate::utils::{INFINITY, PI};
+use crate::HitRecord;
+use crate::Hittable;
+use crate::Point3;
+use crate::Ray;
+use crate::Scatter;
+use crate::Vec3;
+use std::sync::Arc;
+
+// flat disk around center, the front face is the side normal points to
+#[derive(Clone)]
+pub struct Disk {
+    pub center: Point3,
+    pub radius: f64,
+    material: Arc<dyn Scatter>,
+    // w is the unit normal, u and v span the plane of the disk
+    uvw: Onb,
+}
+
+impl Disk {
+    pub fn new(center: Point3, normal: &Vec3, radius: f64, material: Arc<dyn Scatter>) -> Self {
+        Disk {
+            center,
+            radius,
+            material,
+            uvw: Onb::new(normal),
+        }
+    }
+}
+
+impl Hittable for Disk {
+    fn hit(&self, ray: &Ray, ray_t: &mut Interval) -> Option<HitRecord> {
+        let normal = &self.uvw.w;
+        let denom = normal.dot(&ray.dir);
+        // no hit if the ray is parallel to the plane
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+        let t = (&self.center - &ray.orig).dot(normal) / denom;
+        if !ray_t.surrounds(t) {
+            return None;
+        }
+        let point = ray.at(t);
+        let offset = &point - &self.center;
+        let r = offset.length();
+        if r > self.radius {
+            return None;
+        }
+        let mut hit_record = HitRecord::new();
+        hit_record.t = t;
+        hit_record.point = point;
+        // polar coordinates, u is the angle around the normal and v the distance from
+        // the center, both scaled to [0, 1]
+        let phi = offset.dot(&self.uvw.v).atan2(offset.dot(&self.uvw.u));
+        hit_record.u = (phi + PI) / (2.0 * PI);
+        hit_record.v = r / self.radius;
+        hit_record.set_face_normal(ray, normal);
+        hit_record.material = self.material.clone();
+        Some(hit_record)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        // along each axis the disk reaches radius times the sine of the angle between
+        // the axis and the normal
+        let normal = &self.uvw.w;
+        let extent = |n: f64| self.radius * (1.0 - n * n).max(0.0).sqrt();
+        let rvec = Vec3::new(extent(normal.x()), extent(normal.y()), extent(normal.z()));
+        Aabb::from_points(&(&self.center - &rvec), &(&self.center + &rvec)).pad_to_minimums()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        let ray = Ray {
+            orig: origin.clone(),
+            dir: direction.clone(),
+            time,
+        };
+        let Some(rec) = self.hit(&ray, &mut Interval::new(0.001, INFINITY)) else {
+            return 0.0;
+        };
+        let area = PI * self.radius * self.radius;
+        let distance_squared = rec.t * rec.t * direction.length_squared();
+        let cosine = (direction.dot(&rec.normal) / direction.length()).abs();
+        distance_squared / (cosine * area)
+    }
+
+    // direction towards a uniformly chosen point of the disk
+    fn random(&self, origin: &Point3, _time: f64, sampler: &mut Sampler) -> Vec3 {
+        let r = self.radius * sampler.random_f64().sqrt();
+        let phi = 2.0 * PI * sampler.random_f64();
+        let point = &self.center
+            + &self
+                .uvw
+                .transform(&Vec3::new(r * phi.cos(), r * phi.sin(), 0.0));
+        &point - origin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lambertian;
+
+    #[test]
+    fn test_hit() {
+        let disk = Disk::new(
+            Point3::new(0.0, 1.0, 0.0),
+            &Vec3::new(0.0, 1.0, 0.0),
+            2.0,
+            Arc::new(Lambertian::default()),
+        );
+        let down = |x: f64| Ray {
+            orig: Point3::new(x, 3.0, 0.0),
+            dir: Vec3::new(0.0, -1.0, 0.0),
+            time: 0.0,
+        };
+        let rec = disk
+            .hit(&down(1.0), &mut Interval::new(0.001, INFINITY))
+            .unwrap();
+        assert_eq!(rec.t, 2.0);
+        assert!(rec.front_face);
+        assert_eq!(rec.normal, Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(rec.v, 0.5);
+        assert!(disk
+            .hit(&down(2.5), &mut Interval::new(0.001, INFINITY))
+            .is_none());
+
+        let bbox = disk.bounding_box();
+        assert_eq!(bbox.x, Interval::new(-2.0, 2.0));
+        assert!(bbox.y.size() > 0.0 && bbox.y.size() < 0.001);
+    }
+}
@@ -0,0 +1,527 @@
+use crate::color::{to_rgb16, to_rgb8, write_color};
+use crate::image::Image;
+use crate::traits::ImageEncoder;
+use crate::Color;
+use crate::Result;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+// ascii portable pixmap, one text line per pixel
+pub struct PpmAscii;
+
+// binary portable pixmap, three bytes per pixel
+pub struct PpmBinary;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PngDepth {
+    Eight,
+    Sixteen,
+}
+
+pub struct Png {
+    pub depth: PngDepth,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExrPrecision {
+    Half,
+    Float,
+}
+
+// uncompressed scanline OpenEXR holding the linear, unclamped colors. with alpha the
+// colors are premultiplied, as compositing expects: the background is left out and
+// pixels no object covers are black and transparent
+pub struct Exr {
+    pub precision: ExrPrecision,
+    pub alpha: bool,
+}
+
+// Radiance rgbe (.hdr), run length encoded where the image width allows it
+pub struct Rgbe;
+
+// portable float map, three little endian floats per pixel stored from the bottom row up
+pub struct Pfm;
+
+impl ImageEncoder for PpmAscii {
+    fn encode(&self, image: &Image, writer: &mut dyn Write) -> Result<()> {
+        write!(writer, "P3\n{} {}\n255\n", image.width(), image.height())?;
+        for pixel_color in image.pixels() {
+            write_color(writer, pixel_color, 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl ImageEncoder for PpmBinary {
+    fn encode(&self, image: &Image, writer: &mut dyn Write) -> Result<()> {
+        write!(writer, "P6\n{} {}\n255\n", image.width(), image.height())?;
+        let data: Vec<u8> = image.pixels().iter().flat_map(to_rgb8).collect();
+        writer.write_all(&data)?;
+        Ok(())
+    }
+}
+
+impl ImageEncoder for Png {
+    fn encode(&self, image: &Image, writer: &mut dyn Write) -> Result<()> {
+        let mut encoder = png::Encoder::new(writer, image.width(), image.height());
+        encoder.set_color(png::ColorType::Rgb);
+        // the pixels are stored with a gamma of 2, see color::linear_to_gamma
+        encoder.set_source_gamma(png::ScaledFloat::new(0.5));
+        let data: Vec<u8> = match self.depth {
+            PngDepth::Eight => {
+                encoder.set_depth(png::BitDepth::Eight);
+                image.pixels().iter().flat_map(to_rgb8).collect()
+            }
+            PngDepth::Sixteen => {
+                encoder.set_depth(png::BitDepth::Sixteen);
+                image
+                    .pixels()
+                    .iter()
+                    .flat_map(to_rgb16)
+                    .flat_map(u16::to_be_bytes)
+                    .collect()
+            }
+        };
+        let mut png_writer = encoder.write_header()?;
+        png_writer.write_image_data(&data)?;
+        png_writer.finish()?;
+        Ok(())
+    }
+}
+
+impl Exr {
+    const HALF: i32 = 1;
+    const FLOAT: i32 = 2;
+
+    fn write_attribute(header: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+        header.extend_from_slice(name.as_bytes());
+        header.push(0);
+        header.extend_from_slice(kind.as_bytes());
+        header.push(0);
+        header.extend_from_slice(&(value.len() as i32).to_le_bytes());
+        header.extend_from_slice(value);
+    }
+
+    fn write_sample(line: &mut Vec<u8>, precision: ExrPrecision, value: f64) {
+        match precision {
+            ExrPrecision::Half => line.extend_from_slice(&half::f16::from_f64(value).to_le_bytes()),
+            ExrPrecision::Float => line.extend_from_slice(&(value as f32).to_le_bytes()),
+        }
+    }
+}
+
+impl ImageEncoder for Exr {
+    fn encode(&self, image: &Image, writer: &mut dyn Write) -> Result<()> {
+        let (width, height) = (image.width() as usize, image.height() as usize);
+        // channels have to be listed in alphabetical order, in the header and in the pixel data
+        let channels: &[&str] = if self.alpha {
+            &["A", "B", "G", "R"]
+        } else {
+            &["B", "G", "R"]
+        };
+        let pixel_type = match self.precision {
+            ExrPrecision::Half => Self::HALF,
+            ExrPrecision::Float => Self::FLOAT,
+        };
+
+        let mut header = Vec::new();
+        header.extend_from_slice(&20000630_i32.to_le_bytes()); // magic number
+        header.extend_from_slice(&2_i32.to_le_bytes()); // version 2, single part scanline file
+        let mut channel_list = Vec::new();
+        for name in channels {
+            channel_list.extend_from_slice(name.as_bytes());
+            channel_list.push(0);
+            channel_list.extend_from_slice(&pixel_type.to_le_bytes());
+            channel_list.extend_from_slice(&[0, 0, 0, 0]); // p_linear and reserved bytes
+            channel_list.extend_from_slice(&1_i32.to_le_bytes()); // x sampling
+            channel_list.extend_from_slice(&1_i32.to_le_bytes()); // y sampling
+        }
+        channel_list.push(0);
+        Self::write_attribute(&mut header, "channels", "chlist", &channel_list);
+        Self::write_attribute(&mut header, "compression", "compression", &[0]);
+        let window: Vec<u8> = [0, 0, width as i32 - 1, height as i32 - 1]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        Self::write_attribute(&mut header, "dataWindow", "box2i", &window);
+        Self::write_attribute(&mut header, "displayWindow", "box2i", &window);
+        Self::write_attribute(&mut header, "lineOrder", "lineOrder", &[0]); // increasing y
+        Self::write_attribute(
+            &mut header,
+            "pixelAspectRatio",
+            "float",
+            &1.0_f32.to_le_bytes(),
+        );
+        let center: Vec<u8> = [0.0_f32, 0.0]
+            .iter()
+            .flat_map(|v| v.to_le_bytes())
+            .collect();
+        Self::write_attribute(&mut header, "screenWindowCenter", "v2f", &center);
+        Self::write_attribute(
+            &mut header,
+            "screenWindowWidth",
+            "float",
+            &1.0_f32.to_le_bytes(),
+        );
+        header.push(0);
+
+        // every scanline is its own chunk, the offset table holds the file position of each
+        let sample_size = match self.precision {
+            ExrPrecision::Half => 2,
+            ExrPrecision::Float => 4,
+        };
+        let line_size = width * channels.len() * sample_size;
+        let chunk_size = 8 + line_size;
+        let first_chunk = header.len() + 8 * height;
+        for y in 0..height {
+            header.extend_from_slice(&((first_chunk + y * chunk_size) as u64).to_le_bytes());
+        }
+        writer.write_all(&header)?;
+
+        let mut line = Vec::with_capacity(chunk_size);
+        for (y, row) in image.pixels().chunks(width).enumerate() {
+            let alpha = &image.alpha()[y * width..(y + 1) * width];
+            let background = &image.background()[y * width..(y + 1) * width];
+            line.clear();
+            line.extend_from_slice(&(y as i32).to_le_bytes());
+            line.extend_from_slice(&(line_size as i32).to_le_bytes());
+            for name in channels {
+                for ((pixel, a), background) in row.iter().zip(alpha).zip(background) {
+                    let pixel = if self.alpha {
+                        pixel - background
+                    } else {
+                        pixel.clone()
+                    };
+                    let value = match *name {
+                        "A" => *a,
+                        "B" => pixel.z(),
+                        "G" => pixel.y(),
+                        _ => pixel.x(),
+                    };
+                    Self::write_sample(&mut line, self.precision, value);
+                }
+            }
+            writer.write_all(&line)?;
+        }
+        Ok(())
+    }
+}
+
+impl Rgbe {
+    // shared exponent encoding of a linear color, negative components become zero
+    fn to_rgbe(c: &Color) -> [u8; 4] {
+        let (r, g, b) = (c.x().max(0.0), c.y().max(0.0), c.z().max(0.0));
+        let v = r.max(g).max(b);
+        if v < 1e-32 {
+            return [0, 0, 0, 0];
+        }
+        // v = mantissa * 2^exponent with the mantissa in [0.5, 1)
+        let mut exponent = v.log2().floor() as i32 + 1;
+        if v / 2f64.powi(exponent) >= 1.0 {
+            exponent += 1;
+        } else if v / 2f64.powi(exponent) < 0.5 {
+            exponent -= 1;
+        }
+        let exponent = exponent.clamp(-128, 127);
+        let scale = 256.0 / 2f64.powi(exponent);
+        [
+            (r * scale).min(255.0) as u8,
+            (g * scale).min(255.0) as u8,
+            (b * scale).min(255.0) as u8,
+            (exponent + 128) as u8,
+        ]
+    }
+
+    // run length encode one component of a scanline, runs of equal bytes are stored as
+    // (128 + count, value) and everything else as (count, values...)
+    fn write_component(out: &mut Vec<u8>, data: &[u8]) {
+        const MIN_RUN: usize = 4;
+        let mut cur = 0;
+        while cur < data.len() {
+            // find the next run long enough to be worth encoding
+            let mut beg_run = cur;
+            let mut run_count = 0;
+            while run_count < MIN_RUN && beg_run < data.len() {
+                beg_run += run_count;
+                run_count = 1;
+                while beg_run + run_count < data.len()
+                    && run_count < 127
+                    && data[beg_run] == data[beg_run + run_count]
+                {
+                    run_count += 1;
+                }
+            }
+            if run_count < MIN_RUN {
+                beg_run = data.len();
+            }
+            // dump the bytes before the run
+            while cur < beg_run {
+                let count = (beg_run - cur).min(128);
+                out.push(count as u8);
+                out.extend_from_slice(&data[cur..cur + count]);
+                cur += count;
+            }
+            if run_count >= MIN_RUN {
+                out.push(128 + run_count as u8);
+                out.push(data[beg_run]);
+                cur += run_count;
+            }
+        }
+    }
+}
+
+impl ImageEncoder for Rgbe {
+    fn encode(&self, image: &Image, writer: &mut dyn Write) -> Result<()> {
+        let width = image.width() as usize;
+        write!(
+            writer,
+            "#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n-Y {} +X {}\n",
+            image.height(),
+            image.width()
+        )?;
+        let mut line = Vec::new();
+        for row in image.pixels().chunks(width) {
+            line.clear();
+            let rgbe: Vec<[u8; 4]> = row.iter().map(Self::to_rgbe).collect();
+            if (8..0x8000).contains(&width) {
+                line.extend_from_slice(&[2, 2, (width >> 8) as u8, (width & 0xff) as u8]);
+                for component in 0..4 {
+                    let data: Vec<u8> = rgbe.iter().map(|p| p[component]).collect();
+                    Self::write_component(&mut line, &data);
+                }
+            } else {
+                // too narrow or too wide for run length encoding
+                line.extend(rgbe.iter().flatten());
+            }
+            writer.write_all(&line)?;
+        }
+        Ok(())
+    }
+}
+
+impl ImageEncoder for Pfm {
+    fn encode(&self, image: &Image, writer: &mut dyn Write) -> Result<()> {
+        // a negative scale marks little endian data
+        write!(writer, "PF\n{} {}\n-1.0\n", image.width(), image.height())?;
+        for row in image.pixels().chunks(image.width() as usize).rev() {
+            let data: Vec<u8> = row
+                .iter()
+                .flat_map(|c| [c.x() as f32, c.y() as f32, c.z() as f32])
+                .flat_map(f32::to_le_bytes)
+                .collect();
+            writer.write_all(&data)?;
+        }
+        Ok(())
+    }
+}
+
+// the supported output file formats
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    P3,
+    P6,
+    Png8,
+    Png16,
+    Exr {
+        precision: ExrPrecision,
+        alpha: bool,
+    },
+    Rgbe,
+    Pfm,
+}
+
+impl OutputFormat {
+    // pick the format from the extension of the output file: .ppm keeps writing
+    // ascii P3, .pnm is written as binary P6, .png as an 8 bit png and .exr as half
+    // float rgba. 16 bit png and the other variants are only chosen with --format
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .map(|ext| ext.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("ppm") => Ok(OutputFormat::P3),
+            Some("pnm") => Ok(OutputFormat::P6),
+            Some("png") => Ok(OutputFormat::Png8),
+            Some("exr") => Ok(OutputFormat::Exr {
+                precision: ExrPrecision::Half,
+                alpha: true,
+            }),
+            Some("hdr") => Ok(OutputFormat::Rgbe),
+            Some("pfm") => Ok(OutputFormat::Pfm),
+            _ => Err(format!(
+                "cannot tell the output format of '{}', expected a .ppm, .pnm, .png, .exr, .hdr or .pfm file",
+                path.display()
+            )
+            .into()),
+        }
+    }
+
+    pub fn encoder(&self) -> Box<dyn ImageEncoder> {
+        match self {
+            OutputFormat::P3 => Box::new(PpmAscii),
+            OutputFormat::P6 => Box::new(PpmBinary),
+            OutputFormat::Png8 => Box::new(Png {
+                depth: PngDepth::Eight,
+            }),
+            OutputFormat::Png16 => Box::new(Png {
+                depth: PngDepth::Sixteen,
+            }),
+            OutputFormat::Exr { precision, alpha } => Box::new(Exr {
+                precision: *precision,
+                alpha: *alpha,
+            }),
+            OutputFormat::Rgbe => Box::new(Rgbe),
+            OutputFormat::Pfm => Box::new(Pfm),
+        }
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "p3" => Ok(OutputFormat::P3),
+            "p6" => Ok(OutputFormat::P6),
+            "png" | "png8" => Ok(OutputFormat::Png8),
+            "png16" => Ok(OutputFormat::Png16),
+            "exr" | "exr-half" => Ok(OutputFormat::Exr {
+                precision: ExrPrecision::Half,
+                alpha: true,
+            }),
+            "exr-half-rgb" => Ok(OutputFormat::Exr {
+                precision: ExrPrecision::Half,
+                alpha: false,
+            }),
+            "exr-float" => Ok(OutputFormat::Exr {
+                precision: ExrPrecision::Float,
+                alpha: true,
+            }),
+            "exr-float-rgb" => Ok(OutputFormat::Exr {
+                precision: ExrPrecision::Float,
+                alpha: false,
+            }),
+            "hdr" | "rgbe" => Ok(OutputFormat::Rgbe),
+            "pfm" => Ok(OutputFormat::Pfm),
+            _ => Err(format!(
+                "unknown output format '{}', expected p3, p6, png8, png16, exr-half, \
+                 exr-half-rgb, exr-float, exr-float-rgb, hdr or pfm",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test_image() -> Image {
+        let mut image = Image::new(2, 1);
+        image.set_pixel(0, 0, Color::new(1.0, 0.25, 0.0));
+        image.set_pixel(1, 0, Color::new(0.0, 0.0, 4.0));
+        image
+    }
+
+    #[test]
+    fn test_p3() {
+        let mut output = Vec::new();
+        PpmAscii.encode(&test_image(), &mut output).unwrap();
+        assert_eq!(
+            String::from_utf8(output).unwrap(),
+            "P3\n2 1\n255\n255 128 0\n0 0 255\n"
+        );
+    }
+
+    #[test]
+    fn test_p6() {
+        let mut output = Vec::new();
+        PpmBinary.encode(&test_image(), &mut output).unwrap();
+        let mut expected = b"P6\n2 1\n255\n".to_vec();
+        expected.extend_from_slice(&[255, 128, 0, 0, 0, 255]);
+        assert_eq!(output, expected);
+    }
+
+    #[test]
+    fn test_png_round_trip() {
+        for (depth, bytes_per_pixel) in [(PngDepth::Eight, 3), (PngDepth::Sixteen, 6)] {
+            let mut output = Vec::new();
+            Png { depth }.encode(&test_image(), &mut output).unwrap();
+            let decoder = png::Decoder::new(output.as_slice());
+            let mut reader = decoder.read_info().unwrap();
+            let mut data = vec![0; reader.output_buffer_size()];
+            let info = reader.next_frame(&mut data).unwrap();
+            assert_eq!((info.width, info.height), (2, 1));
+            assert_eq!(info.buffer_size(), 2 * bytes_per_pixel);
+            assert_eq!(data[0], 255);
+        }
+    }
+
+    #[test]
+    fn test_exr_layout() {
+        let mut output = Vec::new();
+        Exr {
+            precision: ExrPrecision::Float,
+            alpha: false,
+        }
+        .encode(&test_image(), &mut output)
+        .unwrap();
+        assert_eq!(&output[..8], &[0x76, 0x2f, 0x31, 0x01, 2, 0, 0, 0]);
+        // a single scanline chunk: y, data size, then 2 pixels of B, G and R floats
+        let chunk_size = 8 + 2 * 3 * 4;
+        let offset = u64::from_le_bytes(
+            output[output.len() - chunk_size - 8..][..8]
+                .try_into()
+                .unwrap(),
+        );
+        assert_eq!(offset as usize, output.len() - chunk_size);
+        let red = &output[output.len() - 8..];
+        assert_eq!(f32::from_le_bytes(red[..4].try_into().unwrap()), 1.0);
+    }
+
+    #[test]
+    fn test_rgbe() {
+        assert_eq!(Rgbe::to_rgbe(&Color::new(1.0, 0.5, 0.0)), [128, 64, 0, 129]);
+        assert_eq!(Rgbe::to_rgbe(&Color::new(0.0, -1.0, 0.0)), [0, 0, 0, 0]);
+        let mut line = Vec::new();
+        Rgbe::write_component(&mut line, &[7, 7, 7, 7, 7, 1, 2]);
+        assert_eq!(line, [133, 7, 2, 1, 2]);
+    }
+
+    #[test]
+    fn test_pfm_bottom_row_first() {
+        let mut image = Image::new(1, 2);
+        image.set_pixel(0, 0, Color::new(1.0, 1.0, 1.0));
+        image.set_pixel(0, 1, Color::new(2.0, 2.0, 2.0));
+        let mut output = Vec::new();
+        Pfm.encode(&image, &mut output).unwrap();
+        let header = b"PF\n1 2\n-1.0\n";
+        assert_eq!(&output[..header.len()], header);
+        let first = f32::from_le_bytes(output[header.len()..][..4].try_into().unwrap());
+        assert_eq!(first, 2.0);
+    }
+
+    #[test]
+    fn test_from_path() {
+        assert_eq!(
+            OutputFormat::from_path(Path::new("images/a.ppm")).unwrap(),
+            OutputFormat::P3
+        );
+        assert_eq!(
+            OutputFormat::from_path(Path::new("a.PNM")).unwrap(),
+            OutputFormat::P6
+        );
+        assert_eq!(
+            OutputFormat::from_path(Path::new("a.png")).unwrap(),
+            OutputFormat::Png8
+        );
+        assert_eq!(
+            OutputFormat::from_path(Path::new("a.hdr")).unwrap(),
+            OutputFormat::Rgbe
+        );
+        assert!(OutputFormat::from_path(Path::new("a.bmp")).is_err());
+        assert_eq!("png16".parse(), Ok(OutputFormat::Png16));
+    }
+}
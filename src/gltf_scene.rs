@@ -0,0 +1,620 @@
+use crate::camera::Camera;
+use crate::material::{DiffuseLight, Principled, PrincipledParams};
+use crate::mesh::{Mesh, MeshVertices};
+use crate::scene::Scene;
+use crate::traits::Scatter;
+use crate::utils::radians_to_degrees;
+use crate::vec3::Mat4;
+use crate::Result;
+use crate::{Color, Hittable, Hittables, Lambertian, Point3, Vec3};
+use base64::Engine;
+use gltf::json::validation::{Error as ValidationError, Validate};
+use gltf::{Document, Gltf};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+// gltf 2.0 reader for .gltf and .glb files. the meshes of the nodes of the default
+// scene are baked into world space, their materials are mapped onto the closest
+// materials of the renderer, and the first perspective camera sets up the camera.
+// whatever cannot be rendered gives a warning rather than an error
+
+// extensions the material mapping reads, others are ignored with a warning
+const SUPPORTED_EXTENSIONS: &[&str] = &[
+    "KHR_materials_emissive_strength",
+    "KHR_materials_ior",
+    "KHR_materials_transmission",
+];
+
+fn vec3(v: [f32; 3]) -> Vec3 {
+    Vec3::new(v[0] as f64, v[1] as f64, v[2] as f64)
+}
+
+// the world and camera of the gltf or glb file at path, with warnings about the
+// parts of the file that are not rendered
+pub fn load_gltf(path: &Path) -> Result<(Scene, Vec<String>)> {
+    let data = fs::read(path)
+        .map_err(|err| format!("cannot read gltf file {}: {}", path.display(), err))?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    parse_gltf(&data, &path.display().to_string(), dir)
+}
+
+fn parse_gltf(data: &[u8], name: &str, dir: &Path) -> Result<(Scene, Vec<String>)> {
+    let Gltf { document, blob } =
+        Gltf::from_slice_without_validation(data).map_err(|err| format!("{}: {}", name, err))?;
+    let mut warnings = Vec::new();
+    validate(&document, name, &mut warnings)?;
+    for extension in document.extensions_used() {
+        if !SUPPORTED_EXTENSIONS.contains(&extension) {
+            warnings.push(format!(
+                "{}: extension {} is not supported",
+                name, extension
+            ));
+        }
+    }
+    let buffers = load_buffers(&document, blob, name, dir)?;
+
+    let mut loader = Loader {
+        name,
+        buffers,
+        materials: vec![None; document.materials().len()],
+        world: Hittables::default(),
+        lights: Hittables::default(),
+        camera: None,
+        warnings,
+    };
+    let Some(scene) = document
+        .default_scene()
+        .or_else(|| document.scenes().next())
+    else {
+        return Err(format!("{}: no scene", name).into());
+    };
+    for node in scene.nodes() {
+        loader.visit(&node, &Mat4::identity(), &mut Vec::new())?;
+    }
+    let camera = match loader.camera.take() {
+        Some(camera) => camera,
+        None => {
+            loader
+                .warnings
+                .push(format!("{}: no camera, looking at the whole scene", name));
+            framing_camera(&loader.world)
+        }
+    };
+    Ok((
+        Scene {
+            world: loader.world,
+            lights: loader.lights,
+            camera,
+        },
+        loader.warnings,
+    ))
+}
+
+// the validation of the gltf crate, except that extensions it does not know only
+// give warnings
+fn validate(document: &Document, name: &str, warnings: &mut Vec<String>) -> Result<()> {
+    let root = document.as_json();
+    let mut errors = Vec::new();
+    root.validate(root, gltf::json::Path::new, &mut |path, error| {
+        errors.push((path(), error))
+    });
+    for (path, error) in errors {
+        if error == ValidationError::Unsupported {
+            warnings.push(format!("{}: {} is not supported", name, path));
+        } else {
+            return Err(format!("{}: {}: {}", name, path, error).into());
+        }
+    }
+    Ok(())
+}
+
+fn load_buffers(
+    document: &Document,
+    mut blob: Option<Vec<u8>>,
+    name: &str,
+    dir: &Path,
+) -> Result<Vec<Vec<u8>>> {
+    let mut buffers = Vec::new();
+    for buffer in document.buffers() {
+        let data = match buffer.source() {
+            gltf::buffer::Source::Bin => blob
+                .take()
+                .ok_or_else(|| format!("{}: glb file without a binary chunk", name))?,
+            gltf::buffer::Source::Uri(uri) => match uri.strip_prefix("data:") {
+                Some(data_uri) => {
+                    let Some((_, encoded)) = data_uri.split_once(";base64,") else {
+                        return Err(format!(
+                            "{}: data uri of buffer {} is not base64",
+                            name,
+                            buffer.index()
+                        )
+                        .into());
+                    };
+                    base64::engine::general_purpose::STANDARD
+                        .decode(encoded)
+                        .map_err(|err| format!("{}: buffer {}: {}", name, buffer.index(), err))?
+                }
+                None => {
+                    let buffer_path = dir.join(uri);
+                    fs::read(&buffer_path).map_err(|err| {
+                        format!(
+                            "{}: cannot read buffer {}: {}",
+                            name,
+                            buffer_path.display(),
+                            err
+                        )
+                    })?
+                }
+            },
+        };
+        if data.len() < buffer.length() {
+            return Err(format!(
+                "{}: buffer {} has {} bytes instead of {}",
+                name,
+                buffer.index(),
+                data.len(),
+                buffer.length()
+            )
+            .into());
+        }
+        buffers.push(data);
+    }
+    Ok(buffers)
+}
+
+// camera in front of the scene, far enough to see all of it
+fn framing_camera(world: &Hittables) -> Camera {
+    let mut camera = Camera::new(16.0 / 9.0, 400, 100, 50, 40.0);
+    camera.defocus_angle = 0.0;
+    if world.objects.is_empty() {
+        return camera;
+    }
+    let bbox = world.bounding_box();
+    let center = bbox.centroid();
+    let radius = Vec3::new(bbox.x.size(), bbox.y.size(), bbox.z.size()).length() / 2.0;
+    let distance = radius / (camera.vfov.to_radians() / 2.0).sin();
+    camera.look_from = &center + &Vec3::new(0.0, 0.0, distance);
+    camera.look_at = center;
+    camera.focus_dist = distance;
+    camera
+}
+
+struct Loader<'a> {
+    name: &'a str,
+    buffers: Vec<Vec<u8>>,
+    // materials by gltf index, built when first used
+    materials: Vec<Option<Arc<dyn Scatter>>>,
+    world: Hittables,
+    lights: Hittables,
+    camera: Option<Camera>,
+    warnings: Vec<String>,
+}
+
+impl Loader<'_> {
+    // add the node and its descendants. path holds the indices of the nodes above it,
+    // a node among them would be visited again and again
+    fn visit(&mut self, node: &gltf::Node, parent: &Mat4, path: &mut Vec<usize>) -> Result<()> {
+        if path.contains(&node.index()) {
+            return Err(
+                format!("{}: node {} is its own descendant", self.name, node.index()).into(),
+            );
+        }
+        let local = Mat4::from_columns(
+            node.transform()
+                .matrix()
+                .map(|column| column.map(|x| x as f64)),
+        );
+        let transform = parent * &local;
+        if let Some(mesh) = node.mesh() {
+            for primitive in mesh.primitives() {
+                self.add_primitive(&mesh, &primitive, &transform)?;
+            }
+        }
+        if let Some(camera) = node.camera() {
+            self.set_camera(&camera, &transform);
+        }
+        path.push(node.index());
+        for child in node.children() {
+            self.visit(&child, &transform, path)?;
+        }
+        path.pop();
+        Ok(())
+    }
+
+    fn set_camera(&mut self, camera: &gltf::Camera, transform: &Mat4) {
+        if self.camera.is_some() {
+            return;
+        }
+        let gltf::camera::Projection::Perspective(perspective) = camera.projection() else {
+            self.warnings.push(format!(
+                "{}: orthographic camera {} is not supported",
+                self.name,
+                camera.index()
+            ));
+            return;
+        };
+        // gltf cameras look down their -z axis with +y up
+        let mut result = Camera::new(16.0 / 9.0, 400, 100, 50, 90.0);
+        let y_axis = transform.transform_vector(&Vec3::new(0.0, 1.0, 0.0));
+        let z_axis = transform.transform_vector(&Vec3::new(0.0, 0.0, 1.0));
+        result.look_from = transform.transform_point(&Point3::default());
+        result.look_at = &result.look_from - &z_axis;
+        result.vup = y_axis;
+        result.vfov = radians_to_degrees(perspective.yfov() as f64);
+        if let Some(aspect_ratio) = perspective.aspect_ratio() {
+            result.aspect_ratio = aspect_ratio as f64;
+        }
+        result.defocus_angle = 0.0;
+        result.focus_dist = z_axis.length();
+        self.camera = Some(result);
+    }
+
+    fn material(&mut self, material: &gltf::Material) -> Arc<dyn Scatter> {
+        let Some(index) = material.index() else {
+            // primitives without a material get a plain gray
+            return Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8)));
+        };
+        if let Some(built) = &self.materials[index] {
+            return built.clone();
+        }
+        let built = self.build_material(material);
+        self.materials[index] = Some(built.clone());
+        built
+    }
+
+    // emissive materials are lights, everything else a principled material with the
+    // metallic-roughness parameters, transmission and ior. textures are ignored
+    fn build_material(&mut self, material: &gltf::Material) -> Arc<dyn Scatter> {
+        let material_name = material.name().unwrap_or("unnamed");
+        let pbr = material.pbr_metallic_roughness();
+        if pbr.base_color_texture().is_some()
+            || pbr.metallic_roughness_texture().is_some()
+            || material.normal_texture().is_some()
+            || material.emissive_texture().is_some()
+        {
+            self.warnings.push(format!(
+                "{}: textures of material '{}' are not supported",
+                self.name, material_name
+            ));
+        }
+        let [r, g, b, _] = pbr.base_color_factor().map(|x| x as f64);
+        let base_color = Color::new(r, g, b);
+        let strength = material.emissive_strength().unwrap_or(1.0) as f64;
+        let [r, g, b] = material.emissive_factor().map(|x| x as f64 * strength);
+        let emission = Color::new(r, g, b);
+        let transmission = material
+            .transmission()
+            .map_or(0.0, |transmission| transmission.transmission_factor());
+        if !emission.near_zero() {
+            return Arc::new(DiffuseLight::new(emission));
+        }
+        let params = PrincipledParams {
+            metallic: pbr.metallic_factor().clamp(0.0, 1.0) as f64,
+            roughness: pbr.roughness_factor().clamp(0.0, 1.0) as f64,
+            transmission: transmission.clamp(0.0, 1.0) as f64,
+            ior: material.ior().unwrap_or(1.5) as f64,
+            ..Default::default()
+        };
+        Arc::new(Principled::new(base_color, params))
+    }
+
+    fn add_primitive(
+        &mut self,
+        mesh: &gltf::Mesh,
+        primitive: &gltf::Primitive,
+        transform: &Mat4,
+    ) -> Result<()> {
+        let mesh_name = mesh.name().unwrap_or("unnamed");
+        if primitive.mode() != gltf::mesh::Mode::Triangles {
+            self.warnings.push(format!(
+                "{}: mesh '{}' has {:?} primitives, only triangles are supported",
+                self.name,
+                mesh_name,
+                primitive.mode()
+            ));
+            return Ok(());
+        }
+        let buffers = &self.buffers;
+        let reader = primitive.reader(|buffer| buffers.get(buffer.index()).map(|data| &data[..]));
+        let Some(positions) = reader.read_positions() else {
+            self.warnings.push(format!(
+                "{}: mesh '{}' has a primitive without positions",
+                self.name, mesh_name
+            ));
+            return Ok(());
+        };
+
+        let mut vertices = MeshVertices {
+            positions: positions
+                .map(|position| transform.transform_point(&vec3(position)))
+                .collect(),
+            ..MeshVertices::default()
+        };
+        // normals go through the inverse transpose, which keeps them perpendicular to
+        // the surface. a node scaled to nothing has no surface to shade
+        let normal_matrix = transform.inverse().map(|inverse| inverse.transpose());
+        if let (Some(normals), Some(normal_matrix)) = (reader.read_normals(), normal_matrix) {
+            vertices.normals = normals
+                .map(|normal| {
+                    let normal = normal_matrix.transform_vector(&vec3(normal));
+                    if normal.near_zero() {
+                        normal
+                    } else {
+                        normal.make_unit_vector()
+                    }
+                })
+                .collect();
+            // zero normals give no direction to shade with, the primitive is shaded flat
+            if vertices.normals.iter().any(|normal| normal.near_zero()) {
+                self.warnings.push(format!(
+                    "{}: mesh '{}' has zero normals, shading it flat",
+                    self.name, mesh_name
+                ));
+                vertices.normals.clear();
+            }
+        }
+        if let Some(uvs) = reader.read_tex_coords(0) {
+            // gltf texture coordinates start at the top of the image, ours at the bottom
+            vertices.uvs = uvs
+                .into_f32()
+                .map(|[u, v]| (u as f64, 1.0 - v as f64))
+                .collect();
+        }
+        let base_color = primitive
+            .material()
+            .pbr_metallic_roughness()
+            .base_color_factor();
+        if let Some(colors) = reader.read_colors(0) {
+            // vertex colors are multiplied by the base color, and are linear already
+            vertices.colors = colors
+                .into_rgb_f32()
+                .map(|rgb| {
+                    let [r, g, b] = [0, 1, 2].map(|i| (rgb[i] * base_color[i]) as f64);
+                    Color::new(r, g, b)
+                })
+                .collect();
+        }
+        let count = vertices.positions.len();
+        let indices: Vec<u32> = match reader.read_indices() {
+            Some(indices) => indices.into_u32().collect(),
+            None => (0..count as u32).collect(),
+        };
+        if let Some(bad) = indices.iter().find(|&&index| index as usize >= count) {
+            return Err(format!(
+                "{}: mesh '{}' has vertex index {} out of range",
+                self.name, mesh_name, bad
+            )
+            .into());
+        }
+        // a mirroring transform turns the corners of each face the other way round
+        let mirrored = transform.determinant3() < 0.0;
+        let faces: Vec<[u32; 3]> = indices
+            .chunks_exact(3)
+            .map(|corners| {
+                if mirrored {
+                    [corners[0], corners[2], corners[1]]
+                } else {
+                    [corners[0], corners[1], corners[2]]
+                }
+            })
+            .collect();
+        if faces.is_empty() {
+            return Ok(());
+        }
+
+        let material = self.material(&primitive.material());
+        let mesh: Arc<dyn Hittable> =
+            Arc::new(Mesh::indexed(Arc::new(vertices), faces, material.clone()));
+        if material.is_emissive() {
+            self.lights.add(mesh.clone());
+        }
+        self.world.add(mesh);
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interval::Interval;
+    use crate::utils::INFINITY;
+    use crate::Ray;
+
+    // one triangle in the xy plane facing +z, as three f32 positions and u16 indices
+    fn triangle_buffer() -> Vec<u8> {
+        let mut data = Vec::new();
+        for x in [0.0f32, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0] {
+            data.extend_from_slice(&x.to_le_bytes());
+        }
+        for index in [0u16, 1, 2, 0] {
+            data.extend_from_slice(&index.to_le_bytes());
+        }
+        data
+    }
+
+    // gltf json with the triangle buffer, nodes and materials filled in
+    fn gltf_json(buffer_uri: Option<&str>, extra: &str) -> String {
+        let uri = buffer_uri.map_or(String::new(), |uri| format!(r#""uri": "{}","#, uri));
+        format!(
+            r#"{{
+                "asset": {{"version": "2.0"}},
+                "buffers": [{{{} "byteLength": 44}}],
+                "bufferViews": [
+                    {{"buffer": 0, "byteOffset": 0, "byteLength": 36}},
+                    {{"buffer": 0, "byteOffset": 36, "byteLength": 6}}
+                ],
+                "accessors": [
+                    {{"bufferView": 0, "componentType": 5126, "count": 3, "type": "VEC3",
+                      "min": [0, 0, 0], "max": [1, 1, 0]}},
+                    {{"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}}
+                ],
+                "meshes": [{{"primitives": [{{"attributes": {{"POSITION": 0}}, "indices": 1,
+                                             "material": 0}}]}}],
+                {}
+            }}"#,
+            uri, extra
+        )
+    }
+
+    fn glb(json: &str, bin: &[u8]) -> Vec<u8> {
+        let mut json = json.as_bytes().to_vec();
+        json.resize(json.len().next_multiple_of(4), b' ');
+        let mut bin = bin.to_vec();
+        bin.resize(bin.len().next_multiple_of(4), 0);
+        let length = 12 + 8 + json.len() + 8 + bin.len();
+        let mut data = Vec::new();
+        data.extend_from_slice(b"glTF");
+        data.extend_from_slice(&2u32.to_le_bytes());
+        data.extend_from_slice(&(length as u32).to_le_bytes());
+        data.extend_from_slice(&(json.len() as u32).to_le_bytes());
+        data.extend_from_slice(b"JSON");
+        data.extend_from_slice(&json);
+        data.extend_from_slice(&(bin.len() as u32).to_le_bytes());
+        data.extend_from_slice(b"BIN\0");
+        data.extend_from_slice(&bin);
+        data
+    }
+
+    fn hit_from_above(scene: &Scene, x: f64, y: f64) -> Option<crate::HitRecord> {
+        let ray = Ray {
+            orig: Point3::new(x, y, 10.0),
+            dir: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        scene.world.hit(&ray, &mut Interval::new(0.001, INFINITY))
+    }
+
+    #[test]
+    fn test_nodes_camera_and_materials() {
+        let uri = format!(
+            "data:application/octet-stream;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(triangle_buffer())
+        );
+        let json = gltf_json(
+            Some(&uri),
+            r#""materials": [{"pbrMetallicRoughness": {"baseColorFactor": [1, 0, 0, 1],
+                                                       "metallicFactor": 1.0}}],
+               "cameras": [{"type": "perspective",
+                            "perspective": {"yfov": 0.5, "aspectRatio": 2.0, "znear": 0.1}}],
+               "nodes": [
+                   {"translation": [10, 0, 0], "children": [1, 2]},
+                   {"mesh": 0, "scale": [-2, 2, 2]},
+                   {"camera": 0, "translation": [0, 0, 5]}
+               ],
+               "scenes": [{"nodes": [0]}],
+               "scene": 0"#,
+        );
+        let (scene, warnings) = parse_gltf(json.as_bytes(), "test.gltf", Path::new("")).unwrap();
+        assert_eq!(warnings, Vec::<String>::new());
+        assert_eq!(scene.world.objects.len(), 1);
+        assert!(scene.lights.objects.is_empty());
+
+        // the mirrored triangle spans x 8..10 and still faces +z
+        let bbox = scene.world.bounding_box();
+        assert!((bbox.x.min - 8.0).abs() < 1e-3 && (bbox.x.max - 10.0).abs() < 1e-3);
+        let rec = hit_from_above(&scene, 9.5, 0.5).unwrap();
+        assert!(rec.front_face);
+        assert!(hit_from_above(&scene, 10.5, 0.5).is_none());
+
+        let camera = &scene.camera;
+        assert_eq!(camera.look_from, Point3::new(10.0, 0.0, 5.0));
+        assert_eq!(camera.look_at, Point3::new(10.0, 0.0, 4.0));
+        assert_eq!(camera.aspect_ratio, 2.0);
+        assert!((camera.vfov - radians_to_degrees(0.5)).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_zero_normals_shade_flat() {
+        // the triangle followed by three zero normals
+        let mut buffer = triangle_buffer();
+        buffer.extend([0u8; 36]);
+        let json = gltf_json(
+            None,
+            r#""materials": [{}], "nodes": [{"mesh": 0}], "scenes": [{"nodes": [0]}]"#,
+        )
+        .replace(r#""byteLength": 44"#, r#""byteLength": 80"#)
+        .replace(
+            r#"{"buffer": 0, "byteOffset": 36, "byteLength": 6}"#,
+            r#"{"buffer": 0, "byteOffset": 36, "byteLength": 6},
+               {"buffer": 0, "byteOffset": 44, "byteLength": 36}"#,
+        )
+        .replace(
+            r#"{"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"}"#,
+            r#"{"bufferView": 1, "componentType": 5123, "count": 3, "type": "SCALAR"},
+               {"bufferView": 2, "componentType": 5126, "count": 3, "type": "VEC3"}"#,
+        )
+        .replace(r#""POSITION": 0"#, r#""POSITION": 0, "NORMAL": 2"#);
+        let (scene, warnings) =
+            parse_gltf(&glb(&json, &buffer), "test.glb", Path::new("")).unwrap();
+        assert_eq!(
+            warnings[0],
+            "test.glb: mesh 'unnamed' has zero normals, shading it flat"
+        );
+        let rec = hit_from_above(&scene, 0.25, 0.25).unwrap();
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn test_glb_with_unsupported_extension() {
+        let json = gltf_json(
+            None,
+            r#""extensionsUsed": ["KHR_draco_mesh_compression"],
+               "extensionsRequired": ["KHR_draco_mesh_compression"],
+               "materials": [{"emissiveFactor": [1, 1, 1],
+                              "extensions": {"KHR_materials_emissive_strength":
+                                                 {"emissiveStrength": 4.0}}}],
+               "nodes": [{"mesh": 0}],
+               "scenes": [{"nodes": [0]}]"#,
+        );
+        let (scene, warnings) =
+            parse_gltf(&glb(&json, &triangle_buffer()), "test.glb", Path::new("")).unwrap();
+        assert!(warnings
+            .iter()
+            .any(|warning| warning.contains("KHR_draco_mesh_compression")));
+        assert!(warnings.iter().any(|warning| warning.contains("no camera")));
+        assert_eq!(scene.world.objects.len(), 1);
+        assert_eq!(scene.lights.objects.len(), 1);
+        let rec = hit_from_above(&scene, 0.2, 0.2).unwrap();
+        let ray = Ray {
+            orig: Point3::new(0.2, 0.2, 10.0),
+            dir: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        assert_eq!(rec.material.emitted(&ray, &rec), Color::new(4.0, 4.0, 4.0));
+    }
+
+    #[test]
+    fn test_bad_index_is_an_error() {
+        let mut buffer = triangle_buffer();
+        buffer[40] = 7;
+        let uri = format!(
+            "data:application/octet-stream;base64,{}",
+            base64::engine::general_purpose::STANDARD.encode(buffer)
+        );
+        let json = gltf_json(
+            Some(&uri),
+            r#""materials": [{}], "nodes": [{"mesh": 0}], "scenes": [{"nodes": [0]}]"#,
+        );
+        let err = parse_gltf(json.as_bytes(), "test.gltf", Path::new(""))
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(err.contains("out of range"), "{}", err);
+    }
+
+    #[test]
+    fn test_node_cycle_is_an_error() {
+        let json = gltf_json(
+            None,
+            r#""materials": [{}],
+               "nodes": [{"children": [1]}, {"children": [2]}, {"children": [1]}],
+               "scenes": [{"nodes": [0]}]"#,
+        );
+        let err = parse_gltf(&glb(&json, &triangle_buffer()), "test.glb", Path::new(""))
+            .err()
+            .unwrap()
+            .to_string();
+        assert_eq!(err, "test.glb: node 1 is its own descendant");
+    }
+}
@@ -1,17 +1,25 @@
+use crate::aabb::Aabb;
 use crate::interval::Interval;
+use crate::sampler::Sampler;
 use crate::traits::{Hittable, Scatter};
+use crate::Color;
 use crate::Lambertian;
 use crate::Point3;
 use crate::Ray;
 use crate::Vec3;
-use std::rc::Rc;
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct HitRecord {
     pub point: Point3,
     pub normal: Vec3,
-    pub material: Rc<dyn Scatter>,
+    pub material: Arc<dyn Scatter>,
     pub t: f64,
+    // surface coordinates of the hit point, where textures are looked up
+    pub u: f64,
+    pub v: f64,
+    // color interpolated from the vertices of a mesh with vertex colors
+    pub vertex_color: Option<Color>,
     pub front_face: bool,
 }
 
@@ -20,15 +28,18 @@ impl HitRecord {
         Self {
             point: Point3::default(),
             normal: Vec3::default(),
-            material: Rc::new(Lambertian::default()),
+            material: Arc::new(Lambertian::default()),
             t: 0.0,
+            u: 0.0,
+            v: 0.0,
+            vertex_color: None,
             front_face: false,
         }
     }
     // set the hit record normal vector,
     // assuming outward_normal has unit length, i.e., it is normalized
     pub fn set_face_normal(&mut self, ray: &Ray, outward_normal: &Vec3) {
-        self.front_face = ray.dir.dot(&outward_normal) < 0.0;
+        self.front_face = ray.dir.dot(outward_normal) < 0.0;
         self.normal = match self.front_face {
             true => outward_normal.clone(),
             _ => outward_normal * -1.0,
@@ -37,11 +48,11 @@ impl HitRecord {
 }
 #[derive(Default)]
 pub struct Hittables {
-    pub objects: Vec<Box<dyn Hittable>>,
+    pub objects: Vec<Arc<dyn Hittable>>,
 }
 
 impl Hittables {
-    pub fn add(&mut self, obj: Box<dyn Hittable>) {
+    pub fn add(&mut self, obj: Arc<dyn Hittable>) {
         self.objects.push(obj);
     }
 }
@@ -51,10 +62,52 @@ impl Hittable for Hittables {
         let mut hit_record = None;
         for object in self.objects.iter() {
             if let Some(tmp_hit_record) = object.hit(ray, ray_t) {
-                ray_t.max = tmp_hit_record.t.clone();
+                ray_t.max = tmp_hit_record.t;
                 hit_record = Some(tmp_hit_record);
             }
         }
         hit_record
     }
+
+    fn hit_surface(&self, ray: &Ray, ray_t: &mut Interval) -> Option<HitRecord> {
+        let mut hit_record = None;
+        for object in self.objects.iter() {
+            if let Some(tmp_hit_record) = object.hit_surface(ray, ray_t) {
+                ray_t.max = tmp_hit_record.t;
+                hit_record = Some(tmp_hit_record);
+            }
+        }
+        hit_record
+    }
+
+    fn transmittance(&self, ray: &Ray, ray_t: &Interval, sampler: &mut Sampler) -> f64 {
+        self.objects
+            .iter()
+            .map(|object| object.transmittance(ray, ray_t, sampler))
+            .product()
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.objects.iter().fold(Aabb::default(), |bbox, object| {
+            Aabb::enclosing(&bbox, &object.bounding_box())
+        })
+    }
+
+    // the objects are picked with equal probability when used as lights
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+        let weight = 1.0 / self.objects.len() as f64;
+        self.objects
+            .iter()
+            .map(|object| weight * object.pdf_value(origin, direction, time))
+            .sum()
+    }
+
+    fn random(&self, origin: &Point3, time: f64, sampler: &mut Sampler) -> Vec3 {
+        let n = self.objects.len();
+        let index = ((sampler.random_f64() * n as f64) as usize).min(n - 1);
+        self.objects[index].random(origin, time, sampler)
+    }
 }
@@ -0,0 +1,67 @@
+use crate::Color;
+
+// rendered image, one linear (not gamma corrected) color per pixel stored row by row
+// from the top left corner. alpha holds the fraction of the camera rays through each
+// pixel that hit an object, and background the part of the color the camera rays that
+// missed brought in, which leaves the objects alone over a transparent background
+#[derive(Debug, Clone, Default)]
+pub struct Image {
+    width: u32,
+    height: u32,
+    pixels: Vec<Color>,
+    alpha: Vec<f64>,
+    background: Vec<Color>,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32) -> Self {
+        Image {
+            width,
+            height,
+            pixels: vec![Color::default(); width as usize * height as usize],
+            alpha: vec![1.0; width as usize * height as usize],
+            background: vec![Color::default(); width as usize * height as usize],
+        }
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn set_pixel(&mut self, i: u32, j: u32, color: Color) {
+        let index = self.index(i, j);
+        self.pixels[index] = color;
+    }
+
+    pub fn set_alpha(&mut self, i: u32, j: u32, alpha: f64) {
+        let index = self.index(i, j);
+        self.alpha[index] = alpha;
+    }
+
+    pub fn set_background(&mut self, i: u32, j: u32, color: Color) {
+        let index = self.index(i, j);
+        self.background[index] = color;
+    }
+
+    // all the pixels in row major order
+    pub fn pixels(&self) -> &[Color] {
+        &self.pixels
+    }
+
+    // alpha of all the pixels in row major order
+    pub fn alpha(&self) -> &[f64] {
+        &self.alpha
+    }
+
+    // background part of all the pixels in row major order
+    pub fn background(&self) -> &[Color] {
+        &self.background
+    }
+
+    fn index(&self, i: u32, j: u32) -> usize {
+        j as usize * self.width as usize + i as usize
+    }
+}
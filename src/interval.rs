@@ -1,4 +1,5 @@
 use crate::utils::*;
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct Interval {
     pub min: f64,
     pub max: f64,
@@ -17,6 +18,27 @@ impl Interval {
         Interval { min, max }
     }
 
+    // the tightest interval enclosing both given intervals
+    pub fn enclosing(a: &Interval, b: &Interval) -> Self {
+        Interval {
+            min: a.min.min(b.min),
+            max: a.max.max(b.max),
+        }
+    }
+
+    pub fn size(&self) -> f64 {
+        self.max - self.min
+    }
+
+    // the interval widened by delta, half on each side
+    pub fn expand(&self, delta: f64) -> Self {
+        let padding = delta / 2.0;
+        Interval {
+            min: self.min - padding,
+            max: self.max + padding,
+        }
+    }
+
     pub fn contains(&self, x: f64) -> bool {
         self.min <= x && self.max >= x
     }
@@ -37,9 +59,6 @@ impl Interval {
 
 impl Default for Interval {
     fn default() -> Self {
-        Self {
-            min: INFINITY,
-            max: -INFINITY,
-        }
+        EMPTY_INTERVAL
     }
 }
@@ -1,68 +1,118 @@
+mod aabb;
+mod bvh;
 mod camera;
+mod cli;
 mod color;
+mod decoder;
+mod disk;
+mod encoder;
+mod gltf_scene;
 mod hittables;
+mod image;
 mod interval;
 mod material;
+mod medium;
+mod mesh;
+mod microfacet;
+mod obj;
+mod onb;
+mod perlin;
+mod ply;
+mod quad;
 mod ray;
+mod sampler;
+mod scene;
+mod spectrum;
 mod sphere;
+mod texture;
+mod thin_film;
 mod traits;
+mod transform;
+mod triangle;
 mod utils;
+// some of the oldest vec3 tests declare vectors mut that they never change
+#[cfg_attr(test, allow(unused_mut))]
 mod vec3;
-use std::env;
+mod voxels;
+use clap::Parser;
+use cli::Args;
+use std::process;
+use std::time::Instant;
 
+use bvh::BvhNode;
 use camera::Camera;
-use color::write_color;
 use color::Color;
+use encoder::OutputFormat;
 use hittables::{HitRecord, Hittables};
 use interval::Interval;
-use material::{Dielectric, Lambertian, Metal};
+use material::{Dielectric, Lambertian, Metal, Principled, PrincipledParams};
 use ray::Ray;
+use sampler::Sampler;
 use sphere::Sphere;
 use std::fs::File;
-use std::io::BufWriter;
-use std::rc::Rc;
+use std::io::{BufWriter, Write};
+use std::sync::Arc;
 use traits::{Hittable, Scatter};
 use vec3::{Point3, Vec3};
 pub type Error = Box<dyn std::error::Error>;
 pub type Result<T> = std::result::Result<T, Error>;
 
 // A helper function to randomly pick a material
-fn get_rand_material() -> Rc<dyn Scatter> {
-    let choose_mat = utils::random_f64();
+fn get_rand_material(sampler: &mut Sampler) -> Arc<dyn Scatter> {
+    let choose_mat = sampler.random_f64();
     match choose_mat {
         x if x < 0.8 => {
             // difuse
-            Rc::new(Lambertian::new(
-                &Color::random(0.0, 1.0) * &Color::random(0.0, 1.0),
+            Arc::new(Lambertian::new(
+                &Color::random(0.0, 1.0, sampler) * &Color::random(0.0, 1.0, sampler),
             ))
         }
         x if x < 0.95 => {
             // matel
-            let albedo = Color::random(0.5, 1.0);
-            let fuzz = utils::random_f64_range(0.0, 0.5);
-            Rc::new(Metal::new(albedo, fuzz))
+            let albedo = Color::random(0.5, 1.0, sampler);
+            let fuzz = sampler.random_f64_range(0.0, 0.5);
+            Arc::new(Metal::new(albedo, fuzz))
         }
         _ => {
             // glass
-            Rc::new(Dielectric::new(1.5))
+            Arc::new(Dielectric::new(1.5))
         }
     }
 }
 
-fn main() -> Result<()> {
-    let arg1 = env::args().nth(1);
-    let output_fname = if let Some(fname) = arg1 {
-        fname
-    } else {
-        "images/image_0.ppm".to_string()
+// a random principled material: plastics, paints and metals of any roughness, some of
+// them varnished, and now and then tinted glass
+fn get_rand_principled(sampler: &mut Sampler) -> Arc<dyn Scatter> {
+    let base_color = Color::random(0.2, 1.0, sampler);
+    let mut params = PrincipledParams {
+        roughness: sampler.random_f64(),
+        ..Default::default()
     };
-    let file = File::create(output_fname)?;
-    let mut writer = BufWriter::new(file);
+    match sampler.random_f64() {
+        x if x < 0.6 => {
+            params.clearcoat = if sampler.random_f64() < 0.3 { 1.0 } else { 0.0 };
+            params.sheen = sampler.random_f64_range(0.0, 0.5);
+        }
+        x if x < 0.9 => params.metallic = 1.0,
+        _ => {
+            params.transmission = 1.0;
+            params.roughness = sampler.random_f64_range(0.0, 0.3);
+        }
+    }
+    Arc::new(Principled::new(base_color, params))
+}
+
+// the cover scene of the book: a ground sphere, many small random balls made of
+// random_material and three big ones
+fn cover_scene(
+    sampler: &mut Sampler,
+    random_material: fn(&mut Sampler) -> Arc<dyn Scatter>,
+) -> Hittables {
     let mut world = Hittables::default();
     // ground
     //meterial
-    let material_ground = Rc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
-    world.add(Box::new(Sphere::new(
+    let material_ground = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    world.add(Arc::new(Sphere::new(
         Point3::new(0.0, -1000.0, 0.0),
         1000.0,
         material_ground.clone(),
@@ -74,35 +124,38 @@ fn main() -> Result<()> {
     for a in -count..count {
         for b in -count..count {
             let center = Point3::new(
-                a as f64 + utils::random_f64(),
+                a as f64 + sampler.random_f64(),
                 0.2,
-                b as f64 + utils::random_f64(),
+                b as f64 + sampler.random_f64(),
             );
             if (&center - &p).length() > 0.9 {
-                world.add(Box::new(Sphere::new(center, 0.2, get_rand_material())));
+                world.add(Arc::new(Sphere::new(center, 0.2, random_material(sampler))));
             }
         }
     }
     // a few big balls
-    let material_1 = Rc::new(Dielectric::new(1.5));
-    world.add(Box::new(Sphere::new(
+    let material_1 = Arc::new(Dielectric::new(1.5));
+    world.add(Arc::new(Sphere::new(
         Point3::new(0.0, 1.0, 0.0),
         1.0,
         material_1.clone(),
     )));
-    let material_2 = Rc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
-    world.add(Box::new(Sphere::new(
+    let material_2 = Arc::new(Lambertian::new(Color::new(0.4, 0.2, 0.1)));
+    world.add(Arc::new(Sphere::new(
         Point3::new(-4.0, 1.0, 0.0),
         1.0,
         material_2.clone(),
     )));
-    let material_3 = Rc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
-    world.add(Box::new(Sphere::new(
+    let material_3 = Arc::new(Metal::new(Color::new(0.7, 0.6, 0.5), 0.0));
+    world.add(Arc::new(Sphere::new(
         Point3::new(4.0, 1.0, 0.0),
         1.0,
         material_3.clone(),
     )));
+    world
+}
 
+fn cover_camera() -> Camera {
     let image_width = 1200; // pixels
                             // camera
     let mut camera = Camera::new(
@@ -116,10 +169,71 @@ fn main() -> Result<()> {
     camera.look_at = Point3::new(0.0, 0.0, 0.0);
     camera.defocus_angle = 0.6;
     camera.focus_dist = 10.0;
-    if let Ok(()) = camera.render(&world, &mut writer) {
-        println!("Program runs Ok");
-    } else {
-        eprintln!("Program runs NOT Ok");
-    }
+    camera
+}
+
+fn run(args: &Args) -> Result<()> {
+    let (world, lights, mut camera) = match &args.scene {
+        Some(scene_fname) => {
+            let extension = scene_fname
+                .extension()
+                .and_then(|extension| extension.to_str())
+                .map(|extension| extension.to_ascii_lowercase());
+            let scene = match extension.as_deref() {
+                Some("gltf" | "glb") => {
+                    let (scene, warnings) = gltf_scene::load_gltf(scene_fname)?;
+                    for warning in warnings {
+                        eprintln!("warning: {}", warning);
+                    }
+                    scene
+                }
+                _ => scene::load_scene(scene_fname)?,
+            };
+            (scene.world, scene.lights, scene.camera)
+        }
+        None => {
+            let mut sampler = Sampler::new(args.seed);
+            let random_material = if args.principled {
+                get_rand_principled
+            } else {
+                get_rand_material
+            };
+            (
+                cover_scene(&mut sampler, random_material),
+                Hittables::default(),
+                cover_camera(),
+            )
+        }
+    };
+    args.apply(&mut camera);
+    camera.validate()?;
+    let format = match args.format {
+        Some(format) => format,
+        None => OutputFormat::from_path(&args.output)?,
+    };
+    let file = File::create(&args.output)
+        .map_err(|err| format!("cannot create {}: {}", args.output.display(), err))?;
+    let mut writer = BufWriter::new(file);
+
+    let start = Instant::now();
+    let world = BvhNode::new(world, args.bvh);
+    println!("BVH built in {:.2?}", start.elapsed());
+
+    let start = Instant::now();
+    let image = camera.render(&world, &lights);
+    println!("Rendered in {:.2?}", start.elapsed());
+    format.encoder().encode(&image, &mut writer)?;
+    writer.flush()?;
     Ok(())
 }
+
+fn main() {
+    let args = Args::parse();
+    match run(&args) {
+        Ok(()) => println!("Program runs Ok"),
+        Err(err) => {
+            eprintln!("error: {}", err);
+            process::exit(1);
+        }
+    }
+}
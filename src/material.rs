@@ -1,82 +1,401 @@
-use crate::utils::random_f64;
+use crate::microfacet::{
+    fresnel_conductor_rgb, fresnel_dielectric, fresnel_schlick, schlick_weight, Ggx, MetalPreset,
+};
+use crate::onb::{henyey_greenstein, random_cosine_direction, random_henyey_greenstein, Onb};
+use crate::sampler::Sampler;
+use crate::spectrum::{Ior, Wavelengths, LAMBDA_D};
+use crate::texture::SolidColor;
+use crate::thin_film::{channel_value, ThinFilm};
+use crate::traits::Texture;
+use crate::utils::PI;
 use crate::Color;
+use crate::HitRecord;
 use crate::Ray;
 use crate::Scatter;
 use crate::Vec3;
+use std::sync::Arc;
 
-// diffusive
-#[derive(Default, Debug, Clone)]
+// the outcome of a ray scattering off a surface
+pub struct ScatterRecord {
+    // bsdf times cosine over the pdf of the scattered direction, the factor the light
+    // arriving along the scattered ray is multiplied by
+    pub attenuation: Color,
+    pub scattered: Ray,
+    // density the scattered direction was chosen with, None for specular scattering,
+    // which cannot be combined with light sampling
+    pub pdf: Option<f64>,
+}
+
+// diffusive, the vertex colors of a mesh replace the albedo
+#[derive(Clone)]
 pub struct Lambertian {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
 }
 
 // reflective
-#[derive(Default, Debug, Clone)]
+#[derive(Clone)]
 pub struct Metal {
-    albedo: Color,
+    albedo: Arc<dyn Texture>,
     fuzz: f64,
 }
 
-#[derive(Default, Debug, Clone)]
+// metal whose surface is made of mirror microfacets with a ggx distribution, each
+// reflecting as much light as the fresnel equations give for the complex index of
+// refraction eta + i k of the metal. a thin film on top, such as the oxide of anodised
+// metal, colors the reflection with interference
+#[derive(Debug, Clone)]
+pub struct Conductor {
+    eta: Color,
+    k: Color,
+    distribution: Ggx,
+    film: Option<ThinFilm>,
+}
+
+// glass or water. light going through the inside is absorbed following the
+// beer-lambert law, keeping exp(-absorption * distance) of each color, and light passing
+// through the surface is tinted, for thin shells such as bubbles with no inside to
+// speak of. absorption needs a closed surface: the distance is taken from the surface
+// the ray left before reaching the back of the glass, which is only where it entered
+// when the glass encloses its inside. an index of refraction changing with the wavelength splits white light
+// into its colors in spectral mode. a thin film on the surface, such as the wall of a
+// soap bubble on glass of index 1, reflects in colors changing with the angle
+#[derive(Debug, Clone)]
 pub struct Dielectric {
-    ir: f64, // index of reflection
+    ior: Ior,
+    absorption: Color,
+    tint: Color,
+    film: Option<ThinFilm>,
+}
+
+// glass or water with a rough surface of microfacets with a ggx distribution, each
+// reflecting or refracting like a smooth dielectric
+#[derive(Debug, Clone)]
+pub struct RoughDielectric {
+    ir: f64, // index of refraction
+    distribution: Ggx,
+}
+
+// the scalar parameters of a principled material, all between 0 and 1 but the ior
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PrincipledParams {
+    // blends the dielectric base into a metal reflecting the base color
+    pub metallic: f64,
+    // roughness of the specular and transmission lobes, 0 is a mirror
+    pub roughness: f64,
+    // strength of the dielectric reflection, 0.5 is the 4% of most materials
+    pub specular: f64,
+    // strength of a second, colorless and glossy specular layer on top
+    pub clearcoat: f64,
+    // a soft rim of white light at grazing angles, as on cloth
+    pub sheen: f64,
+    // share of the dielectric base that is glass rather than diffuse
+    pub transmission: f64,
+    // index of refraction of the glass
+    pub ior: f64,
+}
+
+// disney style uber material: a diffuse base with sheen, a specular layer turning into
+// metal with metallic, glass for transmission and a clearcoat, all blended together
+// and sampled lobe by lobe
+#[derive(Clone)]
+pub struct Principled {
+    base_color: Arc<dyn Texture>,
+    params: PrincipledParams,
+    specular: Ggx,
+    clearcoat: Ggx,
+    glass: RoughDielectric,
+}
+
+// scatters light equally into all directions, the phase function of the particles of
+// a medium
+#[derive(Clone)]
+pub struct Isotropic {
+    albedo: Arc<dyn Texture>,
+}
+
+// scatters light with the henyey-greenstein phase function, mostly onwards for g
+// above 0 and back for g below 0, like the particles of smoke and clouds
+#[derive(Clone)]
+pub struct HenyeyGreenstein {
+    albedo: Arc<dyn Texture>,
+    g: f64,
+}
+
+// emits light equally in all directions, and scatters none
+#[derive(Default, Debug, Clone)]
+pub struct DiffuseLight {
+    emit: Color,
 }
 
 impl Lambertian {
     pub fn new(color: Color) -> Self {
-        Self { albedo: color }
+        Self::with_texture(Arc::new(SolidColor::new(color)))
+    }
+
+    pub fn with_texture(albedo: Arc<dyn Texture>) -> Self {
+        Self { albedo }
+    }
+
+    fn albedo(&self, rec: &HitRecord) -> Color {
+        match &rec.vertex_color {
+            Some(color) => color.clone(),
+            None => self.albedo.value(rec.u, rec.v, &rec.point),
+        }
+    }
+}
+
+impl Default for Lambertian {
+    fn default() -> Self {
+        Self::new(Color::default())
     }
 }
+
 impl Scatter for Lambertian {
     fn scatter(
         &self,
-        _ray_in: &crate::ray::Ray,
-        rec: &crate::hittables::HitRecord,
-        attenuation: &mut Color,
-        ray_scattered: &mut Ray,
-    ) -> bool {
-        let mut scatter_direction = &rec.normal + Vec3::random_unit_vec3();
-        if scatter_direction.near_zero() {
-            scatter_direction = rec.normal.clone();
-        }
-        *ray_scattered = Ray {
-            orig: rec.point.clone(),
-            dir: scatter_direction,
-        };
-        attenuation.set_with_other(&self.albedo);
-        true
+        ray_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut Sampler,
+    ) -> Option<ScatterRecord> {
+        let uvw = Onb::new(&rec.normal);
+        let scatter_direction = uvw.transform(&random_cosine_direction(sampler));
+        let pdf = scatter_direction.dot(&rec.normal).max(0.0) / PI;
+        Some(ScatterRecord {
+            attenuation: self.albedo(rec),
+            scattered: Ray {
+                orig: rec.point.clone(),
+                dir: scatter_direction,
+                time: ray_in.time,
+            },
+            pdf: Some(pdf),
+        })
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        // the bsdf albedo / pi times the cosine is the same as albedo * pdf
+        &self.albedo(rec) * self.scattering_pdf(ray_in, rec, direction)
+    }
+
+    fn scattering_pdf(&self, _ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        let cosine = rec.normal.dot(&direction.make_unit_vector());
+        cosine.max(0.0) / PI
     }
 }
 
 impl Metal {
     pub fn new(color: Color, fuzz: f64) -> Self {
-        Self {
-            albedo: color,
-            fuzz,
-        }
+        Self::with_texture(Arc::new(SolidColor::new(color)), fuzz)
+    }
+
+    pub fn with_texture(albedo: Arc<dyn Texture>, fuzz: f64) -> Self {
+        Self { albedo, fuzz }
     }
 }
 impl Scatter for Metal {
     fn scatter(
         &self,
         ray_in: &Ray,
-        rec: &crate::hittables::HitRecord,
-        attenuation: &mut Color,
-        ray_scattered: &mut Ray,
-    ) -> bool {
+        rec: &HitRecord,
+        sampler: &mut Sampler,
+    ) -> Option<ScatterRecord> {
         let reflected = Vec3::reflect(&ray_in.dir, &rec.normal);
-        *ray_scattered = Ray {
-            orig: rec.point.clone(),
-            dir: reflected + Vec3::random_unit_vec3() * self.fuzz,
+        Some(ScatterRecord {
+            attenuation: self.albedo.value(rec.u, rec.v, &rec.point),
+            scattered: Ray {
+                orig: rec.point.clone(),
+                dir: reflected + Vec3::random_unit_vec3(sampler) * self.fuzz,
+                time: ray_in.time,
+            },
+            pdf: None,
+        })
+    }
+}
+
+// the directions of a ray hitting a surface in the frame of its normal, wo pointing
+// back along the incoming ray and wi along direction, both of unit length
+fn local_directions(ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> (Onb, Vec3, Vec3) {
+    let uvw = Onb::new(&rec.normal);
+    let wo = uvw.to_local(&ray_in.dir.make_unit_vector().reverse());
+    let wi = uvw.to_local(&direction.make_unit_vector());
+    (uvw, wo, wi)
+}
+
+impl Conductor {
+    // roughness from 0, a mirror, to 1
+    pub fn new(eta: Color, k: Color, roughness: f64) -> Self {
+        Self {
+            eta,
+            k,
+            distribution: Ggx::from_roughness(roughness),
+            film: None,
+        }
+    }
+
+    pub fn preset(metal: MetalPreset, roughness: f64) -> Self {
+        let (eta, k) = metal.ior();
+        Self::new(eta, k, roughness)
+    }
+
+    pub fn with_film(mut self, film: ThinFilm) -> Self {
+        self.film = Some(film);
+        self
+    }
+
+    // fraction of the light reflected by a microfacet seen at cos_i, at the wavelengths
+    // in spectral mode
+    fn fresnel(&self, cos_i: f64, wavelengths: Option<&Wavelengths>) -> Color {
+        match (&self.film, wavelengths) {
+            (Some(film), _) => film.reflectance_color(cos_i, wavelengths, 1.0, |lambda| {
+                (
+                    channel_value(&self.eta, lambda),
+                    channel_value(&self.k, lambda),
+                )
+            }),
+            (None, Some(wavelengths)) => {
+                wavelengths.spectrum_of(&fresnel_conductor_rgb(cos_i, &self.eta, &self.k))
+            }
+            (None, None) => fresnel_conductor_rgb(cos_i, &self.eta, &self.k),
+        }
+    }
+
+    fn scatter_at(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        wavelengths: Option<&Wavelengths>,
+        sampler: &mut Sampler,
+    ) -> Option<ScatterRecord> {
+        let (uvw, wo, _) = local_directions(ray_in, rec, &rec.normal);
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let (wi, attenuation, pdf) = if self.distribution.is_smooth() {
+            let wi = Vec3::new(-wo.x(), -wo.y(), wo.z());
+            (wi, self.fresnel(wo.z(), wavelengths), None)
+        } else {
+            let h = self.distribution.sample_visible_normal(
+                &wo,
+                sampler.random_f64(),
+                sampler.random_f64(),
+            );
+            let wo_dot_h = wo.dot(&h);
+            let wi = &(2.0 * wo_dot_h * &h) - &wo;
+            // reflected below the surface by the microfacet, the light is lost
+            if wi.z() <= 0.0 {
+                return None;
+            }
+            // the bsdf times cosine over the pdf leaves the fresnel term and the share
+            // of the light not shadowed on its way out
+            let shadowing = self.distribution.g(&wo, &wi) / self.distribution.g1(&wo);
+            let attenuation = self.fresnel(wo_dot_h, wavelengths) * shadowing;
+            let pdf = self.distribution.visible_normal_pdf(&wo, &h) / (4.0 * wo_dot_h);
+            (wi, attenuation, Some(pdf))
         };
-        attenuation.set_with_other(&self.albedo);
-        true
+        Some(ScatterRecord {
+            attenuation,
+            scattered: Ray {
+                orig: rec.point.clone(),
+                dir: uvw.transform(&wi),
+                time: ray_in.time,
+            },
+            pdf,
+        })
+    }
+
+    fn eval_at(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        direction: &Vec3,
+        wavelengths: Option<&Wavelengths>,
+    ) -> Color {
+        let (_, wo, wi) = local_directions(ray_in, rec, direction);
+        if self.distribution.is_smooth() || wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return Color::default();
+        }
+        let h = (&wo + &wi).make_unit_vector();
+        let fresnel = self.fresnel(wo.dot(&h), wavelengths);
+        fresnel * (self.distribution.d(&h) * self.distribution.g(&wo, &wi) / (4.0 * wo.z()))
+    }
+}
+
+impl Scatter for Conductor {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut Sampler,
+    ) -> Option<ScatterRecord> {
+        self.scatter_at(ray_in, rec, None, sampler)
+    }
+
+    fn scatter_spectral(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        wavelengths: &mut Wavelengths,
+        sampler: &mut Sampler,
+    ) -> Option<ScatterRecord> {
+        self.scatter_at(ray_in, rec, Some(wavelengths), sampler)
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        self.eval_at(ray_in, rec, direction, None)
+    }
+
+    fn eval_spectral(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        direction: &Vec3,
+        wavelengths: &Wavelengths,
+    ) -> Color {
+        self.eval_at(ray_in, rec, direction, Some(wavelengths))
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        let (_, wo, wi) = local_directions(ray_in, rec, direction);
+        if self.distribution.is_smooth() || wo.z() <= 0.0 || wi.z() <= 0.0 {
+            return 0.0;
+        }
+        let h = (&wo + &wi).make_unit_vector();
+        self.distribution.visible_normal_pdf(&wo, &h) / (4.0 * wo.dot(&h))
     }
 }
 
 impl Dielectric {
+    // clear glass
     pub fn new(ir: f64) -> Self {
-        Self { ir }
+        Self::tinted(
+            Ior::Constant(ir),
+            Color::default(),
+            Color::new(1.0, 1.0, 1.0),
+        )
+    }
+
+    // absorption is per unit of distance, the tint is applied every time light refracts
+    pub fn tinted(ior: Ior, absorption: Color, tint: Color) -> Self {
+        Self {
+            ior,
+            absorption,
+            tint,
+            film: None,
+        }
+    }
+
+    pub fn with_film(mut self, film: ThinFilm) -> Self {
+        self.film = Some(film);
+        self
+    }
+
+    // share of the light left after travelling distance through the inside
+    fn transmittance(&self, distance: f64) -> Color {
+        let absorbed = |absorption: f64| (-absorption * distance).exp();
+        Color::new(
+            absorbed(self.absorption.x()),
+            absorbed(self.absorption.y()),
+            absorbed(self.absorption.z()),
+        )
     }
 
     fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
@@ -85,38 +404,905 @@ impl Dielectric {
         let r0 = r0.powi(2);
         r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
     }
+
+    // scatter for light seeing the index of refraction ir, at the wavelengths in
+    // spectral mode
+    fn scatter_with_ir(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        ir: f64,
+        wavelengths: Option<&Wavelengths>,
+        sampler: &mut Sampler,
+    ) -> Option<ScatterRecord> {
+        let refraction_ratio = if rec.front_face { 1.0 / ir } else { ir };
+        let unit_direction = ray_in.dir.make_unit_vector();
+        let cos_theta = unit_direction.reverse().dot(&rec.normal).min(1.0);
+        let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
+        let can_reflect = refraction_ratio * sin_theta > 1.0;
+        // a film reflects each color its own share, the ray is reflected with their
+        // average chance and carries the colors weighed against it
+        let (near, far) = if rec.front_face { (1.0, ir) } else { (ir, 1.0) };
+        let film_reflectance = self
+            .film
+            .filter(|_| !can_reflect)
+            .map(|film| film.reflectance_color(cos_theta, wavelengths, near, |_| (far, 0.0)));
+        let reflect_chance = match &film_reflectance {
+            Some(reflectance) => (reflectance.x() + reflectance.y() + reflectance.z()) / 3.0,
+            None => Self::reflectance(cos_theta, refraction_ratio),
+        };
+        let reflected = can_reflect || reflect_chance > sampler.random_f64();
+        let (direction, tint) = if reflected {
+            (Vec3::reflect(&unit_direction, &rec.normal), None)
+        } else {
+            let direction = Vec3::refract(&unit_direction, &rec.normal, refraction_ratio);
+            (direction, Some(&self.tint))
+        };
+        // hit from behind, the ray crossed the inside of the glass from where it entered
+        // or was last reflected
+        let mut attenuation = if rec.front_face {
+            Color::new(1.0, 1.0, 1.0)
+        } else {
+            self.transmittance(rec.t * ray_in.dir.length())
+        };
+        if let Some(tint) = tint {
+            attenuation = &attenuation * tint;
+        }
+        if let Some(wavelengths) = wavelengths {
+            attenuation = wavelengths.spectrum_of(&attenuation);
+        }
+        if let Some(reflectance) = film_reflectance {
+            attenuation = if reflected {
+                &attenuation * &(reflectance / reflect_chance)
+            } else {
+                let transmitted = Color::new(1.0, 1.0, 1.0) - reflectance;
+                &attenuation * &(transmitted / (1.0 - reflect_chance))
+            };
+        }
+        Some(ScatterRecord {
+            attenuation,
+            scattered: Ray {
+                orig: rec.point.clone(),
+                dir: direction,
+                time: ray_in.time,
+            },
+            pdf: None,
+        })
+    }
 }
 
 impl Scatter for Dielectric {
     fn scatter(
         &self,
         ray_in: &Ray,
-        rec: &crate::hittables::HitRecord,
-        attenuation: &mut Color,
-        ray_scattered: &mut Ray,
-    ) -> bool {
-        attenuation.set(1.0, 1.0, 1.0);
+        rec: &HitRecord,
+        sampler: &mut Sampler,
+    ) -> Option<ScatterRecord> {
+        self.scatter_with_ir(ray_in, rec, self.ior.at(LAMBDA_D), None, sampler)
+    }
 
-        let refraction_ratio = if rec.front_face {
+    fn scatter_spectral(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        wavelengths: &mut Wavelengths,
+        sampler: &mut Sampler,
+    ) -> Option<ScatterRecord> {
+        // the other wavelengths would refract into other directions
+        if self.ior.is_dispersive() {
+            wavelengths.terminate_secondary();
+        }
+        let ir = self.ior.at(wavelengths.hero());
+        self.scatter_with_ir(ray_in, rec, ir, Some(wavelengths), sampler)
+    }
+}
+
+impl RoughDielectric {
+    // roughness from 0, smooth glass, to 1
+    pub fn new(ir: f64, roughness: f64) -> Self {
+        Self {
+            ir,
+            distribution: Ggx::from_roughness(roughness),
+        }
+    }
+
+    // index of refraction of the far side of the surface over that of the near side
+    fn eta(&self, rec: &HitRecord) -> f64 {
+        if rec.front_face {
+            self.ir
+        } else {
             1.0 / self.ir
+        }
+    }
+
+    // the microfacet normal refracting wo into wi, eta is the index of wi's side over
+    // that of wo's
+    fn refraction_normal(wo: &Vec3, wi: &Vec3, eta: f64) -> Option<Vec3> {
+        let h = (wo + &(eta * wi)).make_unit_vector();
+        let h = if h.z() < 0.0 { h.reverse() } else { h };
+        // the microfacet must face wo and have wi behind it
+        (wo.dot(&h) > 0.0 && wi.dot(&h) < 0.0).then_some(h)
+    }
+
+    // change of the microfacet normal with the refracted direction, over its solid angle
+    fn refraction_jacobian(wo: &Vec3, wi: &Vec3, h: &Vec3, eta: f64) -> f64 {
+        let denom = wi.dot(h) + wo.dot(h) / eta;
+        wi.dot(h).abs() / (denom * denom)
+    }
+
+    // scatter in the frame of the normal: the direction wi, the attenuation and the pdf,
+    // None for smooth glass
+    fn sample_local(
+        &self,
+        wo: &Vec3,
+        eta: f64,
+        sampler: &mut Sampler,
+    ) -> Option<(Vec3, f64, Option<f64>)> {
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let smooth = self.distribution.is_smooth();
+        let h = if smooth {
+            Vec3::new(0.0, 0.0, 1.0)
         } else {
-            self.ir
+            self.distribution
+                .sample_visible_normal(wo, sampler.random_f64(), sampler.random_f64())
         };
-        let unit_direction = ray_in.dir.make_unit_vector();
-        let cos_theta = unit_direction.reverse().dot(&rec.normal).min(1.0);
-        let sin_theta = (1.0 - cos_theta.powi(2)).sqrt();
-        let can_reflect = refraction_ratio * sin_theta > 1.0;
-        let direction =
-            if can_reflect || Self::reflectance(cos_theta, refraction_ratio) > random_f64() {
-                Vec3::reflect(&unit_direction, &rec.normal)
+        let wo_dot_h = wo.dot(&h);
+        let fresnel = fresnel_dielectric(wo_dot_h, eta);
+        let (wi, attenuation, pdf) = if sampler.random_f64() < fresnel {
+            let wi = &(2.0 * wo_dot_h * &h) - wo;
+            if wi.z() <= 0.0 {
+                return None;
+            }
+            let pdf = fresnel * self.distribution.visible_normal_pdf(wo, &h) / (4.0 * wo_dot_h);
+            (wi, 1.0, pdf)
+        } else {
+            let wi = Vec3::refract(&wo.reverse(), &h, 1.0 / eta);
+            if wi.z() >= 0.0 {
+                return None;
+            }
+            // radiance is squeezed into the narrower cone of the denser side
+            let pdf = (1.0 - fresnel)
+                * self.distribution.visible_normal_pdf(wo, &h)
+                * Self::refraction_jacobian(wo, &wi, &h, eta);
+            (wi, 1.0 / (eta * eta), pdf)
+        };
+        if smooth {
+            return Some((wi, attenuation, None));
+        }
+        // the bsdf times cosine over the pdf leaves the share of the light not shadowed on
+        // its way out
+        let shadowing = self.distribution.g(wo, &wi) / self.distribution.g1(wo);
+        Some((wi, attenuation * shadowing, Some(pdf)))
+    }
+
+    // eval in the frame of the normal
+    fn eval_local(&self, wo: &Vec3, wi: &Vec3, eta: f64) -> f64 {
+        if self.distribution.is_smooth() || wo.z() <= 0.0 || wi.z() == 0.0 {
+            return 0.0;
+        }
+        let dg = |h: &Vec3| self.distribution.d(h) * self.distribution.g(wo, wi);
+        if wi.z() > 0.0 {
+            let h = (wo + wi).make_unit_vector();
+            fresnel_dielectric(wo.dot(&h), eta) * dg(&h) / (4.0 * wo.z())
+        } else {
+            let Some(h) = Self::refraction_normal(wo, wi, eta) else {
+                return 0.0;
+            };
+            let fresnel = fresnel_dielectric(wo.dot(&h), eta);
+            (1.0 - fresnel) * dg(&h) * wo.dot(&h) * Self::refraction_jacobian(wo, wi, &h, eta)
+                / (wo.z() * eta * eta)
+        }
+    }
+
+    // scattering_pdf in the frame of the normal
+    fn pdf_local(&self, wo: &Vec3, wi: &Vec3, eta: f64) -> f64 {
+        if self.distribution.is_smooth() || wo.z() <= 0.0 || wi.z() == 0.0 {
+            return 0.0;
+        }
+        if wi.z() > 0.0 {
+            let h = (wo + wi).make_unit_vector();
+            fresnel_dielectric(wo.dot(&h), eta) * self.distribution.visible_normal_pdf(wo, &h)
+                / (4.0 * wo.dot(&h))
+        } else {
+            let Some(h) = Self::refraction_normal(wo, wi, eta) else {
+                return 0.0;
+            };
+            (1.0 - fresnel_dielectric(wo.dot(&h), eta))
+                * self.distribution.visible_normal_pdf(wo, &h)
+                * Self::refraction_jacobian(wo, wi, &h, eta)
+        }
+    }
+}
+
+impl Scatter for RoughDielectric {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut Sampler,
+    ) -> Option<ScatterRecord> {
+        let (uvw, wo, _) = local_directions(ray_in, rec, &rec.normal);
+        let (wi, attenuation, pdf) = self.sample_local(&wo, self.eta(rec), sampler)?;
+        Some(ScatterRecord {
+            attenuation: Color::new(1.0, 1.0, 1.0) * attenuation,
+            scattered: Ray {
+                orig: rec.point.clone(),
+                dir: uvw.transform(&wi),
+                time: ray_in.time,
+            },
+            pdf,
+        })
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let (_, wo, wi) = local_directions(ray_in, rec, direction);
+        let value = self.eval_local(&wo, &wi, self.eta(rec));
+        Color::new(value, value, value)
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        let (_, wo, wi) = local_directions(ray_in, rec, direction);
+        self.pdf_local(&wo, &wi, self.eta(rec))
+    }
+}
+
+impl Default for PrincipledParams {
+    fn default() -> Self {
+        Self {
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            clearcoat: 0.0,
+            sheen: 0.0,
+            transmission: 0.0,
+            ior: 1.5,
+        }
+    }
+}
+
+// the clearcoat is a glossy varnish whatever the roughness below it
+const CLEARCOAT_ROUGHNESS: f64 = 0.1;
+// reflectance of the clearcoat at normal incidence, that of a polyurethane varnish
+const CLEARCOAT_F0: f64 = 0.04;
+
+impl Principled {
+    pub fn new(base_color: Color, params: PrincipledParams) -> Self {
+        Self::with_texture(Arc::new(SolidColor::new(base_color)), params)
+    }
+
+    pub fn with_texture(base_color: Arc<dyn Texture>, params: PrincipledParams) -> Self {
+        Self {
+            base_color,
+            params,
+            specular: Ggx::from_roughness(params.roughness),
+            clearcoat: Ggx::from_roughness(CLEARCOAT_ROUGHNESS),
+            glass: RoughDielectric::new(params.ior, params.roughness),
+        }
+    }
+
+    fn base_color(&self, rec: &HitRecord) -> Color {
+        match &rec.vertex_color {
+            Some(color) => color.clone(),
+            None => self.base_color.value(rec.u, rec.v, &rec.point),
+        }
+    }
+
+    // weights of the diffuse, specular and glass lobes
+    fn weights(&self) -> (f64, f64, f64) {
+        let p = &self.params;
+        let dielectric = 1.0 - p.metallic;
+        (
+            dielectric * (1.0 - p.transmission),
+            1.0 - dielectric * p.transmission,
+            dielectric * p.transmission,
+        )
+    }
+
+    // reflectance of the specular lobe at normal incidence, from that of the dielectric
+    // to the base color of the metal
+    fn specular_f0(&self, base: &Color) -> Color {
+        let f0 = 0.08 * self.params.specular;
+        let dielectric = Color::new(f0, f0, f0) * (1.0 - self.params.metallic);
+        dielectric + base * self.params.metallic
+    }
+
+    // chances of sampling the diffuse, specular, glass and clearcoat lobes, roughly
+    // following how much light each of them reflects towards wo. None for a material
+    // reflecting nothing
+    fn lobe_probabilities(&self, base: &Color, wo: &Vec3) -> Option<[f64; 4]> {
+        let (diffuse, specular, glass) = self.weights();
+        let fresnel = fresnel_schlick(wo.z(), &self.specular_f0(base));
+        let mut chances = [
+            diffuse,
+            specular * (fresnel.x() + fresnel.y() + fresnel.z()) / 3.0,
+            glass,
+            self.clearcoat_weight(wo.z()),
+        ];
+        let total: f64 = chances.iter().sum();
+        if total <= 0.0 {
+            return None;
+        }
+        chances.iter_mut().for_each(|chance| *chance /= total);
+        Some(chances)
+    }
+
+    fn clearcoat_weight(&self, cos: f64) -> f64 {
+        let weight = schlick_weight(cos);
+        0.25 * self.params.clearcoat * (CLEARCOAT_F0 * (1.0 - weight) + weight)
+    }
+
+    // bsdf times cosine of the rough lobes in the frame of the normal, the smooth
+    // specular and glass lobes of a roughness of 0 are left out
+    fn eval_local(&self, base: &Color, wo: &Vec3, wi: &Vec3, eta: f64) -> Color {
+        let p = &self.params;
+        let (diffuse, specular, glass) = self.weights();
+        let mut value = Color::default();
+        if wi.z() > 0.0 {
+            let h = (wo + wi).make_unit_vector();
+            let cos_d = wi.dot(&h);
+            if diffuse > 0.0 {
+                // disney's diffuse darkens at grazing angles for smooth surfaces and
+                // brightens for rough ones
+                let fd90 = 0.5 + 2.0 * p.roughness * cos_d * cos_d;
+                let fd = (1.0 + (fd90 - 1.0) * schlick_weight(wo.z()))
+                    * (1.0 + (fd90 - 1.0) * schlick_weight(wi.z()));
+                let sheen = p.sheen * schlick_weight(cos_d);
+                value +=
+                    &((base * (fd / PI) + Color::new(sheen, sheen, sheen)) * (diffuse * wi.z()));
+            }
+            if !self.specular.is_smooth() {
+                let dg = self.specular.d(&h) * self.specular.g(wo, wi);
+                value += &(fresnel_schlick(wo.dot(&h), &self.specular_f0(base))
+                    * (specular * dg / (4.0 * wo.z())));
+            }
+            if p.clearcoat > 0.0 {
+                let dg = self.clearcoat.d(&h) * self.clearcoat.g(wo, wi);
+                let clearcoat = self.clearcoat_weight(wo.dot(&h)) * dg / (4.0 * wo.z());
+                value += &Color::new(clearcoat, clearcoat, clearcoat);
+            }
+        }
+        if glass > 0.0 {
+            let value_glass = glass * self.glass.eval_local(wo, wi, eta);
+            // light going through the glass is tinted by the base color
+            if wi.z() < 0.0 {
+                value += &(base * value_glass);
             } else {
-                Vec3::refract(&unit_direction, &rec.normal, refraction_ratio)
+                value += &Color::new(value_glass, value_glass, value_glass);
+            }
+        }
+        value
+    }
+
+    // scattering_pdf of the rough lobes in the frame of the normal
+    fn pdf_local(&self, chances: &[f64; 4], wo: &Vec3, wi: &Vec3, eta: f64) -> f64 {
+        let [diffuse, specular, glass, clearcoat] = *chances;
+        let mut pdf = glass * self.glass.pdf_local(wo, wi, eta);
+        if wi.z() > 0.0 {
+            let h = (wo + wi).make_unit_vector();
+            let reflection =
+                |distribution: &Ggx| distribution.visible_normal_pdf(wo, &h) / (4.0 * wo.dot(&h));
+            pdf += diffuse * wi.z() / PI + clearcoat * reflection(&self.clearcoat);
+            if !self.specular.is_smooth() {
+                pdf += specular * reflection(&self.specular);
+            }
+        }
+        pdf
+    }
+
+    // mirror wo about a microfacet normal drawn from the distribution
+    fn sample_reflection(distribution: &Ggx, wo: &Vec3, sampler: &mut Sampler) -> Vec3 {
+        let h = distribution.sample_visible_normal(wo, sampler.random_f64(), sampler.random_f64());
+        &(2.0 * wo.dot(&h) * &h) - wo
+    }
+}
+
+impl Scatter for Principled {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut Sampler,
+    ) -> Option<ScatterRecord> {
+        let (uvw, wo, _) = local_directions(ray_in, rec, &rec.normal);
+        if wo.z() <= 0.0 {
+            return None;
+        }
+        let base = self.base_color(rec);
+        let eta = self.glass.eta(rec);
+        let chances = self.lobe_probabilities(&base, &wo)?;
+        let (_, specular, glass) = self.weights();
+
+        let mut u = sampler.random_f64();
+        let lobe = chances
+            .iter()
+            .position(|chance| {
+                u -= chance;
+                u < 0.0
+            })
+            .unwrap_or(3);
+        // the smooth lobes scatter into a single direction
+        let specular_record = |wi: &Vec3, attenuation: Color| ScatterRecord {
+            attenuation,
+            scattered: Ray {
+                orig: rec.point.clone(),
+                dir: uvw.transform(wi),
+                time: ray_in.time,
+            },
+            pdf: None,
+        };
+        let wi = match lobe {
+            0 => random_cosine_direction(sampler),
+            1 if self.specular.is_smooth() => {
+                let wi = Vec3::new(-wo.x(), -wo.y(), wo.z());
+                let fresnel = fresnel_schlick(wo.z(), &self.specular_f0(&base));
+                return Some(specular_record(&wi, fresnel * (specular / chances[1])));
+            }
+            1 => Self::sample_reflection(&self.specular, &wo, sampler),
+            2 => {
+                let (wi, attenuation, pdf) = self.glass.sample_local(&wo, eta, sampler)?;
+                if pdf.is_none() {
+                    let tint = if wi.z() < 0.0 {
+                        base
+                    } else {
+                        Color::new(1.0, 1.0, 1.0)
+                    };
+                    let attenuation = tint * (attenuation * glass / chances[2]);
+                    return Some(specular_record(&wi, attenuation));
+                }
+                wi
+            }
+            _ => Self::sample_reflection(&self.clearcoat, &wo, sampler),
+        };
+        if wi.z() == 0.0 || (lobe != 2 && wi.z() < 0.0) {
+            return None;
+        }
+        // every rough lobe could have given wi, the attenuation takes all of them into
+        // account
+        let pdf = self.pdf_local(&chances, &wo, &wi, eta);
+        if pdf <= 0.0 {
+            return None;
+        }
+        Some(ScatterRecord {
+            attenuation: self.eval_local(&base, &wo, &wi, eta) / pdf,
+            scattered: Ray {
+                orig: rec.point.clone(),
+                dir: uvw.transform(&wi),
+                time: ray_in.time,
+            },
+            pdf: Some(pdf),
+        })
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        let (_, wo, wi) = local_directions(ray_in, rec, direction);
+        if wo.z() <= 0.0 {
+            return Color::default();
+        }
+        self.eval_local(&self.base_color(rec), &wo, &wi, self.glass.eta(rec))
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> f64 {
+        let (_, wo, wi) = local_directions(ray_in, rec, direction);
+        if wo.z() <= 0.0 {
+            return 0.0;
+        }
+        match self.lobe_probabilities(&self.base_color(rec), &wo) {
+            Some(chances) => self.pdf_local(&chances, &wo, &wi, self.glass.eta(rec)),
+            None => 0.0,
+        }
+    }
+}
+
+impl Isotropic {
+    pub fn with_texture(albedo: Arc<dyn Texture>) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Scatter for Isotropic {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut Sampler,
+    ) -> Option<ScatterRecord> {
+        Some(ScatterRecord {
+            attenuation: self.albedo.value(rec.u, rec.v, &rec.point),
+            scattered: Ray {
+                orig: rec.point.clone(),
+                dir: Vec3::random_unit_vec3(sampler),
+                time: ray_in.time,
+            },
+            pdf: Some(1.0 / (4.0 * PI)),
+        })
+    }
+
+    // a phase function has no cosine, it is light scattered per unit solid angle
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        &self.albedo.value(rec.u, rec.v, &rec.point) * self.scattering_pdf(ray_in, rec, direction)
+    }
+
+    fn scattering_pdf(&self, _ray_in: &Ray, _rec: &HitRecord, _direction: &Vec3) -> f64 {
+        1.0 / (4.0 * PI)
+    }
+}
+
+impl HenyeyGreenstein {
+    // g is the average cosine of the angle light turns by, between -1 and 1
+    pub fn with_texture(albedo: Arc<dyn Texture>, g: f64) -> Self {
+        Self { albedo, g }
+    }
+}
+
+impl Scatter for HenyeyGreenstein {
+    fn scatter(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        sampler: &mut Sampler,
+    ) -> Option<ScatterRecord> {
+        let uvw = Onb::new(&ray_in.dir);
+        let direction = uvw.transform(&random_henyey_greenstein(self.g, sampler));
+        let pdf = self.scattering_pdf(ray_in, rec, &direction);
+        Some(ScatterRecord {
+            attenuation: self.albedo.value(rec.u, rec.v, &rec.point),
+            scattered: Ray {
+                orig: rec.point.clone(),
+                dir: direction,
+                time: ray_in.time,
+            },
+            pdf: Some(pdf),
+        })
+    }
+
+    fn eval(&self, ray_in: &Ray, rec: &HitRecord, direction: &Vec3) -> Color {
+        &self.albedo.value(rec.u, rec.v, &rec.point) * self.scattering_pdf(ray_in, rec, direction)
+    }
+
+    fn scattering_pdf(&self, ray_in: &Ray, _rec: &HitRecord, direction: &Vec3) -> f64 {
+        let cosine = ray_in
+            .dir
+            .make_unit_vector()
+            .dot(&direction.make_unit_vector());
+        henyey_greenstein(self.g, cosine)
+    }
+}
+
+impl DiffuseLight {
+    pub fn new(emit: Color) -> Self {
+        Self { emit }
+    }
+}
+
+impl Scatter for DiffuseLight {
+    fn scatter(
+        &self,
+        _ray_in: &Ray,
+        _rec: &HitRecord,
+        _sampler: &mut Sampler,
+    ) -> Option<ScatterRecord> {
+        None
+    }
+
+    fn emitted(&self, _ray_in: &Ray, _rec: &HitRecord) -> Color {
+        self.emit.clone()
+    }
+
+    fn is_emissive(&self) -> bool {
+        !self.emit.near_zero()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Point3;
+
+    // a hit at the origin with the normal along z, seen from the front or from behind
+    fn hit(front_face: bool) -> HitRecord {
+        let mut rec = HitRecord::new();
+        rec.normal = Vec3::new(0.0, 0.0, 1.0);
+        rec.front_face = front_face;
+        rec
+    }
+
+    // for directions the material scatters into, the pdf and attenuation it gives match
+    // scattering_pdf and eval; and the pdf over all directions integrates to the share of
+    // rays not lost
+    fn check_sampling(material: &dyn Scatter, rec: &HitRecord, lost: f64) {
+        let ray_in = Ray {
+            orig: Point3::new(1.0, 0.5, 1.0),
+            dir: Vec3::new(-1.0, -0.5, -1.0),
+            time: 0.0,
+        };
+        let mut sampler = Sampler::new(17);
+        let mut scattered = 0;
+        let n = 20000;
+        for _ in 0..n {
+            let Some(srec) = material.scatter(&ray_in, rec, &mut sampler) else {
+                continue;
+            };
+            scattered += 1;
+            let direction = &srec.scattered.dir;
+            let pdf = srec.pdf.unwrap();
+            let expected = material.scattering_pdf(&ray_in, rec, direction);
+            assert!((pdf - expected).abs() <= 1e-6 * pdf, "{} {}", pdf, expected);
+            let value = &material.eval(&ray_in, rec, direction) / pdf;
+            assert!((&value - &srec.attenuation).length() < 1e-6 * value.length().max(1.0));
+        }
+        let kept = scattered as f64 / n as f64;
+        assert!(kept > 1.0 - lost, "{}", kept);
+        let total: f64 = (0..n)
+            .map(|_| {
+                let direction = Vec3::random_unit_vec3(&mut sampler);
+                material.scattering_pdf(&ray_in, rec, &direction) * 4.0 * PI
+            })
+            .sum::<f64>()
+            / n as f64;
+        assert!((total - kept).abs() < 0.05, "{} {}", total, kept);
+    }
+
+    #[test]
+    fn test_conductor_sampling() {
+        // rough surfaces reflect more light into their own microfacets, where it is lost
+        for (roughness, lost) in [(0.3, 0.05), (0.8, 0.3)] {
+            let material = Conductor::preset(MetalPreset::Gold, roughness);
+            check_sampling(&material, &hit(true), lost);
+            let coated = material.with_film(ThinFilm::new(250.0, 1.8));
+            check_sampling(&coated, &hit(true), lost);
+        }
+    }
+
+    #[test]
+    fn test_rough_dielectric_sampling() {
+        for front_face in [true, false] {
+            let material = RoughDielectric::new(1.5, 0.5);
+            check_sampling(&material, &hit(front_face), 0.1);
+        }
+    }
+
+    #[test]
+    fn test_dielectric_absorption() {
+        let glass = Dielectric::tinted(
+            Ior::Constant(1.5),
+            Color::new(0.0, 0.5, 1.0),
+            Color::new(1.0, 1.0, 0.5),
+        );
+        let mut sampler = Sampler::new(11);
+        // leaving the glass after two units inside, whether the ray refracts out or is
+        // reflected back in
+        let mut rec = hit(false);
+        rec.normal = Vec3::new(0.0, 0.0, -1.0);
+        rec.t = 4.0;
+        let ray_in = Ray {
+            orig: Point3::new(0.0, 0.0, -2.0),
+            dir: Vec3::new(0.0, 0.0, 0.5),
+            time: 0.0,
+        };
+        for _ in 0..50 {
+            let srec = glass.scatter(&ray_in, &rec, &mut sampler).unwrap();
+            let tint = if srec.scattered.dir.z() > 0.0 {
+                0.5
+            } else {
+                1.0
+            };
+            let expected = Color::new(1.0, (-1.0f64).exp(), (-2.0f64).exp() * tint);
+            assert!((&srec.attenuation - &expected).length() < 1e-12);
+        }
+        // entering, only the light refracting in is tinted
+        let rec = hit(true);
+        let ray_in = Ray {
+            orig: Point3::new(0.0, 0.0, 2.0),
+            dir: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        for _ in 0..50 {
+            let srec = glass.scatter(&ray_in, &rec, &mut sampler).unwrap();
+            let expected = if srec.scattered.dir.z() < 0.0 {
+                Color::new(1.0, 1.0, 0.5)
+            } else {
+                Color::new(1.0, 1.0, 1.0)
             };
-        *ray_scattered = Ray {
-            orig: rec.point.clone(),
-            dir: direction,
+            assert_eq!(srec.attenuation, expected);
+        }
+    }
+
+    #[test]
+    fn test_dispersion() {
+        let rec = hit(true);
+        let ray_in = Ray {
+            orig: Point3::new(1.0, 0.0, 1.0),
+            dir: Vec3::new(-1.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        let white = Color::new(1.0, 1.0, 1.0);
+        let flint = Dielectric::tinted("sf11".parse().unwrap(), Color::default(), white.clone());
+        let clear = Dielectric::tinted(Ior::Constant(1.5), Color::default(), white);
+        // the refracted direction of the hero wavelength, and whether the others went on
+        let refract = |material: &Dielectric, u: f64| {
+            let mut sampler = Sampler::new(1);
+            let mut wavelengths = Wavelengths::sample(u);
+            loop {
+                let srec = material
+                    .scatter_spectral(&ray_in, &rec, &mut wavelengths, &mut sampler)
+                    .unwrap();
+                if srec.scattered.dir.z() < 0.0 {
+                    return (srec.scattered.dir, wavelengths.secondary_terminated());
+                }
+            }
+        };
+        // violet light bends further towards the normal than red light
+        let (violet, terminated) = refract(&flint, 0.1);
+        let (red, _) = refract(&flint, 0.6);
+        assert!(terminated);
+        assert!(violet.x() / violet.z() < red.x() / red.z());
+        let (violet, terminated) = refract(&clear, 0.1);
+        let (red, _) = refract(&clear, 0.6);
+        assert!(!terminated);
+        assert!((&violet - &red).length() < 1e-12);
+    }
+
+    #[test]
+    fn test_thin_film_coatings() {
+        let rec = hit(true);
+        let ray_in = Ray {
+            orig: Point3::new(1.0, 0.0, 1.0),
+            dir: Vec3::new(-1.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        let cos_i = 0.5_f64.sqrt();
+        let film = ThinFilm::new(300.0, 1.33);
+        let bubble = Dielectric::new(1.0).with_film(film);
+        // averaged over its choices, a bubble reflects the film's colors and lets the
+        // rest through, in rgb and at the wavelengths in spectral mode
+        let n = 20000;
+        for wavelengths in [None, Some(Wavelengths::sample(0.3))] {
+            let mut sampler = Sampler::new(5);
+            let mut reflected = Color::default();
+            let mut refracted = Color::default();
+            for _ in 0..n {
+                let srec = match wavelengths {
+                    Some(mut wavelengths) => {
+                        bubble.scatter_spectral(&ray_in, &rec, &mut wavelengths, &mut sampler)
+                    }
+                    None => bubble.scatter(&ray_in, &rec, &mut sampler),
+                }
+                .unwrap();
+                if srec.scattered.dir.z() > 0.0 {
+                    reflected += &srec.attenuation;
+                } else {
+                    // glass of index 1 lets the light through unbent
+                    let straight = ray_in.dir.make_unit_vector();
+                    assert!((&srec.scattered.dir - &straight).length() < 1e-12);
+                    refracted += &srec.attenuation;
+                }
+            }
+            let expected = film.reflectance_color(cos_i, wavelengths.as_ref(), 1.0, |_| (1.0, 0.0));
+            let reflected = &reflected / n as f64;
+            let refracted = &refracted / n as f64;
+            assert!((&reflected - &expected).length() < 0.01, "{:?}", reflected);
+            let white = Color::new(1.0, 1.0, 1.0);
+            assert!((&(&reflected + &refracted) - &white).length() < 0.02);
+        }
+        // a smooth metal reflects the film's colors, blended with its own
+        let titanium = Conductor::new(Color::new(2.7, 2.5, 2.2), Color::new(3.8, 3.4, 3.0), 0.0);
+        let anodised = titanium.clone().with_film(ThinFilm::new(60.0, 2.4));
+        let mut sampler = Sampler::new(5);
+        let bare = titanium.scatter(&ray_in, &rec, &mut sampler).unwrap();
+        let srec = anodised.scatter(&ray_in, &rec, &mut sampler).unwrap();
+        assert!(srec.pdf.is_none());
+        let expected = ThinFilm::new(60.0, 2.4).reflectance_color(cos_i, None, 1.0, |lambda| {
+            (
+                channel_value(&Color::new(2.7, 2.5, 2.2), lambda),
+                channel_value(&Color::new(3.8, 3.4, 3.0), lambda),
+            )
+        });
+        assert!((&srec.attenuation - &expected).length() < 1e-12);
+        assert!((&srec.attenuation - &bare.attenuation).length() > 0.05);
+    }
+
+    #[test]
+    fn test_principled_sampling() {
+        let color = Color::new(0.8, 0.4, 0.2);
+        for (params, lost) in [
+            (PrincipledParams::default(), 0.01),
+            (
+                PrincipledParams {
+                    metallic: 0.7,
+                    roughness: 0.5,
+                    clearcoat: 1.0,
+                    sheen: 0.5,
+                    ..Default::default()
+                },
+                0.05,
+            ),
+            (
+                PrincipledParams {
+                    roughness: 0.5,
+                    transmission: 0.8,
+                    ..Default::default()
+                },
+                0.1,
+            ),
+        ] {
+            let material = Principled::new(color.clone(), params);
+            check_sampling(&material, &hit(true), lost);
+        }
+        // the inside of the glass, where the ior is inverted
+        let params = PrincipledParams {
+            transmission: 1.0,
+            ..Default::default()
         };
+        check_sampling(&Principled::new(color, params), &hit(false), 0.1);
+    }
 
-        true
+    #[test]
+    fn test_smooth_principled_lobes() {
+        let rec = hit(true);
+        let ray_in = Ray {
+            orig: Point3::new(0.0, 0.0, 1.0),
+            dir: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        let color = Color::new(0.9, 0.5, 0.1);
+        let mut sampler = Sampler::new(5);
+        // a smooth metal is a mirror reflecting its base color head on
+        let metal = Principled::new(
+            color.clone(),
+            PrincipledParams {
+                metallic: 1.0,
+                roughness: 0.0,
+                ..Default::default()
+            },
+        );
+        let srec = metal.scatter(&ray_in, &rec, &mut sampler).unwrap();
+        assert!(srec.pdf.is_none());
+        assert!((&srec.scattered.dir - &Vec3::new(0.0, 0.0, 1.0)).length() < 1e-12);
+        assert!((&srec.attenuation - &color).length() < 1e-12);
+        // light going through smooth glass is tinted by the base color
+        let glass = Principled::new(
+            color.clone(),
+            PrincipledParams {
+                roughness: 0.0,
+                transmission: 1.0,
+                ..Default::default()
+            },
+        );
+        let mut refracted = 0;
+        for _ in 0..100 {
+            let srec = glass.scatter(&ray_in, &rec, &mut sampler).unwrap();
+            assert!(srec.pdf.is_none());
+            if srec.scattered.dir.z() < 0.0 {
+                refracted += 1;
+                let expected = &color / (1.5 * 1.5);
+                assert!((&srec.attenuation - &expected).length() < 1e-12);
+            }
+        }
+        assert!(refracted > 90);
+    }
+
+    #[test]
+    fn test_smooth_microfacets_are_specular() {
+        let rec = hit(true);
+        let ray_in = Ray {
+            orig: Point3::new(1.0, 0.0, 1.0),
+            dir: Vec3::new(-1.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        let mut sampler = Sampler::new(3);
+        let srec = Conductor::preset(MetalPreset::Silver, 0.0)
+            .scatter(&ray_in, &rec, &mut sampler)
+            .unwrap();
+        assert!(srec.pdf.is_none());
+        let mirrored = Vec3::new(-1.0, 0.0, 1.0).make_unit_vector();
+        assert!((&srec.scattered.dir - &mirrored).length() < 1e-12);
+        // the silver reflects more red than blue
+        assert!(srec.attenuation.x() > srec.attenuation.z());
+        let srec = RoughDielectric::new(1.5, 0.0)
+            .scatter(&ray_in, &rec, &mut sampler)
+            .unwrap();
+        assert!(srec.pdf.is_none());
     }
 }
@@ -0,0 +1,340 @@
+use crate::aabb::Aabb;
+use crate::interval::{Interval, UNIVERSE_INTERVAL};
+use crate::material::ScatterRecord;
+use crate::sampler::Sampler;
+use crate::utils::INFINITY;
+use crate::voxels::VoxelGrid;
+use crate::HitRecord;
+use crate::Hittable;
+use crate::Point3;
+use crate::Ray;
+use crate::Scatter;
+use crate::Vec3;
+use std::sync::Arc;
+
+// participating medium of constant density filling a closed boundary, such as fog or
+// smoke. a ray crossing it travels a random distance before scattering off a particle,
+// the denser the medium the shorter, and passes straight through when the distance is
+// longer than its way through the boundary
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hittable>,
+    neg_inv_density: f64,
+    // phase function, the directions the particles scatter light into
+    phase_function: Arc<dyn Scatter>,
+}
+
+impl ConstantMedium {
+    pub fn new(
+        boundary: Arc<dyn Hittable>,
+        density: f64,
+        phase_function: Arc<dyn Scatter>,
+    ) -> Self {
+        ConstantMedium {
+            boundary,
+            neg_inv_density: -1.0 / density,
+            phase_function,
+        }
+    }
+}
+
+// where the ray within ray_t is inside the boundary, the ray may start inside
+fn inside(boundary: &dyn Hittable, ray: &Ray, ray_t: &Interval) -> Option<Interval> {
+    let mut whole_line = UNIVERSE_INTERVAL;
+    let entry = boundary.hit(ray, &mut whole_line)?;
+    let exit = boundary.hit(ray, &mut Interval::new(entry.t + 0.0001, INFINITY))?;
+    let inside = Interval::new(entry.t.max(ray_t.min).max(0.0), exit.t.min(ray_t.max));
+    (inside.min < inside.max).then_some(inside)
+}
+
+// the hit of a ray scattering off a particle at t
+fn scattering_hit(ray: &Ray, t: f64, phase_function: &Arc<dyn Scatter>) -> HitRecord {
+    let mut rec = HitRecord::new();
+    rec.t = t;
+    rec.point = ray.at(t);
+    // particles have no surface, the normal is arbitrary
+    rec.normal = Vec3::new(1.0, 0.0, 0.0);
+    rec.front_face = true;
+    rec.material = phase_function.clone();
+    rec
+}
+
+impl Hittable for ConstantMedium {
+    fn hit(&self, ray: &Ray, ray_t: &mut Interval) -> Option<HitRecord> {
+        let inside = inside(self.boundary.as_ref(), ray, ray_t)?;
+        let ray_length = ray.dir.length();
+        let distance_inside_boundary = inside.size() * ray_length;
+        let hit_distance = self.neg_inv_density * Sampler::for_ray(ray).random_f64().ln();
+        if hit_distance > distance_inside_boundary {
+            return None;
+        }
+        Some(scattering_hit(
+            ray,
+            inside.min + hit_distance / ray_length,
+            &self.phase_function,
+        ))
+    }
+
+    fn hit_surface(&self, _ray: &Ray, _ray_t: &mut Interval) -> Option<HitRecord> {
+        None
+    }
+
+    // the density is the same everywhere, the light decays exponentially
+    fn transmittance(&self, ray: &Ray, ray_t: &Interval, _sampler: &mut Sampler) -> f64 {
+        match inside(self.boundary.as_ref(), ray, ray_t) {
+            Some(inside) => (inside.size() * ray.dir.length() / self.neg_inv_density).exp(),
+            None => 1.0,
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.boundary.bounding_box()
+    }
+}
+
+// participating medium whose density varies over a voxel grid stretched over the box
+// of its boundary, such as a cloud. at density 1 light is absorbed and scattered at the
+// given rates per unit length. the density is unknown until looked up, so rays sample
+// distances as if the medium were as dense as the grid is at its densest, and treat
+// a collision as real with the ratio of the true density to that (delta tracking)
+pub struct GridMedium {
+    boundary: Arc<dyn Hittable>,
+    grid: Arc<VoxelGrid>,
+    bbox: Aabb,
+    absorption: f64,
+    scattering: f64,
+    // upper bound of the extinction, absorption and scattering together, in the grid
+    majorant: f64,
+    phase_function: Arc<dyn Scatter>,
+    absorbed: Arc<dyn Scatter>,
+}
+
+// what rays absorbed by a medium hit, they go no further
+struct Absorber;
+
+impl Scatter for Absorber {
+    fn scatter(
+        &self,
+        _ray_in: &Ray,
+        _rec: &HitRecord,
+        _sampler: &mut Sampler,
+    ) -> Option<ScatterRecord> {
+        None
+    }
+}
+
+impl GridMedium {
+    pub fn new(
+        boundary: Arc<dyn Hittable>,
+        grid: Arc<VoxelGrid>,
+        absorption: f64,
+        scattering: f64,
+        phase_function: Arc<dyn Scatter>,
+    ) -> Self {
+        let bbox = boundary.bounding_box();
+        GridMedium {
+            boundary,
+            majorant: (absorption + scattering) * grid.max(),
+            grid,
+            bbox,
+            absorption,
+            scattering,
+            phase_function,
+            absorbed: Arc::new(Absorber),
+        }
+    }
+
+    // extinction at p, the rate per unit length at which light is absorbed or scattered
+    fn extinction(&self, p: &Point3) -> f64 {
+        let local = [0, 1, 2].map(|axis| {
+            let interval = self.bbox.axis_interval(axis);
+            (p[axis] - interval.min) / interval.size()
+        });
+        (self.absorption + self.scattering) * self.grid.density(local)
+    }
+
+    // parameter along the ray of the next collision with the densest medium after t
+    fn next_collision(&self, t: f64, ray_length: f64, sampler: &mut Sampler) -> f64 {
+        t - (1.0 - sampler.random_f64()).ln() / (self.majorant * ray_length)
+    }
+}
+
+impl Hittable for GridMedium {
+    fn hit(&self, ray: &Ray, ray_t: &mut Interval) -> Option<HitRecord> {
+        if self.majorant <= 0.0 {
+            return None;
+        }
+        let inside = inside(self.boundary.as_ref(), ray, ray_t)?;
+        let ray_length = ray.dir.length();
+        let mut sampler = Sampler::for_ray(ray);
+        let mut t = inside.min;
+        loop {
+            t = self.next_collision(t, ray_length, &mut sampler);
+            if t >= inside.max {
+                return None;
+            }
+            let extinction = self.extinction(&ray.at(t));
+            if sampler.random_f64() * self.majorant < extinction {
+                // the particle scatters the light or absorbs it
+                let material = if sampler.random_f64() * (self.absorption + self.scattering)
+                    < self.scattering
+                {
+                    &self.phase_function
+                } else {
+                    &self.absorbed
+                };
+                return Some(scattering_hit(ray, t, material));
+            }
+        }
+    }
+
+    fn hit_surface(&self, _ray: &Ray, _ray_t: &mut Interval) -> Option<HitRecord> {
+        None
+    }
+
+    // ratio tracking: at each collision with the densest medium the light keeps the
+    // share that would not have collided with the true one
+    fn transmittance(&self, ray: &Ray, ray_t: &Interval, sampler: &mut Sampler) -> f64 {
+        if self.majorant <= 0.0 {
+            return 1.0;
+        }
+        let Some(inside) = inside(self.boundary.as_ref(), ray, ray_t) else {
+            return 1.0;
+        };
+        let ray_length = ray.dir.length();
+        let mut transmittance = 1.0;
+        let mut t = inside.min;
+        loop {
+            t = self.next_collision(t, ray_length, sampler);
+            if t >= inside.max {
+                return transmittance;
+            }
+            transmittance *= 1.0 - self.extinction(&ray.at(t)) / self.majorant;
+        }
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Isotropic;
+    use crate::quad::Quad;
+    use crate::texture::SolidColor;
+    use crate::{Color, Hittables, Sphere};
+
+    #[test]
+    fn test_transmission_follows_density() {
+        let material = Arc::new(Isotropic::with_texture(Arc::new(SolidColor::new(
+            Color::new(0.5, 0.5, 0.5),
+        ))));
+        let boundary = Arc::new(Sphere::new(Point3::default(), 1.0, material.clone()));
+        let medium = ConstantMedium::new(boundary, 0.5, material);
+        // rays through the center cross 2 units of medium and pass with probability
+        // exp(-0.5 * 2), rays starting at the center cross 1 unit
+        let mut sampler = Sampler::new(5);
+        for (start, length) in [(-5.0, 2.0f64), (0.0, 1.0)] {
+            let rays = 20000;
+            let mut passed = 0;
+            for _ in 0..rays {
+                let ray = Ray {
+                    orig: Point3::new(start, 0.0, 0.0),
+                    dir: Vec3::new(sampler.random_f64_range(0.5, 2.0), 0.0, 0.0),
+                    time: 0.0,
+                };
+                match medium.hit(&ray, &mut Interval::new(0.001, INFINITY)) {
+                    Some(rec) => {
+                        let x = rec.point.x();
+                        assert!(x >= start.max(-1.0) && x <= 1.0, "{}", x);
+                    }
+                    None => passed += 1,
+                }
+            }
+            let expected = (-0.5 * length).exp();
+            let fraction = passed as f64 / rays as f64;
+            assert!(
+                (fraction - expected).abs() < 0.02,
+                "{} {}",
+                fraction,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_grid_tracking() {
+        // a box 2 long along x, clear in its first half and of density 1 in the second,
+        // with the density ramping up in between. rays along x cross an extinction of
+        // 2 over what adds up to 1 unit
+        let material = Arc::new(Isotropic::with_texture(Arc::new(SolidColor::new(
+            Color::new(0.5, 0.5, 0.5),
+        ))));
+        let boundary = Arc::new(Hittables {
+            objects: Quad::make_box(
+                &Point3::new(0.0, 0.0, 0.0),
+                &Point3::new(2.0, 1.0, 1.0),
+                material.clone(),
+            )
+            .into_iter()
+            .map(|quad| Arc::new(quad) as Arc<dyn Hittable>)
+            .collect(),
+        });
+        let grid = VoxelGrid::new([4, 1, 1], vec![0.0, 0.0, 1.0, 1.0]).unwrap();
+        let medium = GridMedium::new(boundary, Arc::new(grid), 0.5, 1.5, material);
+        let expected = (-2.0f64).exp();
+
+        let mut sampler = Sampler::new(3);
+        let rays = 20000;
+        let (mut passed, mut scattered, mut transmittance) = (0, 0, 0.0);
+        for _ in 0..rays {
+            let ray = Ray {
+                orig: Point3::new(-1.0, sampler.random_f64(), 0.3),
+                dir: Vec3::new(1.0, 0.0, 0.0),
+                time: 0.0,
+            };
+            // delta tracking
+            match medium.hit(&ray, &mut Interval::new(0.001, INFINITY)) {
+                Some(rec) => {
+                    // the voxel centers are at 0.25, 0.75, 1.25 and 1.75
+                    assert!(rec.point.x() > 0.75, "{}", rec.point.x());
+                    if rec.material.scatter(&ray, &rec, &mut sampler).is_some() {
+                        scattered += 1;
+                    }
+                }
+                None => passed += 1,
+            }
+            // ratio tracking
+            transmittance +=
+                medium.transmittance(&ray, &Interval::new(0.001, INFINITY), &mut sampler);
+        }
+        let fraction = passed as f64 / rays as f64;
+        assert!(
+            (fraction - expected).abs() < 0.01,
+            "{} {}",
+            fraction,
+            expected
+        );
+        let average = transmittance / rays as f64;
+        assert!(
+            (average - expected).abs() < 0.01,
+            "{} {}",
+            average,
+            expected
+        );
+        // three quarters of the collisions scatter, the rest absorb
+        let share = scattered as f64 / (rays - passed) as f64;
+        assert!((share - 0.75).abs() < 0.02, "{}", share);
+
+        // media are not surfaces
+        let ray = Ray {
+            orig: Point3::new(-1.0, 0.5, 0.5),
+            dir: Vec3::new(1.0, 0.0, 0.0),
+            time: 0.0,
+        };
+        assert!(medium
+            .hit_surface(&ray, &mut Interval::new(0.001, INFINITY))
+            .is_none());
+    }
+}
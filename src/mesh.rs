@@ -0,0 +1,383 @@
+use crate::aabb::Aabb;
+use crate::interval::Interval;
+use crate::sampler::Sampler;
+use crate::triangle;
+use crate::utils::INFINITY;
+use crate::Color;
+use crate::HitRecord;
+use crate::Hittable;
+use crate::Point3;
+use crate::Ray;
+use crate::Scatter;
+use crate::Vec3;
+use std::sync::Arc;
+
+// most faces in a leaf of the bounding volume hierarchy of a mesh
+const LEAF_SIZE: usize = 4;
+
+// vertex attributes of a model, shared by the meshes made from it. faces index into
+// each list separately, as in obj files, or use the indices of their positions for
+// all of them, as in ply and gltf files. the colors are either empty or one per
+// position
+#[derive(Default)]
+pub struct MeshVertices {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>,
+    pub uvs: Vec<(f64, f64)>,
+    pub colors: Vec<Color>,
+}
+
+// a triangle of a mesh, as indices into the vertex attribute lists
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Face {
+    pub positions: [u32; 3],
+    pub normals: Option<[u32; 3]>,
+    pub uvs: Option<[u32; 3]>,
+}
+
+// indices of the normals and uvs of the corners of a face
+type FaceAttributes = (Option<[u32; 3]>, Option<[u32; 3]>);
+
+// node of the bounding volume hierarchy of a mesh, stored flat in a vector. a leaf
+// holds count faces from start, an inner node (count 0) has its left child right
+// after it and its right child at start
+struct MeshNode {
+    bbox: Aabb,
+    start: u32,
+    count: u32,
+}
+
+// triangles with one material, sharing their vertices. the mesh is a single
+// hittable with its own bounding volume hierarchy, which keeps large meshes
+// much smaller than one hittable per triangle
+pub struct Mesh {
+    vertices: Arc<MeshVertices>,
+    // corners of each face, as indices into the positions
+    faces: Vec<[u32; 3]>,
+    // normal and uv indices of each face, empty when the normals and uvs are indexed
+    // like the positions
+    attributes: Vec<FaceAttributes>,
+    material: Arc<dyn Scatter>,
+    nodes: Vec<MeshNode>,
+    // running total of the face areas, for sampling the mesh as a light
+    areas: Vec<f64>,
+}
+
+impl Mesh {
+    // the faces must index into the vertex lists
+    pub fn new(vertices: Arc<MeshVertices>, faces: Vec<Face>, material: Arc<dyn Scatter>) -> Self {
+        let attributes = faces.iter().map(|face| (face.normals, face.uvs)).collect();
+        let faces = faces.into_iter().map(|face| face.positions).collect();
+        Self::with_attributes(vertices, faces, attributes, material)
+    }
+
+    // a mesh whose faces are given by the indices of their corners, which index the
+    // normals and uvs as well when there is one of them per position
+    pub fn indexed(
+        vertices: Arc<MeshVertices>,
+        faces: Vec<[u32; 3]>,
+        material: Arc<dyn Scatter>,
+    ) -> Self {
+        Self::with_attributes(vertices, faces, Vec::new(), material)
+    }
+
+    fn with_attributes(
+        vertices: Arc<MeshVertices>,
+        faces: Vec<[u32; 3]>,
+        attributes: Vec<FaceAttributes>,
+        material: Arc<dyn Scatter>,
+    ) -> Self {
+        let mut mesh = Mesh {
+            vertices,
+            faces,
+            attributes,
+            material,
+            nodes: Vec::new(),
+            areas: Vec::new(),
+        };
+        mesh.build_nodes();
+        let mut total = 0.0;
+        mesh.areas = (0..mesh.faces.len())
+            .map(|i| {
+                total += mesh.face_normal(i).length() / 2.0;
+                total
+            })
+            .collect();
+        mesh
+    }
+
+    #[cfg(test)]
+    pub fn faces(&self) -> Vec<Face> {
+        (0..self.faces.len()).map(|i| self.face(i)).collect()
+    }
+
+    // the indices of face i into each vertex list
+    fn face(&self, i: usize) -> Face {
+        let positions = self.faces[i];
+        if let Some(&(normals, uvs)) = self.attributes.get(i) {
+            return Face {
+                positions,
+                normals,
+                uvs,
+            };
+        }
+        let count = self.vertices.positions.len();
+        Face {
+            positions,
+            normals: (self.vertices.normals.len() == count).then_some(positions),
+            uvs: (self.vertices.uvs.len() == count).then_some(positions),
+        }
+    }
+
+    pub fn material(&self) -> &Arc<dyn Scatter> {
+        &self.material
+    }
+
+    fn corners(&self, i: usize) -> [&Point3; 3] {
+        self.faces[i].map(|index| &self.vertices.positions[index as usize])
+    }
+
+    // (b - a) x (c - a), twice the area long
+    fn face_normal(&self, i: usize) -> Vec3 {
+        let [a, b, c] = self.corners(i);
+        (b - a).cross(&(c - a))
+    }
+
+    fn face_box(&self, i: usize) -> Aabb {
+        let [a, b, c] = self.corners(i);
+        Aabb::enclosing(&Aabb::from_points(a, b), &Aabb::from_points(b, c)).pad_to_minimums()
+    }
+
+    // sort the faces into a hierarchy split at the median centroid along the widest
+    // axis of the centroids
+    fn build_nodes(&mut self) {
+        if self.faces.is_empty() {
+            return;
+        }
+        let mut items: Vec<(Aabb, u32)> = (0..self.faces.len())
+            .map(|i| (self.face_box(i), i as u32))
+            .collect();
+        build_node(&mut self.nodes, &mut items, 0);
+        // the boxes are no longer needed, free them before copying the faces
+        let order: Vec<u32> = items.into_iter().map(|(_, i)| i).collect();
+        self.faces = order.iter().map(|&i| self.faces[i as usize]).collect();
+        if !self.attributes.is_empty() {
+            self.attributes = order.iter().map(|&i| self.attributes[i as usize]).collect();
+        }
+    }
+
+    // index of the closest face the ray hits within ray_t, with the distance along the
+    // ray and the barycentric coordinates of the hit
+    fn closest_face(&self, ray: &Ray, ray_t: &Interval) -> Option<(usize, (f64, f64, f64))> {
+        if self.nodes.is_empty() {
+            return None;
+        }
+        let mut ray_t = *ray_t;
+        // median splits keep the depth, and so the stack, small
+        let mut stack = [0u32; 64];
+        let mut len = 1;
+        let mut closest = None;
+        while len > 0 {
+            len -= 1;
+            let index = stack[len];
+            let node = &self.nodes[index as usize];
+            if !node.bbox.hit(ray, &ray_t) {
+                continue;
+            }
+            if node.count == 0 {
+                stack[len] = node.start;
+                stack[len + 1] = index + 1;
+                len += 2;
+                continue;
+            }
+            let start = node.start as usize;
+            for i in start..start + node.count as usize {
+                if let Some(hit) = triangle::intersect(self.corners(i), ray, &ray_t) {
+                    ray_t.max = hit.0;
+                    closest = Some((i, hit));
+                }
+            }
+        }
+        closest
+    }
+}
+
+// add the node holding items, which start at index start of the faces, and its
+// descendants to nodes, returning the index of the node
+fn build_node(nodes: &mut Vec<MeshNode>, items: &mut [(Aabb, u32)], start: usize) -> usize {
+    let bbox = items.iter().fold(Aabb::default(), |bbox, (item, _)| {
+        Aabb::enclosing(&bbox, item)
+    });
+    let index = nodes.len();
+    nodes.push(MeshNode {
+        bbox,
+        start: start as u32,
+        count: items.len() as u32,
+    });
+    if items.len() <= LEAF_SIZE {
+        return index;
+    }
+    let centroids = items.iter().fold(Aabb::default(), |bbox, (item, _)| {
+        let centroid = item.centroid();
+        Aabb::enclosing(&bbox, &Aabb::from_points(&centroid, &centroid))
+    });
+    let axis = centroids.longest_axis();
+    if centroids.axis_interval(axis).size() <= 0.0 {
+        // all the faces are at the same spot, no split separates them
+        return index;
+    }
+    let mid = items.len() / 2;
+    items.select_nth_unstable_by(mid, |a, b| {
+        a.0.centroid()[axis].total_cmp(&b.0.centroid()[axis])
+    });
+    let (left, right) = items.split_at_mut(mid);
+    build_node(nodes, left, start);
+    let right_index = build_node(nodes, right, start + mid);
+    nodes[index].start = right_index as u32;
+    nodes[index].count = 0;
+    index
+}
+
+impl Hittable for Mesh {
+    fn hit(&self, ray: &Ray, ray_t: &mut Interval) -> Option<HitRecord> {
+        let (i, hit) = self.closest_face(ray, ray_t)?;
+        let face = self.face(i);
+        let (_, beta, gamma) = hit;
+        let normals = face
+            .normals
+            .map(|indices| indices.map(|index| &self.vertices.normals[index as usize]));
+        let uvs = face
+            .uvs
+            .map(|indices| indices.map(|index| self.vertices.uvs[index as usize]));
+        let mut hit_record = triangle::hit_record(
+            ray,
+            hit,
+            &self.face_normal(i).make_unit_vector(),
+            normals,
+            uvs,
+            &self.material,
+        );
+        if !self.vertices.colors.is_empty() {
+            let [ca, cb, cc] = face
+                .positions
+                .map(|index| &self.vertices.colors[index as usize]);
+            hit_record.vertex_color = Some((1.0 - beta - gamma) * ca + beta * cb + gamma * cc);
+        }
+        Some(hit_record)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.nodes.first().map_or(Aabb::default(), |node| node.bbox)
+    }
+
+    // a direction hits several faces of a mesh that is not convex, the density is the
+    // sum of the densities of choosing any of the points it passes through
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        let Some(&total_area) = self.areas.last() else {
+            return 0.0;
+        };
+        let ray = Ray {
+            orig: origin.clone(),
+            dir: direction.clone(),
+            time,
+        };
+        let mut pdf = 0.0;
+        let mut t_min = 0.001;
+        while let Some((i, (t, _, _))) = self.closest_face(&ray, &Interval::new(t_min, INFINITY)) {
+            let distance_squared = t * t * direction.length_squared();
+            let normal = self.face_normal(i);
+            let cosine = (direction.dot(&normal) / (direction.length() * normal.length())).abs();
+            pdf += distance_squared / (cosine * total_area);
+            t_min = t * (1.0 + 1e-9) + 1e-9;
+        }
+        pdf
+    }
+
+    // direction towards a uniformly chosen point of the mesh, faces are picked in
+    // proportion to their area
+    fn random(&self, origin: &Point3, _time: f64, sampler: &mut Sampler) -> Vec3 {
+        let Some(&total_area) = self.areas.last() else {
+            return Vec3::new(1.0, 0.0, 0.0);
+        };
+        let target = sampler.random_f64() * total_area;
+        let i = self
+            .areas
+            .partition_point(|&area| area <= target)
+            .min(self.faces.len() - 1);
+        &triangle::random_point(self.corners(i), sampler) - origin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::triangle::Triangle;
+    use crate::{Hittables, Lambertian};
+
+    // a bumpy grid of n by n squares, each split into two triangles
+    fn grid(n: u32) -> MeshVertices {
+        let mut vertices = MeshVertices::default();
+        for i in 0..=n {
+            for j in 0..=n {
+                let height = ((i * 7 + j * 3) % 5) as f64 * 0.1;
+                vertices
+                    .positions
+                    .push(Point3::new(i as f64, height, j as f64));
+            }
+        }
+        vertices
+    }
+
+    fn grid_faces(n: u32) -> Vec<Face> {
+        let index = |i: u32, j: u32| i * (n + 1) + j;
+        let mut faces = Vec::new();
+        for i in 0..n {
+            for j in 0..n {
+                for positions in [
+                    [index(i, j), index(i, j + 1), index(i + 1, j)],
+                    [index(i + 1, j), index(i, j + 1), index(i + 1, j + 1)],
+                ] {
+                    faces.push(Face {
+                        positions,
+                        normals: None,
+                        uvs: None,
+                    });
+                }
+            }
+        }
+        faces
+    }
+
+    #[test]
+    fn test_hit_matches_triangles() {
+        let n = 12;
+        let vertices = Arc::new(grid(n));
+        let faces = grid_faces(n);
+        let material: Arc<dyn Scatter> = Arc::new(Lambertian::default());
+        let mut triangles = Hittables::default();
+        for face in faces.iter() {
+            let corners = face
+                .positions
+                .map(|index| vertices.positions[index as usize].clone());
+            triangles.add(Arc::new(Triangle::new(corners, material.clone())));
+        }
+        let mesh = Mesh::new(vertices, faces, material);
+        assert_eq!(mesh.bounding_box(), triangles.bounding_box());
+
+        let mut sampler = Sampler::new(5);
+        for _ in 0..500 {
+            let ray = Ray {
+                orig: Point3::new(
+                    sampler.random_f64_range(-2.0, 14.0),
+                    3.0,
+                    sampler.random_f64_range(-2.0, 14.0),
+                ),
+                dir: Vec3::random_unit_vec3(&mut sampler),
+                time: 0.0,
+            };
+            let expected = triangles.hit(&ray, &mut Interval::new(0.001, INFINITY));
+            let actual = mesh.hit(&ray, &mut Interval::new(0.001, INFINITY));
+            assert_eq!(expected.map(|rec| rec.t), actual.map(|rec| rec.t));
+        }
+    }
+}
@@ -0,0 +1,273 @@
+use crate::utils::PI;
+use crate::Color;
+use crate::Vec3;
+use std::str::FromStr;
+
+// below this alpha a surface is treated as perfectly smooth, the distribution of its
+// normals is too sharp to sample or evaluate
+const SMOOTH_ALPHA: f64 = 1e-3;
+
+// ggx (trowbridge-reitz) distribution of the normals of the microfacets making up a
+// rough surface, with smith shadowing between them. directions are given in the frame
+// of the surface, with the macro normal along z
+#[derive(Debug, Clone, Copy)]
+pub struct Ggx {
+    alpha: f64,
+}
+
+impl Ggx {
+    // perceptual roughness from 0 (smooth) to 1, squared into the width of the
+    // distribution so that roughness changes the look evenly
+    pub fn from_roughness(roughness: f64) -> Self {
+        Ggx {
+            alpha: (roughness * roughness).max(SMOOTH_ALPHA),
+        }
+    }
+
+    pub fn is_smooth(&self) -> bool {
+        self.alpha <= SMOOTH_ALPHA
+    }
+
+    // density of microfacet normals h per unit solid angle, times the cosine of h
+    // integrates to 1 over the hemisphere
+    pub fn d(&self, h: &Vec3) -> f64 {
+        if h.z() <= 0.0 {
+            return 0.0;
+        }
+        let alpha2 = self.alpha * self.alpha;
+        let cos2 = h.z() * h.z();
+        let denom = cos2 * (alpha2 - 1.0) + 1.0;
+        alpha2 / (PI * denom * denom)
+    }
+
+    // smith's auxiliary function, the masked share of microfacets seen from w
+    fn lambda(&self, w: &Vec3) -> f64 {
+        let cos2 = w.z() * w.z();
+        if cos2 == 0.0 {
+            return f64::INFINITY;
+        }
+        let tan2 = (1.0 - cos2).max(0.0) / cos2;
+        ((1.0 + self.alpha * self.alpha * tan2).sqrt() - 1.0) / 2.0
+    }
+
+    // share of the microfacets seen from w that are not hidden by others
+    pub fn g1(&self, w: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // share of the microfacets visible from both directions, height correlated
+    pub fn g(&self, wo: &Vec3, wi: &Vec3) -> f64 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // density of the normals visible from wo, which sample_visible_normal draws from
+    pub fn visible_normal_pdf(&self, wo: &Vec3, h: &Vec3) -> f64 {
+        if wo.z() <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * wo.dot(h).max(0.0) * self.d(h) / wo.z()
+    }
+
+    // microfacet normal seen from wo, drawn from the distribution of visible normals:
+    // the hemisphere is stretched to the unit roughness, where the projected area of
+    // the microfacets is sampled directly, and squeezed back (heitz 2018)
+    pub fn sample_visible_normal(&self, wo: &Vec3, u1: f64, u2: f64) -> Vec3 {
+        let vh = Vec3::new(self.alpha * wo.x(), self.alpha * wo.y(), wo.z()).make_unit_vector();
+        let lensq = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1 = if lensq > 0.0 {
+            Vec3::new(-vh.y(), vh.x(), 0.0) / lensq.sqrt()
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let t2 = vh.cross(&t1);
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+        let nh = p1 * &t1 + p2 * &t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * &vh;
+        Vec3::new(self.alpha * nh.x(), self.alpha * nh.y(), nh.z().max(1e-6)).make_unit_vector()
+    }
+}
+
+// fraction of light reflected by the boundary to a dielectric, for light arriving at
+// cos_i to the normal and eta the index of refraction of the far side over that of
+// the near side. all of it when the light cannot get through
+pub fn fresnel_dielectric(cos_i: f64, eta: f64) -> f64 {
+    let cos_i = cos_i.clamp(0.0, 1.0);
+    let sin2_t = (1.0 - cos_i * cos_i) / (eta * eta);
+    if sin2_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_t = (1.0 - sin2_t).sqrt();
+    let parallel = (eta * cos_i - cos_t) / (eta * cos_i + cos_t);
+    let perpendicular = (cos_i - eta * cos_t) / (cos_i + eta * cos_t);
+    (parallel * parallel + perpendicular * perpendicular) / 2.0
+}
+
+// fraction of light reflected by a conductor with the complex index of refraction
+// eta + i k, for light arriving at cos_i to the normal
+pub fn fresnel_conductor(cos_i: f64, eta: f64, k: f64) -> f64 {
+    let cos2 = cos_i.clamp(0.0, 1.0).powi(2);
+    let sin2 = 1.0 - cos2;
+    let t0 = eta * eta - k * k - sin2;
+    let a2_plus_b2 = (t0 * t0 + 4.0 * eta * eta * k * k).sqrt();
+    let t1 = a2_plus_b2 + cos2;
+    let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+    let t2 = 2.0 * cos_i.clamp(0.0, 1.0) * a;
+    let rs = (t1 - t2) / (t1 + t2);
+    let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+    let t4 = t2 * sin2;
+    let rp = rs * (t3 - t4) / (t3 + t4);
+    (rp + rs) / 2.0
+}
+
+// fresnel_conductor for each of the red, green and blue indices
+pub fn fresnel_conductor_rgb(cos_i: f64, eta: &Color, k: &Color) -> Color {
+    Color::new(
+        fresnel_conductor(cos_i, eta.x(), k.x()),
+        fresnel_conductor(cos_i, eta.y(), k.y()),
+        fresnel_conductor(cos_i, eta.z(), k.z()),
+    )
+}
+
+// schlick's approximation of the fresnel reflectance, blending from f0 at normal
+// incidence to white at grazing angles
+pub fn fresnel_schlick(cos_i: f64, f0: &Color) -> Color {
+    let weight = schlick_weight(cos_i);
+    f0 * (1.0 - weight) + Color::new(weight, weight, weight)
+}
+
+// (1 - cos)^5, how far towards grazing the angle is for schlick's approximation
+pub fn schlick_weight(cos: f64) -> f64 {
+    (1.0 - cos.clamp(0.0, 1.0)).powi(5)
+}
+
+// common metals, with their complex indices of refraction at red, green and blue
+// wavelengths
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MetalPreset {
+    Gold,
+    Copper,
+    Aluminium,
+    Silver,
+}
+
+impl MetalPreset {
+    // eta and k
+    pub fn ior(&self) -> (Color, Color) {
+        match self {
+            MetalPreset::Gold => (
+                Color::new(0.143, 0.374, 1.442),
+                Color::new(3.983, 2.385, 1.603),
+            ),
+            MetalPreset::Copper => (
+                Color::new(0.200, 0.924, 1.102),
+                Color::new(3.912, 2.452, 2.142),
+            ),
+            MetalPreset::Aluminium => (
+                Color::new(1.657, 0.880, 0.521),
+                Color::new(9.224, 6.270, 4.837),
+            ),
+            MetalPreset::Silver => (
+                Color::new(0.155, 0.117, 0.138),
+                Color::new(4.828, 3.122, 2.147),
+            ),
+        }
+    }
+}
+
+impl FromStr for MetalPreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "gold" => Ok(MetalPreset::Gold),
+            "copper" => Ok(MetalPreset::Copper),
+            "aluminium" | "aluminum" => Ok(MetalPreset::Aluminium),
+            "silver" => Ok(MetalPreset::Silver),
+            _ => Err(format!(
+                "unknown metal '{}', expected gold, copper, aluminium or silver",
+                s
+            )),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::Sampler;
+
+    // integral of f over the directions around the z axis, by the midpoint rule
+    fn integrate(f: impl Fn(&Vec3) -> f64, theta_max: f64) -> f64 {
+        let (n_theta, n_phi) = (400, 200);
+        let (d_theta, d_phi) = (theta_max / n_theta as f64, 2.0 * PI / n_phi as f64);
+        let mut sum = 0.0;
+        for i in 0..n_theta {
+            let theta = (i as f64 + 0.5) * d_theta;
+            for j in 0..n_phi {
+                let phi = (j as f64 + 0.5) * d_phi;
+                let w = Vec3::new(
+                    theta.sin() * phi.cos(),
+                    theta.sin() * phi.sin(),
+                    theta.cos(),
+                );
+                sum += f(&w) * theta.sin() * d_theta * d_phi;
+            }
+        }
+        sum
+    }
+
+    #[test]
+    fn test_distribution_is_normalized() {
+        let wo = Vec3::new(0.6, 0.0, 0.8);
+        for roughness in [0.3, 0.7, 1.0] {
+            let ggx = Ggx::from_roughness(roughness);
+            let projected = integrate(|h| ggx.d(h) * h.z(), PI / 2.0);
+            assert!((projected - 1.0).abs() < 1e-3, "{}", projected);
+            let visible = integrate(|h| ggx.visible_normal_pdf(&wo, h), PI / 2.0);
+            assert!((visible - 1.0).abs() < 1e-3, "{}", visible);
+        }
+    }
+
+    #[test]
+    fn test_visible_normal_sampling() {
+        let mut sampler = Sampler::new(6);
+        let wo = Vec3::new(-0.5, 0.3, 0.7).make_unit_vector();
+        for roughness in [0.2, 0.6] {
+            let ggx = Ggx::from_roughness(roughness);
+            let n = 100000;
+            let samples: Vec<Vec3> = (0..n)
+                .map(|_| ggx.sample_visible_normal(&wo, sampler.random_f64(), sampler.random_f64()))
+                .collect();
+            assert!(samples.iter().all(|h| h.z() > 0.0 && wo.dot(h) >= -1e-9));
+            // the share within 20 degrees of the macro normal matches the density
+            let theta = 20f64.to_radians();
+            let expected = integrate(|h| ggx.visible_normal_pdf(&wo, h), theta);
+            let share = samples.iter().filter(|h| h.z() > theta.cos()).count() as f64 / n as f64;
+            assert!((share - expected).abs() < 0.01, "{} {}", share, expected);
+        }
+    }
+
+    #[test]
+    fn test_fresnel() {
+        // at normal incidence ((n - 1)^2 + k^2) / ((n + 1)^2 + k^2)
+        let (eta, k) = (0.2, 3.9);
+        let expected = ((eta - 1.0) * (eta - 1.0) + k * k) / ((eta + 1.0) * (eta + 1.0) + k * k);
+        assert!((fresnel_conductor(1.0, eta, k) - expected).abs() < 1e-12);
+        // without absorption a conductor is a dielectric
+        for cos_i in [0.1, 0.5, 0.9] {
+            let a = fresnel_conductor(cos_i, 1.5, 0.0);
+            let b = fresnel_dielectric(cos_i, 1.5);
+            assert!((a - b).abs() < 1e-12, "{} {}", a, b);
+        }
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-12);
+        // total internal reflection leaving glass beyond the critical angle
+        assert_eq!(fresnel_dielectric(0.5, 1.0 / 1.5), 1.0);
+        // grazing light is reflected whatever the surface
+        assert!(fresnel_conductor(0.0, 1.657, 9.224) > 0.999);
+        assert_eq!("Aluminum".parse(), Ok(MetalPreset::Aluminium));
+        assert!("brass".parse::<MetalPreset>().is_err());
+    }
+}
@@ -0,0 +1,481 @@
+use crate::material::DiffuseLight;
+use crate::mesh::{Face, Mesh, MeshVertices};
+use crate::spectrum::Ior;
+use crate::traits::Scatter;
+use crate::Result;
+use crate::{Color, Dielectric, Lambertian, Metal, Point3, Vec3};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+// wavefront obj reader. the faces are split into one mesh per material, all sharing
+// the vertices of the file. polygons are split into triangle fans, points, lines,
+// groups and smoothing groups are ignored
+
+type Materials = HashMap<String, Arc<dyn Scatter>>;
+
+// read the obj file at path and the mtl files it refers to. faces before any usemtl
+// get default_material. with smooth_normals, faces without normals get vertex
+// normals averaged over the faces around each vertex
+pub fn load_obj(
+    path: &Path,
+    default_material: Arc<dyn Scatter>,
+    smooth_normals: bool,
+) -> Result<Vec<Mesh>> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("cannot read obj file {}: {}", path.display(), err))?;
+    let dir = path.parent().unwrap_or(Path::new(""));
+    let mut load_mtl = |file_name: &str| -> Result<Materials> {
+        let mtl_path = dir.join(file_name);
+        let text = fs::read_to_string(&mtl_path)
+            .map_err(|err| format!("cannot read mtl file {}: {}", mtl_path.display(), err))?;
+        parse_mtl(&text, &mtl_path.display().to_string())
+    };
+    parse_obj(
+        &text,
+        &path.display().to_string(),
+        &mut load_mtl,
+        default_material,
+        smooth_normals,
+    )
+}
+
+// the statement of a line without its comment, split into words
+fn words(line: &str) -> Vec<&str> {
+    let line = line.split('#').next().unwrap_or("");
+    line.split_whitespace().collect()
+}
+
+fn parse_numbers<const N: usize>(args: &[&str], defaults: [f64; N]) -> Option<[f64; N]> {
+    if args.is_empty() || args.len() > N {
+        return None;
+    }
+    let mut numbers = defaults;
+    for (number, arg) in numbers.iter_mut().zip(args) {
+        *number = arg.parse().ok().filter(|x: &f64| x.is_finite())?;
+    }
+    Some(numbers)
+}
+
+// 0-based index of a 1-based or negative (counted back from the end) obj index
+fn parse_index(s: &str, len: usize) -> Option<u32> {
+    let index: i64 = s.parse().ok()?;
+    let index = match index {
+        1.. => index - 1,
+        ..=-1 => len as i64 + index,
+        0 => return None,
+    };
+    (0..len as i64).contains(&index).then_some(index as u32)
+}
+
+// a face corner v, v/vt, v//vn or v/vt/vn, as indices into the vertex lists
+fn parse_corner(
+    s: &str,
+    vertices: &MeshVertices,
+) -> std::result::Result<(u32, Option<u32>, Option<u32>), String> {
+    let mut parts = s.split('/');
+    let bad_index = |kind: &str, index: &str| format!("bad {} index '{}'", kind, index);
+    let position = parts.next().unwrap_or("");
+    let position = parse_index(position, vertices.positions.len())
+        .ok_or_else(|| bad_index("vertex", position))?;
+    let uv = match parts.next() {
+        None | Some("") => None,
+        Some(uv) => {
+            Some(parse_index(uv, vertices.uvs.len()).ok_or_else(|| bad_index("texture", uv))?)
+        }
+    };
+    let normal = match parts.next() {
+        None | Some("") => None,
+        Some(normal) => Some(
+            parse_index(normal, vertices.normals.len())
+                .ok_or_else(|| bad_index("normal", normal))?,
+        ),
+    };
+    if parts.next().is_some() {
+        return Err(format!("bad face vertex '{}'", s));
+    }
+    Ok((position, uv, normal))
+}
+
+fn parse_obj(
+    text: &str,
+    name: &str,
+    load_mtl: &mut dyn FnMut(&str) -> Result<Materials>,
+    default_material: Arc<dyn Scatter>,
+    smooth_normals: bool,
+) -> Result<Vec<Mesh>> {
+    let mut vertices = MeshVertices::default();
+    let mut materials = Materials::new();
+    // faces of each material, in the order the materials are first used
+    let mut groups: Vec<(Arc<dyn Scatter>, Vec<Face>)> = Vec::new();
+    let mut group_of: HashMap<String, usize> = HashMap::new();
+    let mut current: Option<usize> = None;
+
+    for (i, line) in text.lines().enumerate() {
+        let error = |message: String| format!("{}:{}: {}", name, i + 1, message);
+        let words = words(line);
+        let Some((&keyword, args)) = words.split_first() else {
+            continue;
+        };
+        match keyword {
+            "v" => {
+                // vertex colors after the position are ignored
+                let [x, y, z] = args
+                    .get(..3)
+                    .and_then(|xyz| parse_numbers(xyz, [0.0; 3]))
+                    .ok_or_else(|| error(format!("bad vertex '{}'", line.trim())))?;
+                vertices.positions.push(Point3::new(x, y, z));
+            }
+            "vt" => {
+                let [u, v, _] = parse_numbers(args, [0.0; 3])
+                    .ok_or_else(|| error(format!("bad texture coordinate '{}'", line.trim())))?;
+                vertices.uvs.push((u, v));
+            }
+            "vn" => {
+                let [x, y, z] = (args.len() == 3)
+                    .then(|| parse_numbers(args, [0.0; 3]))
+                    .flatten()
+                    .ok_or_else(|| error(format!("bad normal '{}'", line.trim())))?;
+                let normal = Vec3::new(x, y, z);
+                // a zero normal, or one too short or long to measure, has no direction
+                let length = normal.length();
+                if !(length.is_finite() && length > 0.0) {
+                    return Err(error(format!("normal '{}' has no direction", line.trim())).into());
+                }
+                vertices.normals.push(normal / length);
+            }
+            "f" => {
+                if args.len() < 3 {
+                    return Err(error("face with fewer than 3 vertices".to_string()).into());
+                }
+                let corners = args
+                    .iter()
+                    .map(|arg| parse_corner(arg, &vertices))
+                    .collect::<std::result::Result<Vec<_>, _>>()
+                    .map_err(error)?;
+                let group = match current {
+                    Some(group) => group,
+                    None => {
+                        groups.push((default_material.clone(), Vec::new()));
+                        let group = groups.len() - 1;
+                        current = Some(group);
+                        group
+                    }
+                };
+                // attributes only count when every corner has them
+                let all_uvs = corners.iter().all(|corner| corner.1.is_some());
+                let all_normals = corners.iter().all(|corner| corner.2.is_some());
+                for k in 1..corners.len() - 1 {
+                    let fan = [corners[0], corners[k], corners[k + 1]];
+                    groups[group].1.push(Face {
+                        positions: fan.map(|corner| corner.0),
+                        uvs: all_uvs.then(|| fan.map(|corner| corner.1.unwrap_or(0))),
+                        normals: all_normals.then(|| fan.map(|corner| corner.2.unwrap_or(0))),
+                    });
+                }
+            }
+            "mtllib" => {
+                if args.is_empty() {
+                    return Err(error("mtllib without a file name".to_string()).into());
+                }
+                for file_name in args {
+                    let loaded = load_mtl(file_name).map_err(|err| error(err.to_string()))?;
+                    materials.extend(loaded);
+                }
+            }
+            "usemtl" => {
+                let [material_name] = args else {
+                    return Err(error("usemtl needs one material name".to_string()).into());
+                };
+                let material = materials
+                    .get(*material_name)
+                    .ok_or_else(|| error(format!("unknown material '{}'", material_name)))?;
+                let group = *group_of
+                    .entry(material_name.to_string())
+                    .or_insert_with(|| {
+                        groups.push((material.clone(), Vec::new()));
+                        groups.len() - 1
+                    });
+                current = Some(group);
+            }
+            // objects, groups, smoothing groups, points, lines and free-form geometry
+            _ => {}
+        }
+    }
+
+    if smooth_normals {
+        add_smooth_normals(
+            &mut vertices,
+            groups.iter_mut().flat_map(|group| &mut group.1),
+        );
+    }
+    let vertices = Arc::new(vertices);
+    Ok(groups
+        .into_iter()
+        .filter(|(_, faces)| !faces.is_empty())
+        .map(|(material, faces)| Mesh::new(vertices.clone(), faces, material))
+        .collect())
+}
+
+// give the faces without normals vertex normals, the area weighted average of the
+// normals of the faces without normals around the vertex
+fn add_smooth_normals<'a>(vertices: &mut MeshVertices, faces: impl Iterator<Item = &'a mut Face>) {
+    let faces: Vec<&mut Face> = faces.filter(|face| face.normals.is_none()).collect();
+    if faces.is_empty() {
+        return;
+    }
+    let mut sums = vec![Vec3::default(); vertices.positions.len()];
+    for face in faces.iter() {
+        let [a, b, c] = face
+            .positions
+            .map(|index| &vertices.positions[index as usize]);
+        // the cross product is as long as twice the area of the face
+        let normal = (b - a).cross(&(c - a));
+        for index in face.positions {
+            sums[index as usize] += &normal;
+        }
+    }
+    let first = vertices.normals.len() as u32;
+    // a vertex only on faces of no area keeps a zero normal, adding nothing to the
+    // normals interpolated across the faces around it
+    vertices.normals.extend(sums.iter().map(|sum| {
+        if sum.near_zero() {
+            sum.clone()
+        } else {
+            sum.make_unit_vector()
+        }
+    }));
+    for face in faces {
+        face.normals = Some(face.positions.map(|index| first + index));
+    }
+}
+
+// a material of an mtl file. the illumination model and the colors decide which
+// material of the renderer it becomes
+struct MtlDesc {
+    diffuse: Color,
+    specular: Color,
+    emission: Color,
+    shininess: f64,
+    ior: f64,
+    dissolve: f64,
+    // transmission filter, the color of the light through glass
+    filter: Color,
+    illum: u32,
+}
+
+impl Default for MtlDesc {
+    fn default() -> Self {
+        MtlDesc {
+            diffuse: Color::new(0.8, 0.8, 0.8),
+            specular: Color::default(),
+            emission: Color::default(),
+            shininess: 0.0,
+            ior: 1.5,
+            dissolve: 1.0,
+            filter: Color::new(1.0, 1.0, 1.0),
+            illum: 2,
+        }
+    }
+}
+
+impl MtlDesc {
+    fn build(&self) -> Arc<dyn Scatter> {
+        if !self.emission.near_zero() {
+            return Arc::new(DiffuseLight::new(self.emission.clone()));
+        }
+        // models 4, 6, 7 and 9 are glass, as is anything see-through
+        if self.dissolve < 1.0 || matches!(self.illum, 4 | 6 | 7 | 9) {
+            let absorption = Color::default();
+            return Arc::new(Dielectric::tinted(
+                Ior::Constant(self.ior),
+                absorption,
+                self.filter.clone(),
+            ));
+        }
+        // models 3 and 5 are mirrors, a material with only a specular color is metal
+        if matches!(self.illum, 3 | 5) || (self.diffuse.near_zero() && !self.specular.near_zero()) {
+            // the phong exponent turned into a roughness, sharper highlights are less fuzzy
+            let fuzz = (2.0 / (self.shininess + 2.0)).sqrt().min(1.0);
+            return Arc::new(Metal::new(self.specular.clone(), fuzz));
+        }
+        Arc::new(Lambertian::new(self.diffuse.clone()))
+    }
+}
+
+fn parse_mtl(text: &str, name: &str) -> Result<Materials> {
+    let mut descs: Vec<(String, MtlDesc)> = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        let error = |message: String| format!("{}:{}: {}", name, i + 1, message);
+        let words = words(line);
+        let Some((&keyword, args)) = words.split_first() else {
+            continue;
+        };
+        if keyword == "newmtl" {
+            let [material_name] = args else {
+                return Err(error("newmtl needs one material name".to_string()).into());
+            };
+            descs.push((material_name.to_string(), MtlDesc::default()));
+            continue;
+        }
+        let Some((_, desc)) = descs.last_mut() else {
+            return Err(error(format!("'{}' before any newmtl", keyword)).into());
+        };
+        let color = || {
+            // a single value is gray
+            match args.len() {
+                1 => parse_numbers(args, [0.0]).map(|[x]| Color::new(x, x, x)),
+                3 => parse_numbers(args, [0.0; 3]).map(|[r, g, b]| Color::new(r, g, b)),
+                _ => None,
+            }
+            .ok_or_else(|| error(format!("bad color '{}'", line.trim())))
+        };
+        let number = || {
+            parse_numbers(args, [0.0])
+                .filter(|_| args.len() == 1)
+                .map(|[x]| x)
+                .ok_or_else(|| error(format!("bad number '{}'", line.trim())))
+        };
+        match keyword {
+            "Kd" => desc.diffuse = color()?,
+            "Ks" => desc.specular = color()?,
+            "Ke" => desc.emission = color()?,
+            "Ns" => desc.shininess = number()?,
+            "Ni" => desc.ior = number()?,
+            "d" => desc.dissolve = number()?,
+            "Tr" => desc.dissolve = 1.0 - number()?,
+            "Tf" => desc.filter = color()?,
+            "illum" => {
+                desc.illum = args
+                    .first()
+                    .and_then(|arg| arg.parse().ok())
+                    .filter(|_| args.len() == 1)
+                    .ok_or_else(|| error(format!("bad illumination model '{}'", line.trim())))?;
+            }
+            // ambient color, texture maps and the rest have no counterpart
+            _ => {}
+        }
+    }
+    Ok(descs
+        .into_iter()
+        .map(|(material_name, desc)| (material_name, desc.build()))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::INFINITY;
+    use crate::{Hittable, Interval, Ray};
+
+    const MTL: &str = "
+# two materials
+newmtl red
+Kd 0.8 0.1 0.1
+
+newmtl lamp
+Ke 4 4 4
+";
+
+    const OBJ: &str = "
+mtllib box.mtl
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+vt 1 0
+vt 1 1
+vt 0 1
+vn 0 0 1
+
+# a square split into two triangles, then a lamp triangle above it
+usemtl red
+f 1/1/1 2/2/1 3/3/1 4/4/1
+v 0 0 2
+v 1 0 2
+v 0 1 2
+usemtl lamp
+f -3 -1 -2
+";
+
+    fn parse(text: &str, smooth_normals: bool) -> Result<Vec<Mesh>> {
+        let mut load_mtl = |file_name: &str| -> Result<Materials> {
+            assert_eq!(file_name, "box.mtl");
+            parse_mtl(MTL, "box.mtl")
+        };
+        parse_obj(
+            text,
+            "box.obj",
+            &mut load_mtl,
+            Arc::new(Lambertian::default()),
+            smooth_normals,
+        )
+    }
+
+    #[test]
+    fn test_parse_obj() {
+        let meshes = parse(OBJ, false).unwrap();
+        assert_eq!(meshes.len(), 2);
+        assert_eq!(meshes[0].faces().len(), 2);
+        assert!(!meshes[0].material().is_emissive());
+        assert!(meshes[1].material().is_emissive());
+        // the lamp corners, counted back from the last vertex
+        assert_eq!(meshes[1].faces()[0].positions, [4, 6, 5]);
+
+        let ray = Ray {
+            orig: Point3::new(0.75, 0.25, 1.0),
+            dir: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        let rec = meshes[0]
+            .hit(&ray, &mut Interval::new(0.001, INFINITY))
+            .unwrap();
+        assert_eq!(rec.t, 1.0);
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert!((rec.u - 0.75).abs() < 1e-12 && (rec.v - 0.25).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_smooth_normals() {
+        let text = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 1\nf 1 3 2\nf 1 2 4\n";
+        let meshes = parse(text, true).unwrap();
+        let faces = meshes[0].faces();
+        assert!(faces.iter().all(|face| face.normals.is_some()));
+        assert!(parse(text, false).unwrap()[0].faces()[0].normals.is_none());
+    }
+
+    #[test]
+    fn test_errors_report_line() {
+        for (text, expected) in [
+            ("v 0 0\n", "box.obj:1: bad vertex 'v 0 0'"),
+            (
+                "v 0 0 0\nv 1 0 0\nf 1 2\n",
+                "box.obj:3: face with fewer than 3 vertices",
+            ),
+            ("v 0 0 0\n\nf 1 2 3\n", "box.obj:3: bad vertex index '2'"),
+            (
+                "mtllib box.mtl\nusemtl gold\n",
+                "box.obj:2: unknown material 'gold'",
+            ),
+            ("v 0 0 0\nf 1/x 1 1\n", "box.obj:2: bad texture index 'x'"),
+            (
+                "vn 0 0 1\nvn 0 0 0\n",
+                "box.obj:2: normal 'vn 0 0 0' has no direction",
+            ),
+            (
+                "vn 1e-200 0 0\n",
+                "box.obj:1: normal 'vn 1e-200 0 0' has no direction",
+            ),
+        ] {
+            let err = parse(text, false).err().unwrap().to_string();
+            assert_eq!(err, expected);
+        }
+        let err = parse_mtl("newmtl a\nKd 0.5 x 0.5\n", "a.mtl")
+            .err()
+            .unwrap()
+            .to_string();
+        assert_eq!(err, "a.mtl:2: bad color 'Kd 0.5 x 0.5'");
+    }
+}
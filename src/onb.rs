@@ -0,0 +1,133 @@
+use crate::sampler::Sampler;
+use crate::utils::PI;
+use crate::Vec3;
+
+// orthonormal basis with w along a given direction, used to turn directions sampled
+// around the z axis into directions around a normal or towards a light
+#[derive(Clone)]
+pub struct Onb {
+    pub u: Vec3,
+    pub v: Vec3,
+    pub w: Vec3,
+}
+
+impl Onb {
+    pub fn new(n: &Vec3) -> Self {
+        let w = n.make_unit_vector();
+        let a = if w.x().abs() > 0.9 {
+            Vec3::new(0.0, 1.0, 0.0)
+        } else {
+            Vec3::new(1.0, 0.0, 0.0)
+        };
+        let v = w.cross(&a).make_unit_vector();
+        let u = w.cross(&v);
+        Onb { u, v, w }
+    }
+
+    // the vector with the given coordinates in this basis
+    pub fn transform(&self, a: &Vec3) -> Vec3 {
+        a.x() * &self.u + a.y() * &self.v + a.z() * &self.w
+    }
+
+    // the coordinates of a in this basis, the inverse of transform
+    pub fn to_local(&self, a: &Vec3) -> Vec3 {
+        Vec3::new(a.dot(&self.u), a.dot(&self.v), a.dot(&self.w))
+    }
+}
+
+// direction around the z axis with a density proportional to its cosine with the axis
+pub fn random_cosine_direction(sampler: &mut Sampler) -> Vec3 {
+    let r1 = sampler.random_f64();
+    let r2 = sampler.random_f64();
+    let phi = 2.0 * PI * r1;
+    let x = phi.cos() * r2.sqrt();
+    let y = phi.sin() * r2.sqrt();
+    let z = (1.0 - r2).sqrt();
+    Vec3::new(x, y, z)
+}
+
+// direction around the z axis towards a sphere of the given radius, whose center is at
+// distance_squared along the axis, uniform over the cone of directions hitting the sphere
+pub fn random_to_sphere(radius: f64, distance_squared: f64, sampler: &mut Sampler) -> Vec3 {
+    let r1 = sampler.random_f64();
+    let r2 = sampler.random_f64();
+    let cos_theta_max = (1.0 - radius * radius / distance_squared).sqrt();
+    let z = 1.0 + r2 * (cos_theta_max - 1.0);
+    let phi = 2.0 * PI * r1;
+    let sin_theta = (1.0 - z * z).sqrt();
+    Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z)
+}
+
+// henyey-greenstein phase function of the cosine between the directions light travels
+// in before and after scattering, per unit solid angle. g between -1 and 1 is the
+// average of that cosine: forward scattering above 0, backward below
+pub fn henyey_greenstein(g: f64, cosine: f64) -> f64 {
+    let denom = 1.0 + g * g - 2.0 * g * cosine;
+    (1.0 - g * g) / (4.0 * PI * denom * denom.sqrt())
+}
+
+// direction around the z axis with the density of the henyey-greenstein phase function
+// of its cosine with the axis
+pub fn random_henyey_greenstein(g: f64, sampler: &mut Sampler) -> Vec3 {
+    let r1 = sampler.random_f64();
+    let r2 = sampler.random_f64();
+    // inverting the cumulative distribution divides by g, close to 0 the function is
+    // uniform over the sphere
+    let z = if g.abs() < 1e-3 {
+        1.0 - 2.0 * r2
+    } else {
+        let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * r2);
+        ((1.0 + g * g - s * s) / (2.0 * g)).clamp(-1.0, 1.0)
+    };
+    let phi = 2.0 * PI * r1;
+    let sin_theta = (1.0 - z * z).sqrt();
+    Vec3::new(phi.cos() * sin_theta, phi.sin() * sin_theta, z)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_onb_is_orthonormal() {
+        let onb = Onb::new(&Vec3::new(1.0, 2.0, -3.0));
+        for (a, b) in [(&onb.u, &onb.v), (&onb.v, &onb.w), (&onb.w, &onb.u)] {
+            assert!(a.dot(b).abs() < 1e-12);
+            assert!((a.length() - 1.0).abs() < 1e-12);
+        }
+        let a = onb.transform(&Vec3::new(0.3, -0.2, 0.9));
+        assert!((a.dot(&onb.w) - 0.9).abs() < 1e-12);
+        assert!((&onb.to_local(&a) - &Vec3::new(0.3, -0.2, 0.9)).length() < 1e-12);
+    }
+
+    #[test]
+    fn test_henyey_greenstein_sampling() {
+        let mut sampler = Sampler::new(12);
+        for g in [-0.5, 0.0, 0.3, 0.9] {
+            let n = 100000;
+            let samples: Vec<f64> = (0..n)
+                .map(|_| random_henyey_greenstein(g, &mut sampler).z())
+                .collect();
+            // the average cosine is g
+            let mean = samples.iter().sum::<f64>() / n as f64;
+            assert!((mean - g).abs() < 0.01, "{} {}", g, mean);
+            // the share of directions within 60 degrees of the axis matches the integral
+            // of the phase function over them
+            let steps = 1000;
+            let expected: f64 = (0..steps)
+                .map(|i| {
+                    let cosine = 0.5 + (i as f64 + 0.5) * 0.5 / steps as f64;
+                    2.0 * PI * henyey_greenstein(g, cosine) * 0.5 / steps as f64
+                })
+                .sum();
+            let share = samples.iter().filter(|&&z| z > 0.5).count() as f64 / n as f64;
+            assert!(
+                (share - expected).abs() < 0.01,
+                "{} {} {}",
+                g,
+                share,
+                expected
+            );
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use crate::sampler::Sampler;
+use crate::Point3;
+use crate::Vec3;
+
+// number of gradients, the lattice repeats after this many cells along each axis
+const POINT_COUNT: usize = 256;
+
+// gradient noise: random unit vectors at the integer lattice points, blended across
+// each cell with hermite smoothing so the noise has no visible grid
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    // the same sampler state gives the same noise
+    pub fn new(sampler: &mut Sampler) -> Self {
+        let gradients = (0..POINT_COUNT)
+            .map(|_| Vec3::random_unit_vec3(sampler))
+            .collect();
+        Perlin {
+            gradients,
+            perm_x: permutation(sampler),
+            perm_y: permutation(sampler),
+            perm_z: permutation(sampler),
+        }
+    }
+
+    // noise at p, in [-1, 1] and zero at the lattice points
+    pub fn noise(&self, p: &Point3) -> f64 {
+        let floor = [p.x().floor(), p.y().floor(), p.z().floor()];
+        let [u, v, w] = [p.x() - floor[0], p.y() - floor[1], p.z() - floor[2]];
+        let [i, j, k] = floor.map(|x| x as i64);
+        // hermite cubic, flat at both ends of the cell
+        let [uu, vv, ww] = [u, v, w].map(|t| t * t * (3.0 - 2.0 * t));
+
+        let mut accum = 0.0;
+        for di in 0..2 {
+            for dj in 0..2 {
+                for dk in 0..2 {
+                    let index = self.perm_x[lattice(i + di)]
+                        ^ self.perm_y[lattice(j + dj)]
+                        ^ self.perm_z[lattice(k + dk)];
+                    let (fi, fj, fk) = (di as f64, dj as f64, dk as f64);
+                    let weight = Vec3::new(u - fi, v - fj, w - fk);
+                    accum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * self.gradients[index].dot(&weight);
+                }
+            }
+        }
+        accum
+    }
+
+    // absolute value of the sum of depth octaves of noise, each at twice the frequency
+    // and half the weight of the one before
+    pub fn turbulence(&self, p: &Point3, depth: u32) -> f64 {
+        let mut accum = 0.0;
+        let mut p = p.clone();
+        let mut weight = 1.0;
+        for _ in 0..depth {
+            accum += weight * self.noise(&p);
+            weight *= 0.5;
+            p *= 2.0;
+        }
+        accum.abs()
+    }
+}
+
+// lattice coordinate i wrapped into the permutation tables
+fn lattice(i: i64) -> usize {
+    i.rem_euclid(POINT_COUNT as i64) as usize
+}
+
+// the numbers 0 to POINT_COUNT - 1 in random order
+fn permutation(sampler: &mut Sampler) -> Vec<usize> {
+    let mut perm: Vec<usize> = (0..POINT_COUNT).collect();
+    for i in (1..POINT_COUNT).rev() {
+        let target = ((sampler.random_f64() * (i + 1) as f64) as usize).min(i);
+        perm.swap(i, target);
+    }
+    perm
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_noise_is_smooth_and_bounded() {
+        let perlin = Perlin::new(&mut Sampler::new(3));
+        let mut sampler = Sampler::new(4);
+        for _ in 0..1000 {
+            let p = Vec3::random(-50.0, 50.0, &mut sampler);
+            let noise = perlin.noise(&p);
+            assert!((-1.0..=1.0).contains(&noise), "{}", noise);
+            // nearby points have nearby values
+            let near = &p + &Vec3::new(1e-4, 1e-4, 1e-4);
+            assert!((perlin.noise(&near) - noise).abs() < 1e-2);
+        }
+        assert_eq!(perlin.noise(&Point3::new(2.0, -7.0, 11.0)), 0.0);
+    }
+
+    #[test]
+    fn test_seed_gives_same_noise() {
+        let a = Perlin::new(&mut Sampler::new(9));
+        let b = Perlin::new(&mut Sampler::new(9));
+        let c = Perlin::new(&mut Sampler::new(10));
+        let p = Point3::new(0.3, 1.7, -2.2);
+        assert_eq!(a.noise(&p), b.noise(&p));
+        assert_ne!(a.noise(&p), c.noise(&p));
+        assert_eq!(a.turbulence(&p, 7), b.turbulence(&p, 7));
+    }
+}
@@ -0,0 +1,536 @@
+use crate::color::gamma_to_linear;
+use crate::mesh::{Mesh, MeshVertices};
+use crate::traits::Scatter;
+use crate::Result;
+use crate::{Color, Point3, Vec3};
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+// stanford ply reader for ascii and binary files. the vertex element gives the
+// positions, and optionally normals, colors and texture coordinates, the face element
+// gives polygons which are split into triangle fans. other elements are skipped
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Format {
+    Ascii,
+    BinaryLittleEndian,
+    BinaryBigEndian,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Scalar {
+    Int8,
+    UInt8,
+    Int16,
+    UInt16,
+    Int32,
+    UInt32,
+    Float32,
+    Float64,
+}
+
+impl Scalar {
+    fn parse(s: &str) -> Option<Scalar> {
+        Some(match s {
+            "char" | "int8" => Scalar::Int8,
+            "uchar" | "uint8" => Scalar::UInt8,
+            "short" | "int16" => Scalar::Int16,
+            "ushort" | "uint16" => Scalar::UInt16,
+            "int" | "int32" => Scalar::Int32,
+            "uint" | "uint32" => Scalar::UInt32,
+            "float" | "float32" => Scalar::Float32,
+            "double" | "float64" => Scalar::Float64,
+            _ => return None,
+        })
+    }
+
+    fn size(self) -> usize {
+        match self {
+            Scalar::Int8 | Scalar::UInt8 => 1,
+            Scalar::Int16 | Scalar::UInt16 => 2,
+            Scalar::Int32 | Scalar::UInt32 | Scalar::Float32 => 4,
+            Scalar::Float64 => 8,
+        }
+    }
+
+    // the value as a fraction of the largest value of an unsigned type, colors are
+    // stored either way
+    fn unit(self, value: f64) -> f64 {
+        match self {
+            Scalar::UInt8 => value / u8::MAX as f64,
+            Scalar::UInt16 => value / u16::MAX as f64,
+            Scalar::UInt32 => value / u32::MAX as f64,
+            _ => value,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Property {
+    name: String,
+    // the type of the count of a list property
+    count: Option<Scalar>,
+    value: Scalar,
+}
+
+#[derive(Debug)]
+struct Element {
+    name: String,
+    count: usize,
+    properties: Vec<Property>,
+}
+
+impl Element {
+    fn find(&self, names: &[&str]) -> Option<usize> {
+        self.properties
+            .iter()
+            .position(|property| property.count.is_none() && names.contains(&&*property.name))
+    }
+
+    // fewest bytes a row of the element takes, a digit and a space per value in ascii
+    fn min_row_size(&self, format: Format) -> usize {
+        let size: usize = match format {
+            Format::Ascii => 2 * self.properties.len(),
+            Format::BinaryLittleEndian | Format::BinaryBigEndian => self
+                .properties
+                .iter()
+                .map(|property| property.count.unwrap_or(property.value).size())
+                .sum(),
+        };
+        size.max(1)
+    }
+}
+
+// reads the values of the elements one after another
+trait Values {
+    fn next(&mut self, scalar: Scalar) -> std::result::Result<f64, String>;
+
+    // line being read, for error messages of ascii files
+    fn line(&self) -> Option<usize>;
+}
+
+struct AsciiValues<'a> {
+    lines: std::iter::Enumerate<std::str::Lines<'a>>,
+    words: std::str::SplitWhitespace<'a>,
+    line: usize,
+}
+
+impl Values for AsciiValues<'_> {
+    fn next(&mut self, _scalar: Scalar) -> std::result::Result<f64, String> {
+        loop {
+            if let Some(word) = self.words.next() {
+                return word
+                    .parse()
+                    .ok()
+                    .filter(|x: &f64| x.is_finite())
+                    .ok_or_else(|| format!("bad number '{}'", word));
+            }
+            let (i, line) = self.lines.next().ok_or("unexpected end of file")?;
+            self.line = i;
+            self.words = line.split_whitespace();
+        }
+    }
+
+    fn line(&self) -> Option<usize> {
+        Some(self.line)
+    }
+}
+
+struct BinaryValues<'a> {
+    data: &'a [u8],
+    big_endian: bool,
+}
+
+impl Values for BinaryValues<'_> {
+    fn next(&mut self, scalar: Scalar) -> std::result::Result<f64, String> {
+        if self.data.len() < scalar.size() {
+            return Err("unexpected end of file".to_string());
+        }
+        let (bytes, rest) = self.data.split_at(scalar.size());
+        self.data = rest;
+        macro_rules! read {
+            ($t:ty) => {{
+                let bytes = bytes.try_into().unwrap();
+                if self.big_endian {
+                    <$t>::from_be_bytes(bytes) as f64
+                } else {
+                    <$t>::from_le_bytes(bytes) as f64
+                }
+            }};
+        }
+        Ok(match scalar {
+            Scalar::Int8 => read!(i8),
+            Scalar::UInt8 => read!(u8),
+            Scalar::Int16 => read!(i16),
+            Scalar::UInt16 => read!(u16),
+            Scalar::Int32 => read!(i32),
+            Scalar::UInt32 => read!(u32),
+            Scalar::Float32 => read!(f32),
+            Scalar::Float64 => read!(f64),
+        })
+    }
+
+    fn line(&self) -> Option<usize> {
+        None
+    }
+}
+
+// read the ply file at path into a mesh made of material. a lambertian material takes
+// the vertex colors of the file as its albedo
+pub fn load_ply(path: &Path, material: Arc<dyn Scatter>) -> Result<Mesh> {
+    let data = fs::read(path)
+        .map_err(|err| format!("cannot read ply file {}: {}", path.display(), err))?;
+    let (vertices, faces) = parse_ply(&data, &path.display().to_string())?;
+    // the file can be large, free it before building the hierarchy of the mesh
+    drop(data);
+    Ok(Mesh::indexed(Arc::new(vertices), faces, material))
+}
+
+// the elements of the header and the offset of the data after it
+fn parse_header(data: &[u8], name: &str) -> Result<(Format, Vec<Element>, usize)> {
+    let mut format = None;
+    let mut elements: Vec<Element> = Vec::new();
+    let mut offset = 0;
+    for i in 0.. {
+        let error = |message: String| format!("{}:{}: {}", name, i + 1, message);
+        let Some(length) = data[offset..].iter().position(|&byte| byte == b'\n') else {
+            return Err(error("header without end_header".to_string()).into());
+        };
+        let line = String::from_utf8_lossy(&data[offset..offset + length]);
+        offset += length + 1;
+        let words: Vec<&str> = line.split_whitespace().collect();
+        if i == 0 {
+            if words != ["ply"] {
+                return Err(error("not a ply file".to_string()).into());
+            }
+            continue;
+        }
+        match words.as_slice() {
+            ["format", kind, "1.0"] => {
+                format = Some(match *kind {
+                    "ascii" => Format::Ascii,
+                    "binary_little_endian" => Format::BinaryLittleEndian,
+                    "binary_big_endian" => Format::BinaryBigEndian,
+                    _ => return Err(error(format!("unknown format '{}'", kind)).into()),
+                });
+            }
+            ["element", element_name, count] => {
+                let count = count
+                    .parse()
+                    .map_err(|_| error(format!("bad element count '{}'", count)))?;
+                elements.push(Element {
+                    name: element_name.to_string(),
+                    count,
+                    properties: Vec::new(),
+                });
+            }
+            ["property", rest @ ..] => {
+                let bad_type = |ty: &str| error(format!("unknown property type '{}'", ty));
+                let property = match rest {
+                    ["list", count, value, property_name] => Property {
+                        name: property_name.to_string(),
+                        count: Some(Scalar::parse(count).ok_or_else(|| bad_type(count))?),
+                        value: Scalar::parse(value).ok_or_else(|| bad_type(value))?,
+                    },
+                    [value, property_name] => Property {
+                        name: property_name.to_string(),
+                        count: None,
+                        value: Scalar::parse(value).ok_or_else(|| bad_type(value))?,
+                    },
+                    _ => return Err(error(format!("bad property '{}'", line.trim())).into()),
+                };
+                let Some(element) = elements.last_mut() else {
+                    return Err(error("property before any element".to_string()).into());
+                };
+                element.properties.push(property);
+            }
+            ["end_header"] => {
+                let format = format.ok_or_else(|| error("header without format".to_string()))?;
+                return Ok((format, elements, offset));
+            }
+            ["comment", ..] | ["obj_info", ..] | [] => {}
+            _ => return Err(error(format!("bad header line '{}'", line.trim())).into()),
+        }
+    }
+    unreachable!()
+}
+
+fn parse_ply(data: &[u8], name: &str) -> Result<(MeshVertices, Vec<[u32; 3]>)> {
+    let (format, elements, offset) = parse_header(data, name)?;
+    let header_lines = data[..offset].iter().filter(|&&byte| byte == b'\n').count();
+    let text;
+    let mut values: Box<dyn Values> = match format {
+        Format::Ascii => {
+            text = std::str::from_utf8(&data[offset..])
+                .map_err(|_| format!("{}: ascii data is not text", name))?;
+            Box::new(AsciiValues {
+                lines: text.lines().enumerate(),
+                words: "".split_whitespace(),
+                line: 0,
+            })
+        }
+        Format::BinaryLittleEndian | Format::BinaryBigEndian => Box::new(BinaryValues {
+            data: &data[offset..],
+            big_endian: format == Format::BinaryBigEndian,
+        }),
+    };
+
+    // the counts of the header are not to be trusted, memory is only reserved for as
+    // many rows as the data can hold
+    let max_rows = |element: &Element| (data.len() - offset) / element.min_row_size(format) + 1;
+    let mut vertices = MeshVertices::default();
+    let mut faces = Vec::new();
+    let mut has_normals = false;
+    let mut has_uvs = false;
+    let mut row = Vec::new();
+    let mut list = Vec::new();
+    for element in elements.iter() {
+        let position = ["x", "y", "z"].map(|axis| element.find(&[axis]));
+        let normal = ["nx", "ny", "nz"].map(|axis| element.find(&[axis]));
+        let color = [
+            ["red", "diffuse_red", "r"],
+            ["green", "diffuse_green", "g"],
+            ["blue", "diffuse_blue", "b"],
+        ]
+        .map(|names| element.find(&names));
+        let uv =
+            [["u", "s", "texture_u"], ["v", "t", "texture_v"]].map(|names| element.find(&names));
+        let indices = element.properties.iter().position(|property| {
+            property.count.is_some()
+                && (property.name == "vertex_indices" || property.name == "vertex_index")
+        });
+        let is_vertex = element.name == "vertex";
+        let is_face = element.name == "face";
+        if is_vertex {
+            if position.iter().any(|index| index.is_none()) {
+                return Err(format!("{}: vertex element without x, y and z", name).into());
+            }
+            has_normals = normal.iter().all(|index| index.is_some());
+            has_uvs = uv.iter().all(|index| index.is_some());
+            vertices
+                .positions
+                .reserve(element.count.min(max_rows(element)));
+        }
+        if is_face {
+            if indices.is_none() {
+                return Err(format!("{}: face element without vertex_indices", name).into());
+            }
+            faces.reserve(element.count.min(max_rows(element)));
+        }
+
+        for n in 0..element.count {
+            let error = |values: &dyn Values, message: String| match values.line() {
+                Some(line) => format!("{}:{}: {}", name, header_lines + line + 1, message),
+                None => format!("{}: {} {}: {}", name, element.name, n, message),
+            };
+            row.clear();
+            list.clear();
+            for (k, property) in element.properties.iter().enumerate() {
+                let mut read = |scalar| {
+                    values
+                        .next(scalar)
+                        .map_err(|message| error(values.as_ref(), message))
+                };
+                match property.count {
+                    None => row.push(read(property.value)?),
+                    Some(count) => {
+                        let count = read(count)?;
+                        row.push(count);
+                        for _ in 0..count as usize {
+                            let value = read(property.value)?;
+                            if Some(k) == indices {
+                                list.push(value);
+                            }
+                        }
+                    }
+                }
+            }
+            let value = |index: Option<usize>| index.map_or(0.0, |index| row[index]);
+            if is_vertex {
+                let [x, y, z] = position.map(value);
+                vertices.positions.push(Point3::new(x, y, z));
+                if has_normals {
+                    let [x, y, z] = normal.map(value);
+                    let normal = Vec3::new(x, y, z);
+                    // a zero normal, or one too short or long to measure, has no direction
+                    let length = normal.length();
+                    if !(length.is_finite() && length > 0.0) {
+                        let message = format!("normal {:?} has no direction", [x, y, z]);
+                        return Err(error(values.as_ref(), message).into());
+                    }
+                    vertices.normals.push(normal / length);
+                }
+                if has_uvs {
+                    let [u, v] = uv.map(value);
+                    vertices.uvs.push((u, v));
+                }
+                if color.iter().all(|index| index.is_some()) {
+                    // colors are gamma corrected like the rendered image
+                    let [r, g, b] = color.map(|index| {
+                        let scalar = element.properties[index.unwrap_or(0)].value;
+                        gamma_to_linear(scalar.unit(value(index)))
+                    });
+                    vertices.colors.push(Color::new(r, g, b));
+                }
+            }
+            if is_face {
+                if list.len() < 3 {
+                    let message = "face with fewer than 3 vertices".to_string();
+                    return Err(error(values.as_ref(), message).into());
+                }
+                let vertex_count = vertices.positions.len();
+                if let Some(&bad) = list
+                    .iter()
+                    .find(|&&index| index < 0.0 || index as usize >= vertex_count)
+                {
+                    let message = format!("vertex index {} out of range", bad);
+                    return Err(error(values.as_ref(), message).into());
+                }
+                for k in 1..list.len() - 1 {
+                    faces.push([list[0], list[k], list[k + 1]].map(|index| index as u32));
+                }
+            }
+        }
+    }
+    Ok((vertices, faces))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::INFINITY;
+    use crate::{Hittable, Interval, Lambertian, Ray};
+
+    const HEADER: &str = "ply
+format ascii 1.0
+comment a colored square
+element vertex 4
+property float x
+property float y
+property float z
+property uchar red
+property uchar green
+property uchar blue
+element face 1
+property list uchar int vertex_indices
+end_header
+";
+
+    fn parse(data: &[u8]) -> Result<Mesh> {
+        let (vertices, faces) = parse_ply(data, "square.ply")?;
+        Ok(Mesh::indexed(
+            Arc::new(vertices),
+            faces,
+            Arc::new(Lambertian::default()),
+        ))
+    }
+
+    fn down_at(x: f64, y: f64) -> Ray {
+        Ray {
+            orig: Point3::new(x, y, 1.0),
+            dir: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_ascii() {
+        let text = format!(
+            "{}{}",
+            HEADER, "0 0 0 255 0 0\n1 0 0 255 0 0\n1 1 0 0 0 255\n0 1 0 0 0 255\n4 0 1 2 3\n"
+        );
+        let mesh = parse(text.as_bytes()).unwrap();
+        assert_eq!(mesh.faces().len(), 2);
+        let rec = mesh
+            .hit(&down_at(0.5, 0.0), &mut Interval::new(0.001, INFINITY))
+            .unwrap();
+        assert_eq!(rec.t, 1.0);
+        assert_eq!(rec.vertex_color, Some(Color::new(1.0, 0.0, 0.0)));
+        let rec = mesh
+            .hit(&down_at(0.5, 0.5), &mut Interval::new(0.001, INFINITY))
+            .unwrap();
+        let color = rec.vertex_color.unwrap();
+        assert!((color.x() - 0.5).abs() < 1e-9 && (color.z() - 0.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_binary() {
+        for (format, big_endian) in [("binary_little_endian", false), ("binary_big_endian", true)] {
+            let header = "ply\nformat FORMAT 1.0\nelement vertex 3\nproperty double x\n\
+                property double y\nproperty double z\nproperty float nx\nproperty float ny\n\
+                property float nz\nelement edge 1\nproperty int vertex1\nproperty int vertex2\n\
+                element face 1\nproperty uchar flags\nproperty list uchar uint vertex_index\n\
+                end_header\n";
+            let mut data = header.replace("FORMAT", format).into_bytes();
+            for [x, y] in [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]] {
+                for value in [x, y, 0.0f64] {
+                    if big_endian {
+                        data.extend(value.to_be_bytes());
+                    } else {
+                        data.extend(value.to_le_bytes());
+                    }
+                }
+                for value in [0.0f32, 0.0, 2.0] {
+                    if big_endian {
+                        data.extend(value.to_be_bytes());
+                    } else {
+                        data.extend(value.to_le_bytes());
+                    }
+                }
+            }
+            // an edge to skip, then the face after its flags
+            data.extend([0u8; 8]);
+            data.extend([7u8, 3]);
+            for index in [0u32, 1, 2] {
+                if big_endian {
+                    data.extend(index.to_be_bytes());
+                } else {
+                    data.extend(index.to_le_bytes());
+                }
+            }
+            let mesh = parse(&data).unwrap();
+            assert_eq!(mesh.faces().len(), 1);
+            let rec = mesh
+                .hit(&down_at(0.25, 0.25), &mut Interval::new(0.001, INFINITY))
+                .unwrap();
+            assert_eq!(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+            assert_eq!(rec.vertex_color, None);
+
+            let err = parse(&data[..data.len() - 2]).err().unwrap().to_string();
+            assert_eq!(err, "square.ply: face 0: unexpected end of file");
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let text = format!(
+            "{}{}",
+            HEADER, "0 0 0 255 0 0\n1 0 0 255 0 0\n1 x 0 0 0 255\n"
+        );
+        let err = parse(text.as_bytes()).err().unwrap().to_string();
+        assert_eq!(err, "square.ply:16: bad number 'x'");
+        let text = format!(
+            "{}{}",
+            HEADER, "0 0 0 255 0 0\n1 0 0 255 0 0\n1 1 0 0 0 255\n0 1 0 0 0 255\n3 0 1 4\n"
+        );
+        let err = parse(text.as_bytes()).err().unwrap().to_string();
+        assert_eq!(err, "square.ply:18: vertex index 4 out of range");
+        let text = HEADER.replace("uchar blue", "color blue");
+        let err = parse(text.as_bytes()).err().unwrap().to_string();
+        assert_eq!(err, "square.ply:10: unknown property type 'color'");
+        let text = HEADER.replace(
+            "uchar blue",
+            "uchar blue\nproperty float nx\nproperty float ny\nproperty float nz",
+        ) + "0 0 0 255 0 0 0 0 1\n1 0 0 255 0 0 0 0 0\n";
+        let err = parse(text.as_bytes()).err().unwrap().to_string();
+        assert_eq!(
+            err,
+            "square.ply:18: normal [0.0, 0.0, 0.0] has no direction"
+        );
+        // a count far beyond the data runs into its end instead of running out of memory
+        let text = HEADER.replace("vertex 4", "vertex 4000000000000000000") + "0 0 0 255 0 0\n";
+        let err = parse(text.as_bytes()).err().unwrap().to_string();
+        assert_eq!(err, "square.ply:14: unexpected end of file");
+    }
+}
@@ -0,0 +1,237 @@
+use crate::aabb::Aabb;
+use crate::interval::Interval;
+use crate::sampler::Sampler;
+use crate::utils::INFINITY;
+use crate::HitRecord;
+use crate::Hittable;
+use crate::Point3;
+use crate::Ray;
+use crate::Scatter;
+use crate::Vec3;
+use std::sync::Arc;
+
+// parallelogram with corner q and sides u and v, the points q + a * u + b * v
+// for a and b in [0, 1]. the front face is the side u x v points to
+#[derive(Clone)]
+pub struct Quad {
+    pub q: Point3,
+    pub u: Vec3,
+    pub v: Vec3,
+    material: Arc<dyn Scatter>,
+    normal: Vec3,
+    // plane of the quad is the points p with normal . p = d
+    d: f64,
+    // u x v / |u x v|^2, turns a point of the plane into its coordinates along u and v
+    w: Vec3,
+    area: f64,
+}
+
+impl Quad {
+    pub fn new(q: Point3, u: Vec3, v: Vec3, material: Arc<dyn Scatter>) -> Self {
+        let n = u.cross(&v);
+        let normal = n.make_unit_vector();
+        let d = normal.dot(&q);
+        let w = &n / n.dot(&n);
+        let area = n.length();
+        Quad {
+            q,
+            u,
+            v,
+            material,
+            normal,
+            d,
+            w,
+            area,
+        }
+    }
+
+    // the six sides of the box with opposite corners a and b, facing outwards
+    pub fn make_box(a: &Point3, b: &Point3, material: Arc<dyn Scatter>) -> Vec<Quad> {
+        let min = Point3::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z()));
+        let max = Point3::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z()));
+        let dx = Vec3::new(max.x() - min.x(), 0.0, 0.0);
+        let dy = Vec3::new(0.0, max.y() - min.y(), 0.0);
+        let dz = Vec3::new(0.0, 0.0, max.z() - min.z());
+        let corner = |x: f64, y: f64, z: f64| Point3::new(x, y, z);
+        vec![
+            // front, right, back, left, top, bottom
+            Quad::new(
+                corner(min.x(), min.y(), max.z()),
+                dx.clone(),
+                dy.clone(),
+                material.clone(),
+            ),
+            Quad::new(
+                corner(max.x(), min.y(), max.z()),
+                &dz * -1.0,
+                dy.clone(),
+                material.clone(),
+            ),
+            Quad::new(
+                corner(max.x(), min.y(), min.z()),
+                &dx * -1.0,
+                dy.clone(),
+                material.clone(),
+            ),
+            Quad::new(
+                corner(min.x(), min.y(), min.z()),
+                dz.clone(),
+                dy,
+                material.clone(),
+            ),
+            Quad::new(
+                corner(min.x(), max.y(), max.z()),
+                dx.clone(),
+                &dz * -1.0,
+                material.clone(),
+            ),
+            Quad::new(corner(min.x(), min.y(), min.z()), dx, dz, material),
+        ]
+    }
+}
+
+impl Hittable for Quad {
+    fn hit(&self, ray: &Ray, ray_t: &mut Interval) -> Option<HitRecord> {
+        let denom = self.normal.dot(&ray.dir);
+        // no hit if the ray is parallel to the plane
+        if denom.abs() < 1e-8 {
+            return None;
+        }
+        let t = (self.d - self.normal.dot(&ray.orig)) / denom;
+        if !ray_t.surrounds(t) {
+            return None;
+        }
+        // coordinates of the hit point along u and v decide whether it is inside
+        let point = ray.at(t);
+        let planar_hit = &point - &self.q;
+        let alpha = self.w.dot(&planar_hit.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar_hit));
+        let unit = Interval::new(0.0, 1.0);
+        if !unit.contains(alpha) || !unit.contains(beta) {
+            return None;
+        }
+        let mut hit_record = HitRecord::new();
+        hit_record.t = t;
+        hit_record.point = point;
+        hit_record.u = alpha;
+        hit_record.v = beta;
+        hit_record.set_face_normal(ray, &self.normal);
+        hit_record.material = self.material.clone();
+        Some(hit_record)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let diagonal1 = Aabb::from_points(&self.q, &(&self.q + &(&self.u + &self.v)));
+        let diagonal2 = Aabb::from_points(&(&self.q + &self.u), &(&self.q + &self.v));
+        Aabb::enclosing(&diagonal1, &diagonal2).pad_to_minimums()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        let ray = Ray {
+            orig: origin.clone(),
+            dir: direction.clone(),
+            time,
+        };
+        let Some(rec) = self.hit(&ray, &mut Interval::new(0.001, INFINITY)) else {
+            return 0.0;
+        };
+        // turn the uniform density over the area into a density over solid angle
+        let distance_squared = rec.t * rec.t * direction.length_squared();
+        let cosine = (direction.dot(&rec.normal) / direction.length()).abs();
+        distance_squared / (cosine * self.area)
+    }
+
+    // direction towards a uniformly chosen point of the quad
+    fn random(&self, origin: &Point3, _time: f64, sampler: &mut Sampler) -> Vec3 {
+        let point = &self.q + &(sampler.random_f64() * &self.u + sampler.random_f64() * &self.v);
+        &point - origin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lambertian;
+
+    fn unit_square() -> Quad {
+        Quad::new(
+            Point3::new(-1.0, -1.0, 0.0),
+            Vec3::new(2.0, 0.0, 0.0),
+            Vec3::new(0.0, 2.0, 0.0),
+            Arc::new(Lambertian::default()),
+        )
+    }
+
+    #[test]
+    fn test_hit() {
+        let quad = unit_square();
+        let ray = Ray {
+            orig: Point3::new(0.5, 0.0, 3.0),
+            dir: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        let rec = quad.hit(&ray, &mut Interval::new(0.001, INFINITY)).unwrap();
+        assert_eq!(rec.t, 3.0);
+        assert!(rec.front_face);
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!((rec.u, rec.v), (0.75, 0.5));
+
+        // from behind the normal faces the ray
+        let back = Ray {
+            orig: Point3::new(0.5, 0.0, -3.0),
+            dir: Vec3::new(0.0, 0.0, 1.0),
+            time: 0.0,
+        };
+        let rec = quad
+            .hit(&back, &mut Interval::new(0.001, INFINITY))
+            .unwrap();
+        assert!(!rec.front_face);
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, -1.0));
+
+        let outside = Ray {
+            orig: Point3::new(1.5, 0.0, 3.0),
+            dir: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        };
+        assert!(quad
+            .hit(&outside, &mut Interval::new(0.001, INFINITY))
+            .is_none());
+    }
+
+    #[test]
+    fn test_pdf_matches_random() {
+        let quad = unit_square();
+        let origin = Point3::new(0.3, -0.2, 2.0);
+        let mut sampler = Sampler::new(1);
+        // the average of 1 / pdf over sampled directions estimates the solid angle
+        let n = 20000;
+        let solid_angle: f64 = (0..n)
+            .map(|_| 1.0 / quad.pdf_value(&origin, &quad.random(&origin, 0.0, &mut sampler), 0.0))
+            .sum::<f64>()
+            / n as f64;
+        let mut uniform = 0;
+        for _ in 0..n {
+            let direction = Vec3::random_unit_vec3(&mut sampler);
+            if quad.pdf_value(&origin, &direction, 0.0) > 0.0 {
+                uniform += 1;
+            }
+        }
+        let expected = 4.0 * crate::utils::PI * uniform as f64 / n as f64;
+        assert!((solid_angle - expected).abs() < 0.05 * expected);
+    }
+
+    #[test]
+    fn test_make_box_faces_outwards() {
+        let sides = Quad::make_box(
+            &Point3::new(1.0, 1.0, 1.0),
+            &Point3::new(-1.0, -1.0, -1.0),
+            Arc::new(Lambertian::default()),
+        );
+        assert_eq!(sides.len(), 6);
+        for side in sides {
+            let center = &side.q + &(0.5 * &side.u + 0.5 * &side.v);
+            // the center of each side points away from the center of the box
+            assert!(side.normal.dot(&center) > 0.0);
+        }
+    }
+}
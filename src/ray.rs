@@ -4,6 +4,9 @@ use crate::vec3::{Point3, Vec3};
 pub struct Ray {
     pub orig: Point3,
     pub dir: Vec3,
+    // moment during the exposure the ray is traced at, moving objects are hit where
+    // they are at that time
+    pub time: f64,
 }
 
 impl Ray {
@@ -21,6 +24,7 @@ mod tests {
         let ray = Ray {
             orig: Point3::default(),
             dir: Vec3::default(),
+            time: 0.0,
         };
         assert_eq!(ray.at(10.0), Vec3::default());
     }
@@ -30,6 +34,7 @@ mod tests {
         let ray = Ray {
             orig: Point3::default(),
             dir: Vec3::new(1.0, 0.0, 0.0),
+            time: 0.0,
         };
         assert_eq!(ray.at(10.0), Vec3::new(10.0, 0.0, 0.0));
     }
@@ -38,6 +43,7 @@ mod tests {
         let ray = Ray {
             orig: Point3::default(),
             dir: Vec3::new(1.0, 2.0, 3.0),
+            time: 0.0,
         };
         assert_eq!(ray.at(10.0), Vec3::new(10.0, 20.0, 30.0));
     }
@@ -0,0 +1,97 @@
+use crate::Ray;
+use rand::{Rng, SeedableRng};
+use rand_pcg::Pcg32;
+
+// seedable source of random numbers used while building and rendering a scene.
+// a render gives every pixel its own sampler derived from the render seed and the
+// pixel position, so the result does not depend on which thread traced the pixel
+pub struct Sampler {
+    rng: Pcg32,
+}
+
+impl Sampler {
+    pub fn new(seed: u64) -> Self {
+        Sampler {
+            rng: Pcg32::seed_from_u64(seed),
+        }
+    }
+
+    // sampler for the pixel at location i,j of a render seeded with seed
+    pub fn for_pixel(seed: u64, i: u32, j: u32) -> Self {
+        let pixel = ((j as u64) << 32) | i as u64;
+        Sampler {
+            rng: Pcg32::new(splitmix64(seed ^ splitmix64(pixel)), pixel),
+        }
+    }
+
+    // sampler for random choices made while hitting ray, where no sampler is at hand.
+    // the ray is itself the outcome of earlier random choices, so different rays give
+    // unrelated samplers while the same ray always gives the same one
+    pub fn for_ray(ray: &Ray) -> Self {
+        let mut state = 0;
+        for x in [
+            ray.orig.x(),
+            ray.orig.y(),
+            ray.orig.z(),
+            ray.dir.x(),
+            ray.dir.y(),
+            ray.dir.z(),
+            ray.time,
+        ] {
+            state = splitmix64(state ^ x.to_bits());
+        }
+        Sampler {
+            rng: Pcg32::seed_from_u64(state),
+        }
+    }
+
+    // generate random number between 0 and 1
+    pub fn random_f64(&mut self) -> f64 {
+        self.rng.gen()
+    }
+
+    // generate random number between given min and max
+    pub fn random_f64_range(&mut self, min: f64, max: f64) -> f64 {
+        self.rng.gen_range(min..max)
+    }
+}
+
+// scramble the bits of x, so that nearby seeds and pixels end up with unrelated states
+fn splitmix64(x: u64) -> u64 {
+    let mut z = x.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_same_seed_same_sequence() {
+        let mut a = Sampler::new(42);
+        let mut b = Sampler::new(42);
+        for _ in 0..10 {
+            assert_eq!(a.random_f64(), b.random_f64());
+        }
+    }
+
+    #[test]
+    fn test_pixels_are_independent() {
+        let first = Sampler::for_pixel(7, 0, 0).random_f64();
+        assert_eq!(first, Sampler::for_pixel(7, 0, 0).random_f64());
+        assert_ne!(first, Sampler::for_pixel(7, 1, 0).random_f64());
+        assert_ne!(first, Sampler::for_pixel(7, 0, 1).random_f64());
+        assert_ne!(first, Sampler::for_pixel(8, 0, 0).random_f64());
+    }
+
+    #[test]
+    fn test_range() {
+        let mut sampler = Sampler::new(1);
+        for _ in 0..100 {
+            let x = sampler.random_f64_range(-2.0, 3.0);
+            assert!((-2.0..3.0).contains(&x));
+        }
+    }
+}
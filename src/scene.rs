@@ -0,0 +1,1834 @@
+use crate::camera::{Background, Camera};
+use crate::decoder;
+use crate::disk::Disk;
+use crate::material::{
+    Conductor, DiffuseLight, HenyeyGreenstein, Isotropic, Principled, PrincipledParams,
+    RoughDielectric,
+};
+use crate::medium::{ConstantMedium, GridMedium};
+use crate::microfacet::MetalPreset;
+use crate::obj;
+use crate::perlin::Perlin;
+use crate::ply;
+use crate::quad::Quad;
+use crate::sampler::Sampler;
+use crate::spectrum::{Ior, LAMBDA_MAX, LAMBDA_MIN};
+use crate::texture::{
+    CheckerTexture, Filter, ImageTexture, MarbleTexture, NoiseTexture, SolidColor, UvChecker,
+    WoodTexture, Wrap,
+};
+use crate::thin_film::ThinFilm;
+use crate::traits::{Hittable, Scatter, Texture};
+use crate::transform::{Placement, Transform};
+use crate::triangle::Triangle;
+use crate::voxels::{self, VoxelGrid};
+use crate::Result;
+use crate::{Color, Dielectric, Hittables, Lambertian, Metal, Sphere, Vec3};
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use toml::Spanned;
+
+// a world and the camera looking at it, as described by a scene file. lights holds the
+// objects of the world with an emissive material, which the camera samples directly
+pub struct Scene {
+    pub world: Hittables,
+    pub lights: Hittables,
+    pub camera: Camera,
+}
+
+// the scene file is toml, for example
+//
+//   [camera]
+//   image_width = 400
+//   look_from = [13.0, 2.0, 3.0]
+//
+//   [materials.ground]
+//   type = "lambertian"
+//   albedo = [0.5, 0.5, 0.5]
+//
+//   [[objects]]
+//   type = "sphere"
+//   center = [0.0, -1000.0, 0.0]
+//   radius = 1000.0
+//   material = "ground"
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SceneDesc {
+    #[serde(default)]
+    camera: CameraDesc,
+    #[serde(default)]
+    materials: HashMap<String, MaterialDesc>,
+    #[serde(default)]
+    objects: Vec<Spanned<ObjectDesc>>,
+}
+
+// camera settings, anything left out keeps the value given by Camera::new
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+struct CameraDesc {
+    aspect_ratio: Option<f64>,
+    image_width: Option<u32>,
+    samples_per_pixel: Option<u32>,
+    max_depth: Option<i32>,
+    vfov: Option<f64>,
+    look_from: Option<[f64; 3]>,
+    look_at: Option<[f64; 3]>,
+    vup: Option<[f64; 3]>,
+    defocus_angle: Option<f64>,
+    focus_dist: Option<f64>,
+    shutter_open: Option<f64>,
+    shutter_close: Option<f64>,
+    spectral: Option<bool>,
+    background: Option<Spanned<BackgroundDesc>>,
+}
+
+// either gradient, none or a color such as [0.7, 0.8, 1.0]
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BackgroundDesc {
+    Named(String),
+    Solid([f64; 3]),
+}
+
+// either a color such as [0.8, 0.3, 0.1] or a texture, for example
+//
+//   albedo = { type = "checker", scale = 0.5, even = [0.2, 0.3, 0.1], odd = [0.9, 0.9, 0.9] }
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AlbedoDesc {
+    Color([f64; 3]),
+    Texture(TextureDesc),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum TextureDesc {
+    // checkerboard of cubes with sides scale long
+    Checker {
+        scale: f64,
+        even: Box<AlbedoDesc>,
+        odd: Box<AlbedoDesc>,
+    },
+    // checkerboard of width by height squares over the surface coordinates
+    UvChecker {
+        width: u32,
+        height: u32,
+        even: Box<AlbedoDesc>,
+        odd: Box<AlbedoDesc>,
+    },
+    // perlin noise of frequency cells per unit times color. the seed picks the noise,
+    // the same seed gives the same pattern
+    Noise {
+        frequency: f64,
+        #[serde(default)]
+        seed: u64,
+        #[serde(default = "white")]
+        color: [f64; 3],
+    },
+    // dark veins in a stone of color, frequency sets their spacing along z
+    Marble {
+        frequency: f64,
+        #[serde(default)]
+        seed: u64,
+        #[serde(default = "white")]
+        color: [f64; 3],
+    },
+    // rings around the y axis, frequency of them per unit of radius
+    Wood {
+        frequency: f64,
+        #[serde(default)]
+        seed: u64,
+        light: [f64; 3],
+        dark: [f64; 3],
+    },
+    // png, jpeg or hdr image, the path is relative to the scene file. wrap is repeat,
+    // clamp or mirror and filter nearest or bilinear
+    Image {
+        file: String,
+        wrap: Option<String>,
+        filter: Option<String>,
+    },
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum MaterialDesc {
+    Lambertian {
+        albedo: AlbedoDesc,
+    },
+    Metal {
+        albedo: AlbedoDesc,
+        #[serde(default)]
+        fuzz: f64,
+    },
+    // glass keeping exp(-absorption * distance) of the light going through its inside,
+    // and tinting the light passing through its surface. only closed objects have an
+    // inside, flat ones can be tinted but not absorb. a film makes the reflection
+    // iridescent, a soap bubble is glass of index 1 with a film
+    Dielectric {
+        ir: IorDesc,
+        #[serde(default)]
+        absorption: [f64; 3],
+        #[serde(default = "white")]
+        tint: [f64; 3],
+        film: Option<FilmDesc>,
+    },
+    // metal with a ggx microfacet surface, either a preset metal (gold, copper,
+    // aluminium or silver) or the complex index of refraction eta + i k at red, green
+    // and blue. roughness goes from 0, a mirror, to 1, and a film colors the metal as
+    // anodising does
+    Conductor {
+        metal: Option<String>,
+        eta: Option<[f64; 3]>,
+        k: Option<[f64; 3]>,
+        #[serde(default)]
+        roughness: f64,
+        film: Option<FilmDesc>,
+    },
+    // glass with a ggx microfacet surface
+    #[serde(rename = "rough_dielectric")]
+    RoughDielectric {
+        ir: f64,
+        roughness: f64,
+    },
+    // disney style uber material, every parameter but ior goes from 0 to 1. metallic
+    // turns the base into metal, transmission into glass, specular sets the strength
+    // of the reflection off the rest, 0.5 being the usual 4%, and clearcoat and sheen
+    // add a varnish and a soft rim of light
+    Principled {
+        base_color: AlbedoDesc,
+        #[serde(default)]
+        metallic: f64,
+        #[serde(default = "half")]
+        roughness: f64,
+        #[serde(default = "half")]
+        specular: f64,
+        #[serde(default)]
+        clearcoat: f64,
+        #[serde(default)]
+        sheen: f64,
+        #[serde(default)]
+        transmission: f64,
+        #[serde(default = "glass_ior")]
+        ior: f64,
+    },
+    #[serde(rename = "diffuse_light")]
+    DiffuseLight {
+        emit: [f64; 3],
+    },
+    // scatters equally into every direction, for the particles of a medium
+    Isotropic {
+        albedo: AlbedoDesc,
+    },
+    // scatters onwards for g above 0 and back for g below, g between -1 and 1 is the
+    // average cosine of the turn
+    #[serde(rename = "henyey_greenstein")]
+    HenyeyGreenstein {
+        albedo: AlbedoDesc,
+        g: f64,
+    },
+}
+
+// every object can list instances, each placing a copy of the object moved by its
+// transform. the copies share the geometry of the object, so a large model can be
+// placed many times. without instances the object is placed as it is.
+//
+// an object given a density is not a surface but the boundary of a medium filling it,
+// such as fog or smoke, that scatters light with its material, usually isotropic or
+// henyey_greenstein
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase", deny_unknown_fields)]
+enum ObjectDesc {
+    // a sphere given center_end moves to it in a straight line over the frame
+    Sphere {
+        center: [f64; 3],
+        center_end: Option<[f64; 3]>,
+        radius: f64,
+        material: String,
+        density: Option<DensityDesc>,
+        #[serde(default)]
+        instances: Vec<InstanceDesc>,
+    },
+    // parallelogram with corner q and sides u and v
+    Quad {
+        q: [f64; 3],
+        u: [f64; 3],
+        v: [f64; 3],
+        material: String,
+        density: Option<DensityDesc>,
+        #[serde(default)]
+        instances: Vec<InstanceDesc>,
+    },
+    // six quads between opposite corners a and b
+    Box {
+        a: [f64; 3],
+        b: [f64; 3],
+        material: String,
+        density: Option<DensityDesc>,
+        #[serde(default)]
+        instances: Vec<InstanceDesc>,
+    },
+    Triangle {
+        vertices: [[f64; 3]; 3],
+        normals: Option<[[f64; 3]; 3]>,
+        uvs: Option<[[f64; 2]; 3]>,
+        material: String,
+        density: Option<DensityDesc>,
+        #[serde(default)]
+        instances: Vec<InstanceDesc>,
+    },
+    Disk {
+        center: [f64; 3],
+        normal: [f64; 3],
+        radius: f64,
+        material: String,
+        density: Option<DensityDesc>,
+        #[serde(default)]
+        instances: Vec<InstanceDesc>,
+    },
+    // wavefront obj model, the path is relative to the scene file. material is used
+    // for the faces the obj file gives no material
+    Obj {
+        file: String,
+        material: Option<String>,
+        #[serde(default)]
+        smooth_normals: bool,
+        density: Option<DensityDesc>,
+        #[serde(default)]
+        instances: Vec<InstanceDesc>,
+    },
+    // stanford ply mesh, the path is relative to the scene file. without a material
+    // the mesh is lambertian, colored by its vertex colors if it has any
+    Ply {
+        file: String,
+        material: Option<String>,
+        density: Option<DensityDesc>,
+        #[serde(default)]
+        instances: Vec<InstanceDesc>,
+    },
+}
+
+// transform of an instance: scaled, then rotated by degrees around the x, y and z axes
+// in turn, then translated, for example
+//
+//   instances = [{ scale = 2.0, rotate = [0.0, 15.0, 0.0], translate = [1.0, 0.0, 0.0] }]
+//
+// the _end values make the instance move over the frame, from the transform at its
+// start to the one at its end. values without an end stay as they are
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct InstanceDesc {
+    scale: Option<ScaleDesc>,
+    rotate: Option<[f64; 3]>,
+    translate: Option<[f64; 3]>,
+    scale_end: Option<ScaleDesc>,
+    rotate_end: Option<[f64; 3]>,
+    translate_end: Option<[f64; 3]>,
+}
+
+// either the same factor along every axis or one per axis
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum ScaleDesc {
+    Uniform(f64),
+    Axes([f64; 3]),
+}
+
+// the density of a medium: either a number, the same everywhere, or a voxel grid
+// stretched over the box of the object, for example
+//
+//   [objects.density]
+//   voxels = { type = "noise", resolution = [64, 64, 64], frequency = 3.0 }
+//   absorption = 0.5
+//   scattering = 4.0
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum DensityDesc {
+    Constant(f64),
+    Grid(GridDesc),
+}
+
+// the densities of the grid scale how much light is absorbed and scattered per unit
+// length
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct GridDesc {
+    voxels: VoxelsDesc,
+    #[serde(default)]
+    absorption: f64,
+    #[serde(default = "one")]
+    scattering: f64,
+}
+
+// an index of refraction: a number, a glass by name (bk7, sf11, fused_silica or
+// diamond) or a formula of the wavelength in micrometers, which spreads light into its
+// colors in spectral mode, for example
+//
+//   ir = { type = "cauchy", a = 1.5046, b = 0.0042 }
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum IorDesc {
+    Constant(f64),
+    Named(String),
+    Formula(IorFormulaDesc),
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum IorFormulaDesc {
+    // a + b / lambda^2
+    Cauchy { a: f64, b: f64 },
+    // the square root of 1 + sum of b lambda^2 / (lambda^2 - c)
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+// a thin transparent coating whose reflections interfere, thickness in nanometers.
+// films of a few hundred nanometers show the brightest colors, for example
+//
+//   film = { thickness = 350.0, ior = 1.33 }
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct FilmDesc {
+    thickness: f64,
+    ior: f64,
+}
+
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum VoxelsDesc {
+    // raw voxel file of bytes or floats, the path is relative to the scene file
+    Raw {
+        file: String,
+        resolution: [usize; 3],
+    },
+    // perlin turbulence with frequency cells across the grid, fading out at its sides
+    Noise {
+        resolution: [usize; 3],
+        frequency: f64,
+        #[serde(default)]
+        seed: u64,
+    },
+}
+
+// a medium ready to fill boundaries, the voxels are shared by all the instances
+enum Medium {
+    Constant(f64),
+    Grid {
+        grid: Arc<VoxelGrid>,
+        absorption: f64,
+        scattering: f64,
+    },
+}
+
+fn one() -> f64 {
+    1.0
+}
+
+fn half() -> f64 {
+    0.5
+}
+
+fn glass_ior() -> f64 {
+    1.5
+}
+
+fn white() -> [f64; 3] {
+    [1.0, 1.0, 1.0]
+}
+
+fn vec3(v: &[f64; 3]) -> Vec3 {
+    Vec3::new(v[0], v[1], v[2])
+}
+
+// 1-based line of the byte offset in text
+fn line_of(text: &str, offset: usize) -> usize {
+    text[..offset.min(text.len())].matches('\n').count() + 1
+}
+
+impl CameraDesc {
+    fn build(&self, text: &str, name: &str) -> Result<Camera> {
+        let mut camera = Camera::new(16.0 / 9.0, 400, 100, 50, 90.0);
+        if let Some(aspect_ratio) = self.aspect_ratio {
+            camera.aspect_ratio = aspect_ratio;
+        }
+        if let Some(image_width) = self.image_width {
+            camera.image_width = image_width;
+        }
+        if let Some(samples_per_pixel) = self.samples_per_pixel {
+            camera.samples_per_pixel = samples_per_pixel;
+        }
+        if let Some(max_depth) = self.max_depth {
+            camera.max_depth = max_depth;
+        }
+        if let Some(vfov) = self.vfov {
+            camera.vfov = vfov;
+        }
+        if let Some(look_from) = &self.look_from {
+            camera.look_from = vec3(look_from);
+        }
+        if let Some(look_at) = &self.look_at {
+            camera.look_at = vec3(look_at);
+        }
+        if let Some(vup) = &self.vup {
+            camera.vup = vec3(vup);
+        }
+        if let Some(defocus_angle) = self.defocus_angle {
+            camera.defocus_angle = defocus_angle;
+        }
+        if let Some(focus_dist) = self.focus_dist {
+            camera.focus_dist = focus_dist;
+        }
+        if let Some(shutter_open) = self.shutter_open {
+            camera.shutter_open = shutter_open;
+        }
+        if let Some(shutter_close) = self.shutter_close {
+            camera.shutter_close = shutter_close;
+        }
+        if let Some(spectral) = self.spectral {
+            camera.spectral = spectral;
+        }
+        if let Some(background) = &self.background {
+            camera.background = match background.get_ref() {
+                BackgroundDesc::Named(background_name) => {
+                    background_name.parse().map_err(|err| {
+                        format!(
+                            "{}:{}: {}",
+                            name,
+                            line_of(text, background.span().start),
+                            err
+                        )
+                    })?
+                }
+                BackgroundDesc::Solid(color) => Background::Solid(vec3(color)),
+            };
+        }
+        Ok(camera)
+    }
+}
+
+// a hittable of an object and its material
+type Part = (Arc<dyn Hittable>, Arc<dyn Scatter>);
+
+impl ScaleDesc {
+    fn factors(&self) -> Vec3 {
+        match self {
+            ScaleDesc::Uniform(factor) => Vec3::new(*factor, *factor, *factor),
+            ScaleDesc::Axes(factors) => vec3(factors),
+        }
+    }
+}
+
+impl InstanceDesc {
+    fn placement(&self) -> Placement {
+        let mut placement = Placement::default();
+        if let Some(scale) = &self.scale {
+            placement.scale = scale.factors();
+        }
+        if let Some(rotate) = &self.rotate {
+            placement.rotate = vec3(rotate);
+        }
+        if let Some(translate) = &self.translate {
+            placement.translate = vec3(translate);
+        }
+        placement
+    }
+
+    // where the instance ends up, if it moves
+    fn end_placement(&self) -> Option<Placement> {
+        if self.scale_end.is_none() && self.rotate_end.is_none() && self.translate_end.is_none() {
+            return None;
+        }
+        let mut placement = self.placement();
+        if let Some(scale) = &self.scale_end {
+            placement.scale = scale.factors();
+        }
+        if let Some(rotate) = &self.rotate_end {
+            placement.rotate = vec3(rotate);
+        }
+        if let Some(translate) = &self.translate_end {
+            placement.translate = vec3(translate);
+        }
+        Some(placement)
+    }
+}
+
+impl ObjectDesc {
+    fn instances(&self) -> &[InstanceDesc] {
+        match self {
+            ObjectDesc::Sphere { instances, .. }
+            | ObjectDesc::Quad { instances, .. }
+            | ObjectDesc::Box { instances, .. }
+            | ObjectDesc::Triangle { instances, .. }
+            | ObjectDesc::Disk { instances, .. }
+            | ObjectDesc::Obj { instances, .. }
+            | ObjectDesc::Ply { instances, .. } => instances,
+        }
+    }
+
+    fn density(&self) -> Option<&DensityDesc> {
+        match self {
+            ObjectDesc::Sphere { density, .. }
+            | ObjectDesc::Quad { density, .. }
+            | ObjectDesc::Box { density, .. }
+            | ObjectDesc::Triangle { density, .. }
+            | ObjectDesc::Disk { density, .. }
+            | ObjectDesc::Obj { density, .. }
+            | ObjectDesc::Ply { density, .. } => density.as_ref(),
+        }
+    }
+
+    // quads, triangles and disks enclose nothing
+    fn is_flat(&self) -> bool {
+        matches!(
+            self,
+            ObjectDesc::Quad { .. } | ObjectDesc::Triangle { .. } | ObjectDesc::Disk { .. }
+        )
+    }
+
+    fn material(&self) -> Option<&str> {
+        match self {
+            ObjectDesc::Sphere { material, .. }
+            | ObjectDesc::Quad { material, .. }
+            | ObjectDesc::Box { material, .. }
+            | ObjectDesc::Triangle { material, .. }
+            | ObjectDesc::Disk { material, .. } => Some(material),
+            ObjectDesc::Obj { material, .. } | ObjectDesc::Ply { material, .. } => {
+                material.as_deref()
+            }
+        }
+    }
+
+    // the hittables making up the object, each with its material. model files are
+    // looked up in dir
+    fn build(&self, material: Arc<dyn Scatter>, dir: &Path) -> Result<Vec<Part>> {
+        let hittable: Arc<dyn Hittable> = match self {
+            ObjectDesc::Sphere {
+                center,
+                center_end: Some(center_end),
+                radius,
+                ..
+            } => Arc::new(Sphere::moving(
+                vec3(center),
+                &vec3(center_end),
+                *radius,
+                material.clone(),
+            )),
+            ObjectDesc::Sphere { center, radius, .. } => {
+                Arc::new(Sphere::new(vec3(center), *radius, material.clone()))
+            }
+            ObjectDesc::Quad { q, u, v, .. } => {
+                if !is_positive(vec3(u).cross(&vec3(v)).length()) {
+                    return Err(format!(
+                        "quad sides u and v must not be parallel, not {:?} and {:?}",
+                        u, v
+                    )
+                    .into());
+                }
+                Arc::new(Quad::new(vec3(q), vec3(u), vec3(v), material.clone()))
+            }
+            ObjectDesc::Box { a, b, .. } => {
+                if (0..3).any(|axis| !is_positive((a[axis] - b[axis]).abs())) {
+                    return Err(format!(
+                        "box corners must differ along every axis, not {:?} and {:?}",
+                        a, b
+                    )
+                    .into());
+                }
+                return Ok(Quad::make_box(&vec3(a), &vec3(b), material.clone())
+                    .into_iter()
+                    .map(|side| (Arc::new(side) as Arc<dyn Hittable>, material.clone()))
+                    .collect());
+            }
+            ObjectDesc::Triangle {
+                vertices,
+                normals,
+                uvs,
+                ..
+            } => {
+                let [a, b, c] = vertices.map(|v| vec3(&v));
+                if !is_positive((&b - &a).cross(&(&c - &a)).length()) {
+                    return Err(format!(
+                        "triangle vertices must not lie on a line, not {:?}",
+                        vertices
+                    )
+                    .into());
+                }
+                let mut triangle = Triangle::new([a, b, c], material.clone());
+                if let Some(normals) = normals {
+                    triangle = triangle.with_normals(normals.map(|n| vec3(&n)));
+                }
+                if let Some(uvs) = uvs {
+                    triangle = triangle.with_uvs(uvs.map(|uv| (uv[0], uv[1])));
+                }
+                Arc::new(triangle)
+            }
+            ObjectDesc::Disk {
+                center,
+                normal,
+                radius,
+                ..
+            } => {
+                if !is_positive(vec3(normal).length()) {
+                    return Err(format!("disk normal must not be zero, not {:?}", normal).into());
+                }
+                if !is_positive(*radius) {
+                    return Err(format!("disk radius must be positive, not {}", radius).into());
+                }
+                Arc::new(Disk::new(
+                    vec3(center),
+                    &vec3(normal),
+                    *radius,
+                    material.clone(),
+                ))
+            }
+            ObjectDesc::Obj {
+                file,
+                smooth_normals,
+                ..
+            } => {
+                let meshes = obj::load_obj(&dir.join(file), material, *smooth_normals)?;
+                return Ok(meshes
+                    .into_iter()
+                    .map(|mesh| {
+                        let material = mesh.material().clone();
+                        (Arc::new(mesh) as Arc<dyn Hittable>, material)
+                    })
+                    .collect());
+            }
+            ObjectDesc::Ply { file, .. } => {
+                Arc::new(ply::load_ply(&dir.join(file), material.clone())?)
+            }
+        };
+        Ok(vec![(hittable, material)])
+    }
+}
+
+impl AlbedoDesc {
+    // image files are looked up in dir
+    fn build(&self, dir: &Path) -> Result<Arc<dyn Texture>> {
+        Ok(match self {
+            AlbedoDesc::Color(color) => Arc::new(SolidColor::new(vec3(color))),
+            AlbedoDesc::Texture(TextureDesc::Checker { scale, even, odd }) => {
+                if !(scale.is_finite() && *scale > 0.0) {
+                    return Err(format!("checker scale must be positive, not {}", scale).into());
+                }
+                Arc::new(CheckerTexture::new(
+                    *scale,
+                    even.build(dir)?,
+                    odd.build(dir)?,
+                ))
+            }
+            AlbedoDesc::Texture(TextureDesc::UvChecker {
+                width,
+                height,
+                even,
+                odd,
+            }) => {
+                if *width == 0 || *height == 0 {
+                    return Err("uv checker needs at least one square across and up".into());
+                }
+                Arc::new(UvChecker::new(
+                    *width,
+                    *height,
+                    even.build(dir)?,
+                    odd.build(dir)?,
+                ))
+            }
+            AlbedoDesc::Texture(TextureDesc::Noise {
+                frequency,
+                seed,
+                color,
+            }) => {
+                check_frequency("noise", *frequency)?;
+                let noise = Perlin::new(&mut Sampler::new(*seed));
+                Arc::new(NoiseTexture::new(noise, *frequency, vec3(color)))
+            }
+            AlbedoDesc::Texture(TextureDesc::Marble {
+                frequency,
+                seed,
+                color,
+            }) => {
+                check_frequency("marble", *frequency)?;
+                let noise = Perlin::new(&mut Sampler::new(*seed));
+                Arc::new(MarbleTexture::new(noise, *frequency, vec3(color)))
+            }
+            AlbedoDesc::Texture(TextureDesc::Wood {
+                frequency,
+                seed,
+                light,
+                dark,
+            }) => {
+                check_frequency("wood", *frequency)?;
+                let noise = Perlin::new(&mut Sampler::new(*seed));
+                Arc::new(WoodTexture::new(noise, *frequency, vec3(light), vec3(dark)))
+            }
+            AlbedoDesc::Texture(TextureDesc::Image { file, wrap, filter }) => {
+                let wrap = wrap.as_deref().map_or(Ok(Wrap::default()), str::parse)?;
+                let filter = filter
+                    .as_deref()
+                    .map_or(Ok(Filter::default()), str::parse)?;
+                let texels = decoder::read_image(&dir.join(file))?;
+                Arc::new(ImageTexture::new(texels, wrap, filter))
+            }
+        })
+    }
+}
+
+// finite and above zero, for sizes that would make a shape flat or empty
+fn is_positive(value: f64) -> bool {
+    value.is_finite() && value > 0.0
+}
+
+// the frequency of a noise pattern, which has no pattern at zero
+fn check_frequency(pattern: &str, frequency: f64) -> Result<()> {
+    if !is_positive(frequency) {
+        return Err(format!("{} frequency must be positive, not {}", pattern, frequency).into());
+    }
+    Ok(())
+}
+
+fn check_fraction(name: &str, value: f64) -> Result<()> {
+    if !(0.0..=1.0).contains(&value) {
+        return Err(format!("{} must be between 0 and 1, not {}", name, value).into());
+    }
+    Ok(())
+}
+
+impl MaterialDesc {
+    // whether light going through the inside of the material is absorbed
+    fn absorbs(&self) -> bool {
+        match self {
+            MaterialDesc::Dielectric { absorption, .. } => absorption.iter().any(|&x| x > 0.0),
+            _ => false,
+        }
+    }
+
+    fn build(&self, dir: &Path) -> Result<Arc<dyn Scatter>> {
+        Ok(match self {
+            MaterialDesc::Lambertian { albedo } => {
+                Arc::new(Lambertian::with_texture(albedo.build(dir)?))
+            }
+            MaterialDesc::Metal { albedo, fuzz } => {
+                Arc::new(Metal::with_texture(albedo.build(dir)?, *fuzz))
+            }
+            MaterialDesc::Dielectric {
+                ir,
+                absorption,
+                tint,
+                film,
+            } => {
+                for (name, value) in [("absorption", absorption), ("tint", tint)] {
+                    if value.iter().any(|x| !(x.is_finite() && *x >= 0.0)) {
+                        return Err(
+                            format!("{} must not be negative, not {:?}", name, value).into()
+                        );
+                    }
+                }
+                let dielectric = Dielectric::tinted(ir.build()?, vec3(absorption), vec3(tint));
+                match film {
+                    Some(film) => Arc::new(dielectric.with_film(film.build()?)),
+                    None => Arc::new(dielectric),
+                }
+            }
+            MaterialDesc::Conductor {
+                metal,
+                eta,
+                k,
+                roughness,
+                film,
+            } => {
+                check_fraction("roughness", *roughness)?;
+                let conductor = match (metal, eta, k) {
+                    (Some(metal), None, None) => {
+                        Conductor::preset(metal.parse::<MetalPreset>()?, *roughness)
+                    }
+                    (None, Some(eta), Some(k)) => {
+                        if eta.iter().any(|x| !is_positive(*x)) {
+                            return Err(format!("eta must be positive, not {:?}", eta).into());
+                        }
+                        if k.iter().any(|x| !(x.is_finite() && *x >= 0.0)) {
+                            return Err(format!("k must not be negative, not {:?}", k).into());
+                        }
+                        Conductor::new(vec3(eta), vec3(k), *roughness)
+                    }
+                    _ => return Err("conductor needs either a metal or both eta and k".into()),
+                };
+                match film {
+                    Some(film) => Arc::new(conductor.with_film(film.build()?)),
+                    None => Arc::new(conductor),
+                }
+            }
+            MaterialDesc::RoughDielectric { ir, roughness } => {
+                check_fraction("roughness", *roughness)?;
+                if !is_positive(*ir) {
+                    return Err(format!("ir must be positive, not {}", ir).into());
+                }
+                Arc::new(RoughDielectric::new(*ir, *roughness))
+            }
+            MaterialDesc::Principled {
+                base_color,
+                metallic,
+                roughness,
+                specular,
+                clearcoat,
+                sheen,
+                transmission,
+                ior,
+            } => {
+                for (name, value) in [
+                    ("metallic", metallic),
+                    ("roughness", roughness),
+                    ("specular", specular),
+                    ("clearcoat", clearcoat),
+                    ("sheen", sheen),
+                    ("transmission", transmission),
+                ] {
+                    check_fraction(name, *value)?;
+                }
+                if !(ior.is_finite() && *ior > 0.0) {
+                    return Err(format!("ior must be positive, not {}", ior).into());
+                }
+                let params = PrincipledParams {
+                    metallic: *metallic,
+                    roughness: *roughness,
+                    specular: *specular,
+                    clearcoat: *clearcoat,
+                    sheen: *sheen,
+                    transmission: *transmission,
+                    ior: *ior,
+                };
+                Arc::new(Principled::with_texture(base_color.build(dir)?, params))
+            }
+            MaterialDesc::DiffuseLight { emit } => Arc::new(DiffuseLight::new(vec3(emit))),
+            MaterialDesc::Isotropic { albedo } => {
+                Arc::new(Isotropic::with_texture(albedo.build(dir)?))
+            }
+            MaterialDesc::HenyeyGreenstein { albedo, g } => {
+                if !(*g > -1.0 && *g < 1.0) {
+                    return Err(format!("g must be between -1 and 1, not {}", g).into());
+                }
+                Arc::new(HenyeyGreenstein::with_texture(albedo.build(dir)?, *g))
+            }
+        })
+    }
+}
+
+impl IorDesc {
+    fn build(&self) -> Result<Ior> {
+        let ior = match self {
+            IorDesc::Constant(ior) => Ior::Constant(*ior),
+            IorDesc::Named(name) => name.parse()?,
+            IorDesc::Formula(IorFormulaDesc::Cauchy { a, b }) => Ior::Cauchy { a: *a, b: *b },
+            IorDesc::Formula(IorFormulaDesc::Sellmeier { b, c }) => Ior::Sellmeier { b: *b, c: *c },
+        };
+        // every 10 nanometers of the wavelengths spectral mode traces
+        let mut lambda = LAMBDA_MIN;
+        while lambda <= LAMBDA_MAX {
+            let value = ior.at(lambda);
+            if !(value.is_finite() && value > 0.0) {
+                return Err(format!("ir must be positive, not {} at {} nm", value, lambda).into());
+            }
+            lambda += 10.0;
+        }
+        Ok(ior)
+    }
+}
+
+impl FilmDesc {
+    fn build(&self) -> Result<ThinFilm> {
+        if !(self.thickness.is_finite() && self.thickness >= 0.0) {
+            return Err(format!(
+                "film thickness must not be negative, not {}",
+                self.thickness
+            )
+            .into());
+        }
+        if !(self.ior.is_finite() && self.ior > 0.0) {
+            return Err(format!("film ior must be positive, not {}", self.ior).into());
+        }
+        Ok(ThinFilm::new(self.thickness, self.ior))
+    }
+}
+
+impl DensityDesc {
+    // voxel files are looked up in dir
+    fn build(&self, dir: &Path) -> Result<Medium> {
+        let grid = match self {
+            DensityDesc::Constant(density) => {
+                if !(density.is_finite() && *density > 0.0) {
+                    return Err(format!("density must be positive, not {}", density).into());
+                }
+                return Ok(Medium::Constant(*density));
+            }
+            DensityDesc::Grid(grid) => grid,
+        };
+        for (rate, value) in [
+            ("absorption", grid.absorption),
+            ("scattering", grid.scattering),
+        ] {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(format!("{} must not be negative, not {}", rate, value).into());
+            }
+        }
+        let voxels = match &grid.voxels {
+            VoxelsDesc::Raw { file, resolution } => voxels::read_raw(&dir.join(file), *resolution)?,
+            VoxelsDesc::Noise {
+                resolution,
+                frequency,
+                seed,
+            } => {
+                check_frequency("noise", *frequency)?;
+                let perlin = Perlin::new(&mut Sampler::new(*seed));
+                VoxelGrid::from_noise(*resolution, &perlin, *frequency)?
+            }
+        };
+        Ok(Medium::Grid {
+            grid: Arc::new(voxels),
+            absorption: grid.absorption,
+            scattering: grid.scattering,
+        })
+    }
+}
+
+impl Medium {
+    // the medium filling boundary, scattering light with material
+    fn fill(&self, boundary: Arc<dyn Hittable>, material: Arc<dyn Scatter>) -> Arc<dyn Hittable> {
+        match self {
+            Medium::Constant(density) => {
+                Arc::new(ConstantMedium::new(boundary, *density, material))
+            }
+            Medium::Grid {
+                grid,
+                absorption,
+                scattering,
+            } => Arc::new(GridMedium::new(
+                boundary,
+                grid.clone(),
+                *absorption,
+                *scattering,
+                material,
+            )),
+        }
+    }
+}
+
+// read and build the scene described by the toml file at path
+pub fn load_scene(path: &Path) -> Result<Scene> {
+    let text = fs::read_to_string(path)
+        .map_err(|err| format!("cannot read scene file {}: {}", path.display(), err))?;
+    parse_scene(&text, &path.display().to_string())
+}
+
+// build the scene described by text, name is the file name used in error messages
+pub fn parse_scene(text: &str, name: &str) -> Result<Scene> {
+    let desc: SceneDesc = toml::from_str(text).map_err(|err| {
+        let line = err.span().map_or(String::new(), |span| {
+            format!(":{}", line_of(text, span.start))
+        });
+        format!("{}{}: {}", name, line, err.message())
+    })?;
+
+    // model and image files are relative to the directory of the scene file
+    let dir = Path::new(name).parent().unwrap_or(Path::new(""));
+    let mut materials: HashMap<&str, Arc<dyn Scatter>> = HashMap::new();
+    for (material_name, material) in desc.materials.iter() {
+        let built = material
+            .build(dir)
+            .map_err(|err| format!("{}: material '{}': {}", name, material_name, err))?;
+        materials.insert(material_name, built);
+    }
+    let mut world = Hittables::default();
+    let mut lights = Hittables::default();
+    for object in desc.objects.iter() {
+        // errors point at the line where the object's table starts
+        let line = line_of(text, object.span().start);
+        let material = match object.get_ref().material() {
+            Some(material_name) => materials.get(material_name).cloned().ok_or_else(|| {
+                format!("{}:{}: unknown material '{}'", name, line, material_name)
+            })?,
+            None => Arc::new(Lambertian::new(Color::new(0.8, 0.8, 0.8))),
+        };
+        let absorbs = object
+            .get_ref()
+            .material()
+            .and_then(|material_name| desc.materials.get(material_name))
+            .is_some_and(MaterialDesc::absorbs);
+        if absorbs && object.get_ref().is_flat() {
+            return Err(format!(
+                "{}:{}: a flat object has no inside to absorb light in, use tint instead",
+                name, line
+            )
+            .into());
+        }
+        let mut hittables = object
+            .get_ref()
+            .build(material.clone(), dir)
+            .map_err(|err| format!("{}:{}: {}", name, line, err))?;
+        // the parts of a medium's boundary together enclose it. instances place the
+        // medium with its boundary: a grid turns with the object, and scaled copies
+        // are as opaque as the original
+        let medium = match object.get_ref().density() {
+            Some(density) => Some(
+                density
+                    .build(dir)
+                    .map_err(|err| format!("{}:{}: {}", name, line, err))?,
+            ),
+            None => None,
+        };
+        if let Some(medium) = &medium {
+            let mut boundary = Hittables::default();
+            for (hittable, _) in hittables.iter() {
+                boundary.add(hittable.clone());
+            }
+            hittables = vec![(medium.fill(Arc::new(boundary), material.clone()), material)];
+        }
+        let instances = object.get_ref().instances();
+        if !instances.is_empty() {
+            let mut placed = Vec::new();
+            for instance in instances {
+                let start = instance.placement();
+                let end = instance.end_placement();
+                for (hittable, material) in hittables.iter() {
+                    let transform = match &end {
+                        Some(end) => {
+                            Transform::moving(hittable.clone(), start.clone(), end.clone())
+                        }
+                        None => Transform::new(hittable.clone(), start.matrix()),
+                    }
+                    .map_err(|err| format!("{}:{}: {}", name, line, err))?;
+                    placed.push((Arc::new(transform) as Arc<dyn Hittable>, material.clone()));
+                }
+            }
+            hittables = placed;
+        }
+        for (hittable, material) in hittables {
+            if material.is_emissive() {
+                lights.add(hittable.clone());
+            }
+            world.add(hittable);
+        }
+    }
+    Ok(Scene {
+        world,
+        lights,
+        camera: desc.camera.build(text, name)?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::INFINITY;
+    use crate::{Interval, Point3, Ray};
+
+    const SCENE: &str = r#"
+[camera]
+image_width = 320
+vfov = 20.0
+look_from = [13.0, 2.0, 3.0]
+
+[materials.ground]
+type = "lambertian"
+albedo = [0.5, 0.5, 0.5]
+
+[materials.mirror]
+type = "metal"
+albedo = [0.7, 0.6, 0.5]
+fuzz = 0.0
+
+[[objects]]
+type = "sphere"
+center = [0.0, -1000.0, 0.0]
+radius = 1000.0
+material = "ground"
+
+[[objects]]
+type = "sphere"
+center = [4.0, 1.0, 0.0]
+radius = 1.0
+material = "mirror"
+"#;
+
+    #[test]
+    fn test_parse_scene() {
+        let scene = parse_scene(SCENE, "test.toml").unwrap();
+        assert_eq!(scene.world.objects.len(), 2);
+        assert_eq!(scene.camera.image_width, 320);
+        assert_eq!(scene.camera.vfov, 20.0);
+        assert_eq!(scene.camera.look_from, Point3::new(13.0, 2.0, 3.0));
+        // untouched fields keep the defaults of Camera::new
+        assert_eq!(scene.camera.samples_per_pixel, 100);
+        let ray = Ray {
+            orig: Point3::new(4.0, 5.0, 0.0),
+            dir: Vec3::new(0.0, -1.0, 0.0),
+            time: 0.0,
+        };
+        let rec = scene
+            .world
+            .hit(&ray, &mut Interval::new(0.001, INFINITY))
+            .unwrap();
+        assert!((rec.t - 3.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_example_scenes() {
+        let scene = parse_scene(
+            include_str!("../scenes/three_spheres.toml"),
+            "three_spheres.toml",
+        )
+        .unwrap();
+        assert_eq!(scene.world.objects.len(), 5);
+        let scene = parse_scene(
+            include_str!("../scenes/simple_light.toml"),
+            "simple_light.toml",
+        )
+        .unwrap();
+        assert_eq!(scene.camera.background, Background::None);
+        assert_eq!(scene.lights.objects.len(), 1);
+        let scene = parse_scene(
+            include_str!("../scenes/cornell_spheres.toml"),
+            "cornell_spheres.toml",
+        )
+        .unwrap();
+        assert_eq!(scene.world.objects.len(), 8);
+        assert_eq!(scene.lights.objects.len(), 1);
+        let scene = parse_scene(
+            include_str!("../scenes/cornell_box.toml"),
+            "cornell_box.toml",
+        )
+        .unwrap();
+        // six walls and lamp quads plus two boxes of six sides
+        assert_eq!(scene.world.objects.len(), 6 + 2 * 6);
+        assert_eq!(scene.lights.objects.len(), 1);
+        let scene = parse_scene(
+            include_str!("../scenes/cornell_smoke.toml"),
+            "cornell_smoke.toml",
+        )
+        .unwrap();
+        // the boxes are one medium each
+        assert_eq!(scene.world.objects.len(), 6 + 2);
+        assert_eq!(scene.lights.objects.len(), 1);
+        let scene = parse_scene(
+            include_str!("../scenes/checker_spheres.toml"),
+            "checker_spheres.toml",
+        )
+        .unwrap();
+        assert_eq!(scene.world.objects.len(), 3);
+        let scene = parse_scene(
+            include_str!("../scenes/perlin_spheres.toml"),
+            "perlin_spheres.toml",
+        )
+        .unwrap();
+        assert_eq!(scene.world.objects.len(), 3);
+        let scene = parse_scene(
+            include_str!("../scenes/motion_blur.toml"),
+            "motion_blur.toml",
+        )
+        .unwrap();
+        assert_eq!(scene.world.objects.len(), 4 + 6);
+        assert_eq!(scene.camera.shutter_close, 1.0);
+        let scene = parse_scene(
+            include_str!("../scenes/microfacets.toml"),
+            "microfacets.toml",
+        )
+        .unwrap();
+        assert_eq!(scene.world.objects.len(), 2 + 6);
+        assert_eq!(scene.lights.objects.len(), 1);
+        let scene =
+            parse_scene(include_str!("../scenes/principled.toml"), "principled.toml").unwrap();
+        assert_eq!(scene.world.objects.len(), 2 + 6);
+        let scene = parse_scene(
+            include_str!("../scenes/tinted_glass.toml"),
+            "tinted_glass.toml",
+        )
+        .unwrap();
+        // the ground, three balls and the two spheres of the bubble, and a box of six sides
+        assert_eq!(scene.world.objects.len(), 5 + 6);
+        let scene =
+            parse_scene(include_str!("../scenes/dispersion.toml"), "dispersion.toml").unwrap();
+        assert!(scene.camera.spectral);
+        // the floor, seven strips, the diamond and the prism's two triangles and three quads
+        assert_eq!(scene.world.objects.len(), 1 + 7 + 1 + 5);
+        let scene = parse_scene(
+            include_str!("../scenes/iridescence.toml"),
+            "iridescence.toml",
+        )
+        .unwrap();
+        assert_eq!(scene.world.objects.len(), 7);
+        let scene = load_scene(Path::new("scenes/image_texture.toml")).unwrap();
+        assert_eq!(scene.world.objects.len(), 4);
+        let scene = load_scene(Path::new("scenes/volumes.toml")).unwrap();
+        assert_eq!(scene.world.objects.len(), 4);
+        // the floor, and the ball and lamp meshes of the obj file
+        let scene = load_scene(Path::new("scenes/obj_model.toml")).unwrap();
+        assert_eq!(scene.world.objects.len(), 3);
+        assert_eq!(scene.lights.objects.len(), 1);
+    }
+
+    #[test]
+    fn test_instances() {
+        let text = format!(
+            "{}\n[[objects]]\ntype = \"sphere\"\ncenter = [0.0, 0.0, 0.0]\nradius = 1.0\n\
+             material = \"mirror\"\ninstances = [{{ translate = [10.0, 1.0, 0.0] }},\n\
+             {{ scale = [1.0, 3.0, 1.0], rotate = [90.0, 0.0, 0.0], translate = [20.0, 1.0, 0.0] }}]\n",
+            SCENE
+        );
+        let scene = parse_scene(&text, "test.toml").unwrap();
+        assert_eq!(scene.world.objects.len(), 4);
+        // the second copy is stretched along y and then turned to lie along z
+        let hit_t = |x: f64, z: f64| {
+            let ray = Ray {
+                orig: Point3::new(x, 5.0, z),
+                dir: Vec3::new(0.0, -1.0, 0.0),
+                time: 0.0,
+            };
+            scene
+                .world
+                .hit(&ray, &mut Interval::new(0.001, INFINITY))
+                .map(|rec| rec.t)
+        };
+        assert!((hit_t(10.0, 0.0).unwrap() - 3.0).abs() < 1e-9);
+        assert!((hit_t(20.0, 0.0).unwrap() - 3.0).abs() < 1e-9);
+        let top = 1.0 + (1.0 - (2.5f64 / 3.0).powi(2)).sqrt();
+        assert!((hit_t(20.0, 2.5).unwrap() - (5.0 - top)).abs() < 1e-9);
+        // the round copy is missed, and the ray goes on to the ground
+        assert!(hit_t(10.0, 2.5).unwrap() > 4.9);
+
+        let text = text.replace("scale = [1.0, 3.0, 1.0]", "scale = 0.0");
+        let err = parse_scene(&text, "test.toml").err().unwrap().to_string();
+        assert!(
+            err.starts_with("test.toml:28: transform squashes"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_motion() {
+        let text = format!(
+            "{}\n[[objects]]\ntype = \"sphere\"\ncenter = [10.0, 1.0, 0.0]\n\
+             center_end = [10.0, 1.0, 2.0]\nradius = 1.0\nmaterial = \"mirror\"\n\
+             instances = [{{ translate_end = [10.0, 0.0, 0.0] }}]\n",
+            SCENE.replace("[camera]", "[camera]\nshutter_close = 1.0")
+        );
+        let scene = parse_scene(&text, "test.toml").unwrap();
+        assert_eq!(scene.camera.shutter_close, 1.0);
+        // the sphere moves 2 along z and its instance 10 along x over the frame
+        let hit_at = |x: f64, z: f64, time: f64| {
+            let ray = Ray {
+                orig: Point3::new(x, 5.0, z),
+                dir: Vec3::new(0.0, -1.0, 0.0),
+                time,
+            };
+            scene
+                .world
+                .hit(&ray, &mut Interval::new(0.001, INFINITY))
+                .map(|rec| rec.t)
+        };
+        assert!((hit_at(10.0, 0.0, 0.0).unwrap() - 3.0).abs() < 1e-9);
+        assert!((hit_at(15.0, 1.0, 0.5).unwrap() - 3.0).abs() < 1e-9);
+        assert!((hit_at(20.0, 2.0, 1.0).unwrap() - 3.0).abs() < 1e-9);
+        assert!(hit_at(10.0, 0.0, 1.0).unwrap() > 4.9);
+
+        let text = text.replace("shutter_close = 1.0", "shutter_close = 2.0");
+        let scene = parse_scene(&text, "test.toml").unwrap();
+        assert!(scene.camera.validate().is_err());
+    }
+
+    #[test]
+    fn test_medium() {
+        let text = format!(
+            "{}\n[materials.fog]\ntype = \"isotropic\"\nalbedo = [1.0, 1.0, 1.0]\n\
+             \n[[objects]]\ntype = \"box\"\na = [-1.0, -1.0, -1.0]\nb = [1.0, 1.0, 1.0]\n\
+             material = \"fog\"\ndensity = 1000.0\n\
+             instances = [{{ translate = [10.0, 1.0, 0.0] }}, {{ translate = [20.0, 1.0, 0.0] }}]\n",
+            SCENE
+        );
+        let scene = parse_scene(&text, "test.toml").unwrap();
+        assert_eq!(scene.world.objects.len(), 4);
+        // the medium is so dense that rays scatter right where they enter the boxes
+        for x in [10.0, 20.0] {
+            let ray = Ray {
+                orig: Point3::new(x, 5.0, 0.0),
+                dir: Vec3::new(0.0, -1.0, 0.0),
+                time: 0.0,
+            };
+            let rec = scene
+                .world
+                .hit(&ray, &mut Interval::new(0.001, INFINITY))
+                .unwrap();
+            assert!(rec.point.y() < 2.0 && rec.point.y() > 1.9);
+        }
+
+        let text = text.replace("density = 1000.0", "density = 0.0");
+        let err = parse_scene(&text, "test.toml").err().unwrap().to_string();
+        assert_eq!(err, "test.toml:32: density must be positive, not 0");
+    }
+
+    #[test]
+    fn test_grid_medium() {
+        let text = format!(
+            "{}\n[materials.cloud]\ntype = \"henyey_greenstein\"\nalbedo = [1.0, 1.0, 1.0]\n\
+             g = 0.5\n\n[[objects]]\ntype = \"sphere\"\ncenter = [10.0, 1.0, 0.0]\n\
+             radius = 1.0\nmaterial = \"cloud\"\n\n[objects.density]\n\
+             voxels = {{ type = \"noise\", resolution = [8, 8, 8], frequency = 2.0 }}\n\
+             scattering = 1000.0\n",
+            SCENE
+        );
+        let scene = parse_scene(&text, "test.toml").unwrap();
+        // so dense that a ray through the middle scatters inside the sphere rather than
+        // reaching the ground
+        let ray = Ray {
+            orig: Point3::new(10.0, 5.0, 0.0),
+            dir: Vec3::new(0.0, -1.0, 0.0),
+            time: 0.0,
+        };
+        let rec = scene
+            .world
+            .hit(&ray, &mut Interval::new(0.001, INFINITY))
+            .unwrap();
+        assert!(rec.point.y() > 0.001 && rec.point.y() < 2.0);
+
+        for (from, to, err) in [
+            (
+                "g = 0.5",
+                "g = 1.0",
+                "test.toml: material 'cloud': g must be between -1 and 1, not 1",
+            ),
+            (
+                "scattering = 1000.0",
+                "scattering = -1.0",
+                "test.toml:33: scattering must not be negative, not -1",
+            ),
+            (
+                "frequency = 2.0",
+                "frequency = 0.0",
+                "test.toml:33: noise frequency must be positive, not 0",
+            ),
+            (
+                "type = \"noise\", resolution = [8, 8, 8], frequency = 2.0",
+                "type = \"raw\", file = \"missing.raw\", resolution = [8, 8, 8]",
+                "test.toml:33: cannot read voxel file missing.raw",
+            ),
+        ] {
+            let text = text.replace(from, to);
+            let message = parse_scene(&text, "test.toml").err().unwrap().to_string();
+            assert!(message.starts_with(err), "{}", message);
+        }
+    }
+
+    #[test]
+    fn test_missing_model_reports_line() {
+        let text = format!(
+            "{}\n[[objects]]\ntype = \"obj\"\nfile = \"missing.obj\"\n",
+            SCENE
+        );
+        let err = parse_scene(&text, "scenes/test.toml")
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(
+            err.starts_with("scenes/test.toml:28: cannot read obj file scenes/missing.obj"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_planar_objects() {
+        let text = format!(
+            "{}{}",
+            SCENE,
+            r#"
+[[objects]]
+type = "quad"
+q = [0.0, 0.0, -5.0]
+u = [1.0, 0.0, 0.0]
+v = [0.0, 1.0, 0.0]
+material = "ground"
+
+[[objects]]
+type = "triangle"
+vertices = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]
+uvs = [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0]]
+material = "ground"
+
+[[objects]]
+type = "disk"
+center = [0.0, 3.0, 0.0]
+normal = [0.0, 1.0, 0.0]
+radius = 0.5
+material = "mirror"
+"#
+        );
+        let scene = parse_scene(&text, "test.toml").unwrap();
+        assert_eq!(scene.world.objects.len(), 5);
+        let ray = Ray {
+            orig: Point3::new(0.0, 5.0, 0.0),
+            dir: Vec3::new(0.0, -1.0, 0.0),
+            time: 0.0,
+        };
+        let rec = scene
+            .world
+            .hit(&ray, &mut Interval::new(0.001, INFINITY))
+            .unwrap();
+        assert_eq!(rec.t, 2.0);
+    }
+
+    #[test]
+    fn test_degenerate_objects_report_line() {
+        let text = format!(
+            "{}\n[[objects]]\ntype = \"quad\"\nq = [0.0, 0.0, -5.0]\nu = [1.0, 0.0, 0.0]\n\
+             v = [0.0, 1.0, 0.0]\nmaterial = \"ground\"\n\
+             \n[[objects]]\ntype = \"disk\"\ncenter = [0.0, 3.0, 0.0]\n\
+             normal = [0.0, 1.0, 0.0]\nradius = 0.5\nmaterial = \"mirror\"\n\
+             \n[[objects]]\ntype = \"box\"\na = [0.0, 0.0, 0.0]\nb = [1.0, 1.0, 1.0]\n\
+             material = \"ground\"\n\
+             \n[[objects]]\ntype = \"triangle\"\n\
+             vertices = [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]]\n\
+             material = \"ground\"\n",
+            SCENE
+        );
+        parse_scene(&text, "test.toml").unwrap();
+        for (from, to, err) in [
+            (
+                "v = [0.0, 1.0, 0.0]",
+                "v = [2.0, 0.0, 0.0]",
+                "test.toml:28: quad sides u and v must not be parallel, \
+                 not [1.0, 0.0, 0.0] and [2.0, 0.0, 0.0]",
+            ),
+            (
+                "normal = [0.0, 1.0, 0.0]",
+                "normal = [0.0, 0.0, 0.0]",
+                "test.toml:35: disk normal must not be zero, not [0.0, 0.0, 0.0]",
+            ),
+            (
+                "radius = 0.5",
+                "radius = 0.0",
+                "test.toml:35: disk radius must be positive, not 0",
+            ),
+            (
+                "radius = 0.5",
+                "radius = nan",
+                "test.toml:35: disk radius must be positive, not NaN",
+            ),
+            (
+                "b = [1.0, 1.0, 1.0]",
+                "b = [1.0, 0.0, 1.0]",
+                "test.toml:42: box corners must differ along every axis, \
+                 not [0.0, 0.0, 0.0] and [1.0, 0.0, 1.0]",
+            ),
+            (
+                "[0.0, 1.0, 0.0]]",
+                "[2.0, 0.0, 0.0]]",
+                "test.toml:48: triangle vertices must not lie on a line, \
+                 not [[0.0, 0.0, 0.0], [1.0, 0.0, 0.0], [2.0, 0.0, 0.0]]",
+            ),
+        ] {
+            let text = text.replace(from, to);
+            let message = parse_scene(&text, "test.toml").err().unwrap().to_string();
+            assert_eq!(message, err);
+        }
+    }
+
+    #[test]
+    fn test_background() {
+        let text = SCENE.replace("vfov = 20.0", "vfov = 20.0\nbackground = [0.1, 0.2, 0.3]");
+        let scene = parse_scene(&text, "test.toml").unwrap();
+        assert_eq!(
+            scene.camera.background,
+            Background::Solid(Color::new(0.1, 0.2, 0.3))
+        );
+        let text = SCENE.replace("vfov = 20.0", "vfov = 20.0\nbackground = \"sky\"");
+        let err = parse_scene(&text, "test.toml").err().unwrap().to_string();
+        assert!(
+            err.starts_with("test.toml:5: unknown background"),
+            "{}",
+            err
+        );
+    }
+
+    #[test]
+    fn test_microfacet_materials() {
+        let text = format!(
+            "{}\n[materials.gold]\ntype = \"conductor\"\nmetal = \"gold\"\nroughness = 0.3\n\
+             \n[materials.nickel]\ntype = \"conductor\"\neta = [1.98, 1.92, 1.78]\n\
+             k = [3.74, 3.41, 3.03]\n\n[materials.frosted]\ntype = \"rough_dielectric\"\n\
+             ir = 1.5\nroughness = 0.2\n",
+            SCENE
+        );
+        parse_scene(&text, "test.toml").unwrap();
+
+        for (from, to, err) in [
+            (
+                "roughness = 0.3",
+                "roughness = 1.5",
+                "test.toml: material 'gold': roughness must be between 0 and 1, not 1.5",
+            ),
+            (
+                "metal = \"gold\"",
+                "metal = \"brass\"",
+                "test.toml: material 'gold': unknown metal 'brass'",
+            ),
+            (
+                "metal = \"gold\"",
+                "metal = \"gold\"\neta = [1.0, 1.0, 1.0]",
+                "test.toml: material 'gold': conductor needs either a metal or both eta and k",
+            ),
+            (
+                "k = [3.74, 3.41, 3.03]",
+                "",
+                "test.toml: material 'nickel': conductor needs either a metal or both eta and k",
+            ),
+            (
+                "eta = [1.98, 1.92, 1.78]",
+                "eta = [1.98, 0.0, 1.78]",
+                "test.toml: material 'nickel': eta must be positive, not [1.98, 0.0, 1.78]",
+            ),
+            (
+                "k = [3.74, 3.41, 3.03]",
+                "k = [3.74, -3.41, 3.03]",
+                "test.toml: material 'nickel': k must not be negative, not [3.74, -3.41, 3.03]",
+            ),
+            (
+                "ir = 1.5\nroughness = 0.2",
+                "ir = 0.0\nroughness = 0.2",
+                "test.toml: material 'frosted': ir must be positive, not 0",
+            ),
+        ] {
+            let text = text.replace(from, to);
+            let message = parse_scene(&text, "test.toml").err().unwrap().to_string();
+            assert!(message.starts_with(err), "{}", message);
+        }
+    }
+
+    #[test]
+    fn test_tinted_dielectric() {
+        let text = format!(
+            "{}\n[materials.bottle]\ntype = \"dielectric\"\nir = 1.5\n\
+             absorption = [0.5, 0.1, 0.5]\ntint = [0.9, 1.0, 0.9]\n",
+            SCENE
+        );
+        parse_scene(&text, "test.toml").unwrap();
+        let text = text.replace(
+            "absorption = [0.5, 0.1, 0.5]",
+            "absorption = [0.5, -0.1, 0.5]",
+        );
+        let err = parse_scene(&text, "test.toml").err().unwrap().to_string();
+        assert_eq!(
+            err,
+            "test.toml: material 'bottle': absorption must not be negative, not [0.5, -0.1, 0.5]"
+        );
+
+        // a pane of the glass has no inside, it can only be tinted
+        let pane = format!(
+            "{}
+[materials.pane]
+type = \"dielectric\"
+ir = 1.5
+             absorption = [0.5, 0.1, 0.5]
+
+[[objects]]
+type = \"quad\"
+             q = [0.0, 0.0, 0.0]
+u = [1.0, 0.0, 0.0]
+v = [0.0, 1.0, 0.0]
+material = \"pane\"
+",
+            SCENE
+        );
+        let line = SCENE.lines().count() + 7;
+        let err = parse_scene(&pane, "test.toml").err().unwrap().to_string();
+        assert_eq!(
+            err,
+            format!(
+                "test.toml:{}: a flat object has no inside to absorb light in, use tint instead",
+                line
+            )
+        );
+        let pane = pane.replace("absorption = [0.5, 0.1, 0.5]", "tint = [0.5, 0.9, 0.5]");
+        parse_scene(&pane, "test.toml").unwrap();
+    }
+
+    #[test]
+    fn test_dispersive_dielectric() {
+        let text = format!(
+            "{}\n[materials.diamond]\ntype = \"dielectric\"\nir = \"diamond\"\n\
+             \n[materials.crown]\ntype = \"dielectric\"\n\
+             ir = {{ type = \"cauchy\", a = 1.5046, b = 0.0042 }}\n",
+            SCENE.replace("[camera]", "[camera]\nspectral = true")
+        );
+        let scene = parse_scene(&text, "test.toml").unwrap();
+        assert!(scene.camera.spectral);
+
+        for (from, to, err) in [
+            (
+                "ir = \"diamond\"",
+                "ir = \"ruby\"",
+                "test.toml: material 'diamond': unknown glass 'ruby'",
+            ),
+            (
+                "a = 1.5046, b = 0.0042",
+                "a = -1.5, b = 0.0042",
+                "test.toml: material 'crown': ir must be positive, not -1.46",
+            ),
+        ] {
+            let text = text.replace(from, to);
+            let message = parse_scene(&text, "test.toml").err().unwrap().to_string();
+            assert!(message.starts_with(err), "{}", message);
+        }
+    }
+
+    #[test]
+    fn test_principled_material() {
+        let text = format!(
+            "{}\n[materials.paint]\ntype = \"principled\"\nbase_color = [0.8, 0.1, 0.1]\n\
+             metallic = 0.2\nclearcoat = 1.0\n\n[materials.tinted_glass]\n\
+             type = \"principled\"\nbase_color = {{ type = \"noise\", frequency = 4.0 }}\n\
+             transmission = 1.0\nroughness = 0.0\nior = 1.33\n",
+            SCENE
+        );
+        parse_scene(&text, "test.toml").unwrap();
+
+        for (from, to, err) in [
+            (
+                "clearcoat = 1.0",
+                "clearcoat = 2.0",
+                "test.toml: material 'paint': clearcoat must be between 0 and 1, not 2",
+            ),
+            (
+                "metallic = 0.2",
+                "metallic = -0.2",
+                "test.toml: material 'paint': metallic must be between 0 and 1, not -0.2",
+            ),
+            (
+                "ior = 1.33",
+                "ior = 0.0",
+                "test.toml: material 'tinted_glass': ior must be positive, not 0",
+            ),
+        ] {
+            let text = text.replace(from, to);
+            let message = parse_scene(&text, "test.toml").err().unwrap().to_string();
+            assert!(message.starts_with(err), "{}", message);
+        }
+    }
+
+    #[test]
+    fn test_thin_films() {
+        let text = format!(
+            "{}\n[materials.bubble]\ntype = \"dielectric\"\nir = 1.0\n\
+             film = {{ thickness = 350.0, ior = 1.33 }}\n\
+             \n[materials.anodised]\ntype = \"conductor\"\nmetal = \"aluminium\"\n\
+             roughness = 0.2\nfilm = {{ thickness = 120.0, ior = 1.65 }}\n",
+            SCENE
+        );
+        parse_scene(&text, "test.toml").unwrap();
+
+        for (from, to, err) in [
+            (
+                "thickness = 350.0",
+                "thickness = -350.0",
+                "test.toml: material 'bubble': film thickness must not be negative, not -350",
+            ),
+            (
+                "ior = 1.65",
+                "ior = 0.0",
+                "test.toml: material 'anodised': film ior must be positive, not 0",
+            ),
+        ] {
+            let text = text.replace(from, to);
+            let message = parse_scene(&text, "test.toml").err().unwrap().to_string();
+            assert_eq!(message, err);
+        }
+        let text = text.replace("ior = 1.33 }", "ior = 1.33, color = 2 }");
+        assert!(parse_scene(&text, "test.toml").is_err());
+    }
+
+    #[test]
+    fn test_unknown_material_reports_line() {
+        let text = SCENE.replace("material = \"mirror\"", "material = \"gold\"");
+        let err = parse_scene(&text, "test.toml").err().unwrap().to_string();
+        assert_eq!(err, "test.toml:22: unknown material 'gold'");
+    }
+
+    #[test]
+    fn test_syntax_error_reports_line() {
+        let text = SCENE.replace("radius = 1.0", "radius = ");
+        let err = parse_scene(&text, "test.toml").err().unwrap().to_string();
+        assert!(err.starts_with("test.toml:25: "), "{}", err);
+    }
+
+    #[test]
+    fn test_unknown_field_reports_line() {
+        let text = SCENE.replace("fuzz = 0.0", "fuzz = 0.0\nshininess = 2.0");
+        let err = parse_scene(&text, "test.toml").err().unwrap().to_string();
+        assert!(err.starts_with("test.toml:11: "), "{}", err);
+        assert!(err.contains("shininess"), "{}", err);
+    }
+
+    #[test]
+    fn test_textured_albedo() {
+        let text = SCENE.replace(
+            "albedo = [0.5, 0.5, 0.5]",
+            "albedo = { type = \"checker\", scale = 0.0, even = [0, 0, 0], odd = [1, 1, 1] }",
+        );
+        let err = parse_scene(&text, "test.toml").err().unwrap().to_string();
+        assert_eq!(
+            err,
+            "test.toml: material 'ground': checker scale must be positive, not 0"
+        );
+        for (albedo, expected) in [
+            (
+                "type = \"noise\", frequency = 0.0",
+                "noise frequency must be positive, not 0",
+            ),
+            (
+                "type = \"marble\", frequency = -4.0",
+                "marble frequency must be positive, not -4",
+            ),
+            (
+                "type = \"wood\", frequency = nan, light = [1, 1, 1], dark = [0, 0, 0]",
+                "wood frequency must be positive, not NaN",
+            ),
+        ] {
+            let texture = text.replace(
+                "type = \"checker\", scale = 0.0, even = [0, 0, 0], odd = [1, 1, 1]",
+                albedo,
+            );
+            let err = parse_scene(&texture, "test.toml")
+                .err()
+                .unwrap()
+                .to_string();
+            assert_eq!(err, format!("test.toml: material 'ground': {}", expected));
+        }
+        let image = text.replace(
+            "type = \"checker\", scale = 0.0, even = [0, 0, 0], odd = [1, 1, 1]",
+            "type = \"image\", file = \"missing.png\", wrap = \"tile\"",
+        );
+        let err = parse_scene(&image, "test.toml").err().unwrap().to_string();
+        assert_eq!(
+            err,
+            "test.toml: material 'ground': unknown wrap 'tile', expected repeat, clamp or mirror"
+        );
+        let text = text.replace("scale = 0.0", "scale = 2.0");
+        let scene = parse_scene(&text, "test.toml").unwrap();
+        let ray = Ray {
+            orig: Point3::new(3.0, 5.0, 3.0),
+            dir: Vec3::new(0.0, -1.0, 0.0),
+            time: 0.0,
+        };
+        let rec = scene
+            .world
+            .hit(&ray, &mut Interval::new(0.001, INFINITY))
+            .unwrap();
+        // the ground is just below y = 0 there, in cells 1, -1 and 1, which are odd
+        let scattered = rec
+            .material
+            .scatter(&ray, &rec, &mut Sampler::new(1))
+            .unwrap();
+        assert_eq!(scattered.attenuation, Color::new(1.0, 1.0, 1.0));
+    }
+}
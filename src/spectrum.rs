@@ -0,0 +1,325 @@
+use crate::Color;
+use crate::Vec3;
+use std::str::FromStr;
+
+// range of wavelengths in nanometers the spectral mode samples, that of the cie color
+// matching functions
+pub const LAMBDA_MIN: f64 = 360.0;
+pub const LAMBDA_MAX: f64 = 830.0;
+
+// wavelength of the yellow sodium d line, where the ior of a dispersive glass is looked
+// up when rendering in rgb
+pub const LAMBDA_D: f64 = 587.6;
+
+// integrals of the fitted x, y and z color matching functions over the range
+const CMF_INTEGRALS: [f64; 3] = [106.765_818_6, 106.922_074_5, 106.875_004_9];
+
+// the wavelengths a camera ray is traced at in spectral mode: a hero wavelength drawn
+// at random and two more spread evenly over the range from it. in spectral mode the
+// three components of the colors along the ray are the light at these wavelengths
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Wavelengths {
+    lambda: [f64; 3],
+    // set once the ray passed through a dispersive material, which can only send
+    // the hero wavelength on
+    secondary_terminated: bool,
+}
+
+impl Wavelengths {
+    // wavelengths for u, a random number in [0, 1)
+    pub fn sample(u: f64) -> Self {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let hero = LAMBDA_MIN + u * range;
+        let lambda = [0.0, 1.0, 2.0].map(|i| {
+            let lambda = hero + i * range / 3.0;
+            if lambda > LAMBDA_MAX {
+                lambda - range
+            } else {
+                lambda
+            }
+        });
+        Self {
+            lambda,
+            secondary_terminated: false,
+        }
+    }
+
+    pub fn hero(&self) -> f64 {
+        self.lambda[0]
+    }
+
+    pub fn terminate_secondary(&mut self) {
+        self.secondary_terminated = true;
+    }
+
+    pub fn secondary_terminated(&self) -> bool {
+        self.secondary_terminated
+    }
+
+    // values of a function of the wavelength in nanometers at the wavelengths
+    pub fn map(&self, f: impl Fn(f64) -> f64) -> Color {
+        let [a, b, c] = self.lambda.map(f);
+        Color::new(a, b, c)
+    }
+
+    // the values at the wavelengths of a smooth spectrum with the given rgb color, a
+    // reflectance for colors up to 1 and the spectrum of a light for brighter ones
+    pub fn spectrum_of(&self, rgb: &Color) -> Color {
+        self.map(|lambda| rgb_to_spectrum(rgb, lambda))
+    }
+
+    // linear rgb color of the light with the given values at the wavelengths, each
+    // wavelength an unbiased sample of the whole spectrum, so that averaged over
+    // wavelengths spectrum_of and rgb_of give back the rgb color of a flat spectrum
+    pub fn rgb_of(&self, values: &Color) -> Color {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let xyz = self
+            .lambda
+            .iter()
+            .zip([values.x(), values.y(), values.z()])
+            .fold(Vec3::default(), |xyz, (lambda, value)| {
+                xyz + cie_xyz(*lambda) * (value * range / 3.0)
+            });
+        let xyz = &xyz / CMF_INTEGRALS[1];
+        let white = Vec3::new(
+            CMF_INTEGRALS[0] / CMF_INTEGRALS[1],
+            1.0,
+            CMF_INTEGRALS[2] / CMF_INTEGRALS[1],
+        );
+        // balanced so that the flat spectrum of equal energy is white
+        let balance = xyz_to_rgb(&white);
+        let rgb = xyz_to_rgb(&xyz);
+        Color::new(
+            rgb.x() / balance.x(),
+            rgb.y() / balance.y(),
+            rgb.z() / balance.z(),
+        )
+    }
+}
+
+// linear srgb of a cie xyz color
+fn xyz_to_rgb(xyz: &Vec3) -> Color {
+    let (x, y, z) = (xyz.x(), xyz.y(), xyz.z());
+    Color::new(
+        3.240_454_2 * x - 1.537_138_5 * y - 0.498_531_4 * z,
+        -0.969_266 * x + 1.876_010_8 * y + 0.041_556 * z,
+        0.055_643_4 * x - 0.204_025_9 * y + 1.057_225_2 * z,
+    )
+}
+
+// the cie 1931 color matching functions at lambda nanometers, as fitted by piecewise
+// gaussians (wyman, sloan and shirley 2013)
+pub fn cie_xyz(lambda: f64) -> Vec3 {
+    let g = |mu: f64, sigma_below: f64, sigma_above: f64| {
+        let sigma = if lambda < mu {
+            sigma_below
+        } else {
+            sigma_above
+        };
+        (-0.5 * ((lambda - mu) / sigma).powi(2)).exp()
+    };
+    Vec3::new(
+        1.056 * g(599.8, 37.9, 31.0) + 0.362 * g(442.0, 16.0, 26.7) - 0.065 * g(501.1, 20.4, 26.2),
+        0.821 * g(568.8, 46.9, 40.5) + 0.286 * g(530.9, 16.3, 31.1),
+        1.217 * g(437.0, 11.8, 36.0) + 0.681 * g(459.0, 26.0, 13.8),
+    )
+}
+
+// smits' basis spectra for turning rgb into reflectance spectra, sampled at ten
+// wavelengths evenly spread from 380 to 720 nanometers
+const SMITS_MIN: f64 = 380.0;
+const SMITS_MAX: f64 = 720.0;
+const SMITS_WHITE: [f64; 10] = [
+    1.0000, 1.0000, 0.9999, 0.9993, 0.9992, 0.9998, 1.0000, 1.0000, 1.0000, 1.0000,
+];
+const SMITS_CYAN: [f64; 10] = [
+    0.9710, 0.9426, 1.0007, 1.0007, 1.0007, 1.0007, 0.1564, 0.0000, 0.0000, 0.0000,
+];
+const SMITS_MAGENTA: [f64; 10] = [
+    1.0000, 1.0000, 0.9685, 0.2229, 0.0000, 0.0458, 0.8369, 1.0000, 1.0000, 0.9959,
+];
+const SMITS_YELLOW: [f64; 10] = [
+    0.0001, 0.0000, 0.1088, 0.6651, 1.0000, 1.0000, 0.9996, 0.9586, 0.9685, 0.9840,
+];
+const SMITS_RED: [f64; 10] = [
+    0.1012, 0.0515, 0.0000, 0.0000, 0.0000, 0.0000, 0.8325, 1.0149, 1.0149, 1.0149,
+];
+const SMITS_GREEN: [f64; 10] = [
+    0.0000, 0.0000, 0.0273, 0.7937, 1.0000, 0.9418, 0.1719, 0.0000, 0.0000, 0.0025,
+];
+const SMITS_BLUE: [f64; 10] = [
+    1.0000, 1.0000, 0.8916, 0.3323, 0.0000, 0.0000, 0.0003, 0.0369, 0.0483, 0.0496,
+];
+
+// a basis spectrum at lambda, interpolated between its samples and held flat beyond
+// the first and last
+fn basis(spectrum: &[f64; 10], lambda: f64) -> f64 {
+    let x = ((lambda - SMITS_MIN) / (SMITS_MAX - SMITS_MIN) * 9.0).clamp(0.0, 9.0);
+    let i = (x as usize).min(8);
+    let t = x - i as f64;
+    spectrum[i] * (1.0 - t) + spectrum[i + 1] * t
+}
+
+// value at lambda of the spectrum smits' method builds for rgb: as much white as the
+// smallest channel, then as much of the mixed color of the two largest as the middle
+// one has over it, then the largest color for the rest
+fn rgb_to_spectrum(rgb: &Color, lambda: f64) -> f64 {
+    let (r, g, b) = (rgb.x(), rgb.y(), rgb.z());
+    let at = |spectrum: &[f64; 10]| basis(spectrum, lambda);
+    if r <= g && r <= b {
+        if g <= b {
+            r * at(&SMITS_WHITE) + (g - r) * at(&SMITS_CYAN) + (b - g) * at(&SMITS_BLUE)
+        } else {
+            r * at(&SMITS_WHITE) + (b - r) * at(&SMITS_CYAN) + (g - b) * at(&SMITS_GREEN)
+        }
+    } else if g <= r && g <= b {
+        if r <= b {
+            g * at(&SMITS_WHITE) + (r - g) * at(&SMITS_MAGENTA) + (b - r) * at(&SMITS_BLUE)
+        } else {
+            g * at(&SMITS_WHITE) + (b - g) * at(&SMITS_MAGENTA) + (r - b) * at(&SMITS_RED)
+        }
+    } else if r <= g {
+        b * at(&SMITS_WHITE) + (r - b) * at(&SMITS_YELLOW) + (g - r) * at(&SMITS_GREEN)
+    } else {
+        b * at(&SMITS_WHITE) + (g - b) * at(&SMITS_YELLOW) + (r - g) * at(&SMITS_RED)
+    }
+}
+
+// index of refraction, constant or changing with the wavelength, which splits white
+// light into its colors. wavelengths of the formulas are in micrometers
+#[derive(Debug, Clone, PartialEq)]
+pub enum Ior {
+    Constant(f64),
+    // n = a + b / lambda^2
+    Cauchy { a: f64, b: f64 },
+    // n^2 = 1 + sum of b lambda^2 / (lambda^2 - c)
+    Sellmeier { b: [f64; 3], c: [f64; 3] },
+}
+
+impl Ior {
+    // the index at lambda nanometers
+    pub fn at(&self, lambda: f64) -> f64 {
+        let micrometers = lambda / 1000.0;
+        let lambda2 = micrometers * micrometers;
+        match self {
+            Ior::Constant(ior) => *ior,
+            Ior::Cauchy { a, b } => a + b / lambda2,
+            Ior::Sellmeier { b, c } => (1.0
+                + b.iter()
+                    .zip(c)
+                    .map(|(b, c)| b * lambda2 / (lambda2 - c))
+                    .sum::<f64>())
+            .sqrt(),
+        }
+    }
+
+    pub fn is_dispersive(&self) -> bool {
+        !matches!(self, Ior::Constant(_))
+    }
+}
+
+// common glasses and gems by name: bk7 crown glass, sf11 dense flint glass, fused
+// silica and diamond
+impl FromStr for Ior {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let (b, c) = match s.to_ascii_lowercase().as_str() {
+            "bk7" => (
+                [1.039_612_12, 0.231_792_344, 1.010_469_45],
+                [0.006_000_698_67, 0.020_017_914_4, 103.560_653],
+            ),
+            "sf11" => (
+                [1.737_596_95, 0.313_747_346, 1.898_781_01],
+                [0.013_188_707, 0.062_306_814_2, 155.236_29],
+            ),
+            "fused_silica" | "fused-silica" => (
+                [0.696_166_3, 0.407_942_6, 0.897_479_4],
+                [0.004_679_148_26, 0.013_512_063_1, 97.934_002_5],
+            ),
+            "diamond" => ([0.3306, 4.3356, 0.0], [0.030_625, 0.011_236, 0.0]),
+            _ => {
+                return Err(format!(
+                    "unknown glass '{}', expected bk7, sf11, fused_silica or diamond",
+                    s
+                ))
+            }
+        };
+        Ok(Ior::Sellmeier { b, c })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color_matching_integrals() {
+        let steps = 4700;
+        let step = (LAMBDA_MAX - LAMBDA_MIN) / steps as f64;
+        let sum = (0..steps).fold(Vec3::default(), |sum, i| {
+            sum + cie_xyz(LAMBDA_MIN + (i as f64 + 0.5) * step)
+        }) * step;
+        for (integral, expected) in [sum.x(), sum.y(), sum.z()].iter().zip(CMF_INTEGRALS) {
+            assert!(
+                (integral - expected).abs() < 1e-3,
+                "{} {}",
+                integral,
+                expected
+            );
+        }
+    }
+
+    #[test]
+    fn test_rgb_round_trip() {
+        // averaged over the wavelengths, colors come back close to what they were
+        let n = 20000;
+        for rgb in [
+            Color::new(1.0, 1.0, 1.0),
+            Color::new(0.5, 0.5, 0.5),
+            Color::new(0.8, 0.2, 0.1),
+            Color::new(0.1, 0.6, 0.3),
+            Color::new(0.2, 0.3, 0.9),
+        ] {
+            let sum = (0..n).fold(Color::default(), |sum, i| {
+                let wavelengths = Wavelengths::sample((i as f64 + 0.5) / n as f64);
+                sum + wavelengths.rgb_of(&wavelengths.spectrum_of(&rgb))
+            });
+            let average = &sum / n as f64;
+            let tolerance = if rgb.x() == rgb.y() { 1e-3 } else { 0.1 };
+            assert!(
+                (&average - &rgb).length() < tolerance,
+                "{:?} {:?}",
+                rgb,
+                average
+            );
+        }
+    }
+
+    #[test]
+    fn test_wavelengths() {
+        let wavelengths = Wavelengths::sample(0.9);
+        assert_eq!(
+            wavelengths.hero(),
+            LAMBDA_MIN + 0.9 * (LAMBDA_MAX - LAMBDA_MIN)
+        );
+        assert!(wavelengths
+            .lambda
+            .iter()
+            .all(|lambda| (LAMBDA_MIN..=LAMBDA_MAX).contains(lambda)));
+    }
+
+    #[test]
+    fn test_ior() {
+        // bk7 is 1.5168 at the d line, and blue light bends more than red
+        let bk7: Ior = "BK7".parse().unwrap();
+        assert!((bk7.at(LAMBDA_D) - 1.5168).abs() < 1e-4);
+        assert!(bk7.at(450.0) > bk7.at(650.0));
+        let diamond: Ior = "diamond".parse().unwrap();
+        assert!((diamond.at(LAMBDA_D) - 2.417).abs() < 2e-3);
+        let cauchy = Ior::Cauchy { a: 1.5, b: 0.01 };
+        assert!((cauchy.at(500.0) - 1.54).abs() < 1e-12);
+        assert!(!Ior::Constant(1.5).is_dispersive());
+        assert!("glass".parse::<Ior>().is_err());
+    }
+}
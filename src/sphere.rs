@@ -1,37 +1,71 @@
+use crate::aabb::Aabb;
 use crate::interval::Interval;
+use crate::onb::{random_to_sphere, Onb};
+use crate::sampler::Sampler;
+use crate::utils::{INFINITY, PI};
 use crate::HitRecord;
 use crate::Hittable;
 use crate::Point3;
 use crate::Ray;
 use crate::Scatter;
-use std::rc::Rc;
+use crate::Vec3;
+use std::sync::Arc;
 
 #[derive(Clone)]
 pub struct Sphere {
+    // center at time 0, it moves by motion until time 1
     pub center: Point3,
     pub radius: f64,
-    material: Rc<dyn Scatter>,
+    motion: Vec3,
+    material: Arc<dyn Scatter>,
 }
 
 impl Sphere {
-    pub fn new(center: Point3, radius: f64, material: Rc<dyn Scatter>) -> Self {
+    pub fn new(center: Point3, radius: f64, material: Arc<dyn Scatter>) -> Self {
         Sphere {
             center,
             radius,
+            motion: Vec3::default(),
             material,
         }
     }
+
+    // sphere moving in a straight line from center0 at time 0 to center1 at time 1
+    pub fn moving(
+        center0: Point3,
+        center1: &Point3,
+        radius: f64,
+        material: Arc<dyn Scatter>,
+    ) -> Self {
+        Sphere {
+            motion: center1 - &center0,
+            ..Sphere::new(center0, radius, material)
+        }
+    }
+
+    fn center_at(&self, time: f64) -> Point3 {
+        &self.center + &self.motion * time
+    }
+
+    // surface coordinates of a point p on the unit sphere: u is the angle around the y
+    // axis starting from -x, v the angle from -y to +y, both scaled to [0, 1]
+    fn uv(p: &Point3) -> (f64, f64) {
+        let theta = (-p.y()).clamp(-1.0, 1.0).acos();
+        let phi = (-p.z()).atan2(p.x()) + PI;
+        (phi / (2.0 * PI), theta / PI)
+    }
 }
 
 impl Hittable for Sphere {
     fn hit(&self, ray: &Ray, ray_t: &mut Interval) -> Option<HitRecord> {
-        let oc = &ray.orig - &self.center;
+        let center = self.center_at(ray.time);
+        let oc = &ray.orig - &center;
         let a = ray.dir.dot(&ray.dir);
         let half_b = oc.dot(&ray.dir);
         let c = oc.dot(&oc) - self.radius * self.radius;
         let discriminant = half_b * half_b - a * c;
         if discriminant < 0.0 {
-            return None;
+            None
         } else {
             // find the nearest root that lies in the acceptable range
             let mut root = (-half_b - discriminant.sqrt()) / a;
@@ -44,10 +78,110 @@ impl Hittable for Sphere {
             let mut hit_record = HitRecord::new();
             hit_record.t = root;
             hit_record.point = ray.at(root);
-            let outward_normal = (&hit_record.point - &self.center) / self.radius;
-            hit_record.set_face_normal(&ray, &outward_normal);
+            let outward_normal = (&hit_record.point - &center) / self.radius;
+            hit_record.set_face_normal(ray, &outward_normal);
+            (hit_record.u, hit_record.v) = Self::uv(&outward_normal);
             hit_record.material = self.material.clone();
             Some(hit_record)
         }
     }
+
+    fn bounding_box(&self) -> Aabb {
+        // the box covers the whole path of the sphere
+        let rvec = Vec3::new(self.radius, self.radius, self.radius);
+        let end = self.center_at(1.0);
+        Aabb::enclosing(
+            &Aabb::from_points(&(&self.center - &rvec), &(&self.center + &rvec)),
+            &Aabb::from_points(&(&end - &rvec), &(&end + &rvec)),
+        )
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        let ray = Ray {
+            orig: origin.clone(),
+            dir: direction.clone(),
+            time,
+        };
+        if self
+            .hit(&ray, &mut Interval::new(0.001, INFINITY))
+            .is_none()
+        {
+            return 0.0;
+        }
+        let distance_squared = (&self.center_at(time) - origin).length_squared();
+        let radius_squared = self.radius * self.radius;
+        if distance_squared <= radius_squared {
+            // from inside the sphere every direction is sampled uniformly
+            return 1.0 / (4.0 * PI);
+        }
+        let cos_theta_max = (1.0 - radius_squared / distance_squared).sqrt();
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+        1.0 / solid_angle
+    }
+
+    // uniform direction within the cone of directions from origin that hit the sphere
+    fn random(&self, origin: &Point3, time: f64, sampler: &mut Sampler) -> Vec3 {
+        let direction = &self.center_at(time) - origin;
+        let distance_squared = direction.length_squared();
+        if distance_squared <= self.radius * self.radius {
+            return Vec3::random_unit_vec3(sampler);
+        }
+        let uvw = Onb::new(&direction);
+        uvw.transform(&random_to_sphere(self.radius, distance_squared, sampler))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lambertian;
+
+    #[test]
+    fn test_moving_sphere() {
+        let sphere = Sphere::moving(
+            Point3::new(0.0, 0.0, 0.0),
+            &Point3::new(2.0, 0.0, 0.0),
+            0.5,
+            Arc::new(Lambertian::default()),
+        );
+        // a ray down the y axis at x = 1 only meets the sphere half way through its path
+        let hit_at = |time: f64| {
+            let ray = Ray {
+                orig: Point3::new(1.0, 5.0, 0.0),
+                dir: Vec3::new(0.0, -1.0, 0.0),
+                time,
+            };
+            sphere.hit(&ray, &mut Interval::new(0.001, INFINITY))
+        };
+        assert!(hit_at(0.0).is_none());
+        assert!(hit_at(1.0).is_none());
+        let rec = hit_at(0.5).unwrap();
+        assert!((rec.t - 4.5).abs() < 1e-9);
+        assert_eq!(rec.normal, Vec3::new(0.0, 1.0, 0.0));
+
+        let bbox = sphere.bounding_box();
+        assert_eq!((bbox.x.min, bbox.x.max), (-0.5, 2.5));
+        assert_eq!((bbox.y.min, bbox.y.max), (-0.5, 0.5));
+
+        // sampled as a light, the sphere is where it is at the time of the ray
+        let origin = Point3::new(1.0, 5.0, 0.0);
+        let mut sampler = Sampler::new(2);
+        for time in [0.0, 0.5, 1.0] {
+            for _ in 0..20 {
+                let direction = sphere.random(&origin, time, &mut sampler);
+                let ray = Ray {
+                    orig: origin.clone(),
+                    dir: direction.clone(),
+                    time,
+                };
+                assert!(sphere
+                    .hit(&ray, &mut Interval::new(0.001, INFINITY))
+                    .is_some());
+                assert!(sphere.pdf_value(&origin, &direction, time) > 0.0);
+            }
+        }
+        let down = Vec3::new(0.0, -1.0, 0.0);
+        assert_eq!(sphere.pdf_value(&origin, &down, 0.0), 0.0);
+        assert!(sphere.pdf_value(&origin, &down, 0.5) > 0.0);
+    }
 }
@@ -0,0 +1,366 @@
+use crate::decoder::Texels;
+use crate::perlin::Perlin;
+use crate::traits::Texture;
+use crate::utils::PI;
+use crate::Color;
+use crate::Point3;
+use std::str::FromStr;
+use std::sync::Arc;
+
+// the same color everywhere
+#[derive(Default, Debug, Clone)]
+pub struct SolidColor {
+    albedo: Color,
+}
+
+// checkerboard of cubes with sides scale long, filling space, so it does not depend
+// on how the surface is parameterized
+#[derive(Clone)]
+pub struct CheckerTexture {
+    inv_scale: f64,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+}
+
+// checkerboard of width by height squares over the surface coordinates, following
+// the surface as it curves
+#[derive(Clone)]
+pub struct UvChecker {
+    width: u32,
+    height: u32,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+}
+
+// perlin noise scaled to [0, 1] times a color, frequency cells per unit of space
+pub struct NoiseTexture {
+    noise: Perlin,
+    frequency: f64,
+    color: Color,
+}
+
+// stone of color with dark veins: a sine along z distorted by turbulence
+pub struct MarbleTexture {
+    noise: Perlin,
+    frequency: f64,
+    color: Color,
+}
+
+// growth rings around the y axis, light and dark, made irregular by turbulence
+pub struct WoodTexture {
+    noise: Perlin,
+    frequency: f64,
+    light: Color,
+    dark: Color,
+}
+
+// octaves of turbulence used by the marble and wood textures
+const TURBULENCE_DEPTH: u32 = 7;
+
+// how surface coordinates outside [0, 1] map onto an image
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Wrap {
+    // the image tiles the plane
+    #[default]
+    Repeat,
+    // the edge pixels extend outwards
+    Clamp,
+    // the image tiles the plane, every other copy flipped
+    Mirror,
+}
+
+// how the color between pixel centers is found
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Filter {
+    // the pixel the point falls in
+    Nearest,
+    // blend of the four closest pixels
+    #[default]
+    Bilinear,
+}
+
+// image stretched over the surface coordinates, u from the left to the right edge and
+// v from the bottom to the top edge
+pub struct ImageTexture {
+    texels: Texels,
+    wrap: Wrap,
+    filter: Filter,
+}
+
+impl SolidColor {
+    pub fn new(albedo: Color) -> Self {
+        Self { albedo }
+    }
+}
+
+impl Texture for SolidColor {
+    fn value(&self, _u: f64, _v: f64, _point: &Point3) -> Color {
+        self.albedo.clone()
+    }
+}
+
+impl CheckerTexture {
+    pub fn new(scale: f64, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
+        Self {
+            inv_scale: 1.0 / scale,
+            even,
+            odd,
+        }
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value(&self, u: f64, v: f64, point: &Point3) -> Color {
+        let cell = |x: f64| (x * self.inv_scale).floor() as i64;
+        let sum = cell(point.x()) + cell(point.y()) + cell(point.z());
+        if sum.rem_euclid(2) == 0 {
+            self.even.value(u, v, point)
+        } else {
+            self.odd.value(u, v, point)
+        }
+    }
+}
+
+impl UvChecker {
+    pub fn new(width: u32, height: u32, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
+        Self {
+            width,
+            height,
+            even,
+            odd,
+        }
+    }
+}
+
+impl Texture for UvChecker {
+    fn value(&self, u: f64, v: f64, point: &Point3) -> Color {
+        // u and v of 1 belong to the last square rather than starting another one
+        let cell = |x: f64, count: u32| ((x * count as f64).floor() as i64).min(count as i64 - 1);
+        if (cell(u, self.width) + cell(v, self.height)).rem_euclid(2) == 0 {
+            self.even.value(u, v, point)
+        } else {
+            self.odd.value(u, v, point)
+        }
+    }
+}
+
+impl NoiseTexture {
+    pub fn new(noise: Perlin, frequency: f64, color: Color) -> Self {
+        Self {
+            noise,
+            frequency,
+            color,
+        }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value(&self, _u: f64, _v: f64, point: &Point3) -> Color {
+        let noise = self.noise.noise(&(point * self.frequency));
+        &self.color * (0.5 * (1.0 + noise))
+    }
+}
+
+impl MarbleTexture {
+    pub fn new(noise: Perlin, frequency: f64, color: Color) -> Self {
+        Self {
+            noise,
+            frequency,
+            color,
+        }
+    }
+}
+
+impl Texture for MarbleTexture {
+    fn value(&self, _u: f64, _v: f64, point: &Point3) -> Color {
+        let turbulence = self.noise.turbulence(point, TURBULENCE_DEPTH);
+        let vein = 0.5 * (1.0 + (self.frequency * point.z() + 10.0 * turbulence).sin());
+        &self.color * vein
+    }
+}
+
+impl WoodTexture {
+    pub fn new(noise: Perlin, frequency: f64, light: Color, dark: Color) -> Self {
+        Self {
+            noise,
+            frequency,
+            light,
+            dark,
+        }
+    }
+}
+
+impl Texture for WoodTexture {
+    fn value(&self, _u: f64, _v: f64, point: &Point3) -> Color {
+        let radius = (point.x() * point.x() + point.z() * point.z()).sqrt();
+        let turbulence = self.noise.turbulence(point, TURBULENCE_DEPTH);
+        let rings = (radius + 0.5 * turbulence) * self.frequency;
+        // each ring turns from light to dark and back
+        let t = 0.5 * (1.0 - (2.0 * PI * rings).cos());
+        &self.light * (1.0 - t) + &self.dark * t
+    }
+}
+
+impl Wrap {
+    // index of the pixel to use for pixel i of a row or column of count pixels
+    fn index(self, i: i64, count: u32) -> usize {
+        let count = count as i64;
+        let i = match self {
+            Wrap::Repeat => i.rem_euclid(count),
+            Wrap::Clamp => i.clamp(0, count - 1),
+            Wrap::Mirror => {
+                let i = i.rem_euclid(2 * count);
+                if i < count {
+                    i
+                } else {
+                    2 * count - 1 - i
+                }
+            }
+        };
+        i as usize
+    }
+}
+
+impl FromStr for Wrap {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "repeat" => Ok(Wrap::Repeat),
+            "clamp" => Ok(Wrap::Clamp),
+            "mirror" => Ok(Wrap::Mirror),
+            _ => Err(format!(
+                "unknown wrap '{}', expected repeat, clamp or mirror",
+                s
+            )),
+        }
+    }
+}
+
+impl FromStr for Filter {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "nearest" => Ok(Filter::Nearest),
+            "bilinear" => Ok(Filter::Bilinear),
+            _ => Err(format!(
+                "unknown filter '{}', expected nearest or bilinear",
+                s
+            )),
+        }
+    }
+}
+
+impl ImageTexture {
+    // texels must have at least one pixel
+    pub fn new(texels: Texels, wrap: Wrap, filter: Filter) -> Self {
+        Self {
+            texels,
+            wrap,
+            filter,
+        }
+    }
+
+    fn texel(&self, i: i64, j: i64) -> [f32; 3] {
+        let i = self.wrap.index(i, self.texels.width);
+        let j = self.wrap.index(j, self.texels.height);
+        self.texels.pixels[j * self.texels.width as usize + i]
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value(&self, u: f64, v: f64, _point: &Point3) -> Color {
+        // pixel coordinates from the top left corner, with nan treated as 0
+        let x = if u.is_finite() { u } else { 0.0 } * self.texels.width as f64;
+        let y = (1.0 - if v.is_finite() { v } else { 0.0 }) * self.texels.height as f64;
+        let [r, g, b] = match self.filter {
+            Filter::Nearest => self.texel(x.floor() as i64, y.floor() as i64),
+            Filter::Bilinear => {
+                // pixel centers are at half integer coordinates
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (i, j) = (x.floor(), y.floor());
+                let (fx, fy) = ((x - i) as f32, (y - j) as f32);
+                let (i, j) = (i as i64, j as i64);
+                let [c00, c10, c01, c11] =
+                    [(0, 0), (1, 0), (0, 1), (1, 1)].map(|(di, dj)| self.texel(i + di, j + dj));
+                std::array::from_fn(|k| {
+                    (c00[k] * (1.0 - fx) + c10[k] * fx) * (1.0 - fy)
+                        + (c01[k] * (1.0 - fx) + c11[k] * fx) * fy
+                })
+            }
+        };
+        Color::new(r as f64, g as f64, b as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_checker_alternates_in_space() {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let black = Color::new(0.0, 0.0, 0.0);
+        let checker = CheckerTexture::new(
+            0.5,
+            Arc::new(SolidColor::new(white.clone())),
+            Arc::new(SolidColor::new(black.clone())),
+        );
+        let value = |x, y, z| checker.value(0.0, 0.0, &Point3::new(x, y, z));
+        assert_eq!(value(0.1, 0.1, 0.1), white);
+        assert_eq!(value(0.6, 0.1, 0.1), black);
+        assert_eq!(value(0.6, 0.6, 0.1), white);
+        assert_eq!(value(-0.1, 0.1, 0.1), black);
+        assert_eq!(value(-0.1, -0.1, -0.1), black);
+    }
+
+    #[test]
+    fn test_uv_checker_counts_squares() {
+        let white = Color::new(1.0, 1.0, 1.0);
+        let black = Color::new(0.0, 0.0, 0.0);
+        let checker = UvChecker::new(
+            4,
+            2,
+            Arc::new(SolidColor::new(white.clone())),
+            Arc::new(SolidColor::new(black.clone())),
+        );
+        let value = |u, v| checker.value(u, v, &Point3::default());
+        assert_eq!(value(0.1, 0.1), white);
+        assert_eq!(value(0.3, 0.1), black);
+        assert_eq!(value(0.1, 0.6), black);
+        assert_eq!(value(0.3, 0.6), white);
+        assert_eq!(value(1.0, 1.0), white);
+    }
+
+    // 2 by 2 image, black and white in the top row and red and green below
+    fn image_texture(wrap: Wrap, filter: Filter) -> ImageTexture {
+        let texels = Texels {
+            width: 2,
+            height: 2,
+            pixels: vec![[0.0; 3], [1.0; 3], [1.0, 0.0, 0.0], [0.0, 1.0, 0.0]],
+        };
+        ImageTexture::new(texels, wrap, filter)
+    }
+
+    #[test]
+    fn test_image_wrap_and_filter() {
+        let p = Point3::default();
+        let nearest = image_texture(Wrap::Repeat, Filter::Nearest);
+        assert_eq!(nearest.value(0.25, 0.75, &p), Color::new(0.0, 0.0, 0.0));
+        assert_eq!(nearest.value(0.75, 0.25, &p), Color::new(0.0, 1.0, 0.0));
+        assert_eq!(nearest.value(1.25, 1.75, &p), Color::new(0.0, 0.0, 0.0));
+        assert_eq!(nearest.value(-0.25, 0.25, &p), Color::new(0.0, 1.0, 0.0));
+
+        let clamped = image_texture(Wrap::Clamp, Filter::Nearest);
+        assert_eq!(clamped.value(-3.0, 0.25, &p), Color::new(1.0, 0.0, 0.0));
+        let mirrored = image_texture(Wrap::Mirror, Filter::Nearest);
+        assert_eq!(mirrored.value(-0.25, 0.25, &p), Color::new(1.0, 0.0, 0.0));
+        assert_eq!(mirrored.value(1.75, 0.75, &p), Color::new(0.0, 0.0, 0.0));
+
+        // halfway between the two pixels of the top row
+        let bilinear = image_texture(Wrap::Clamp, Filter::Bilinear);
+        assert_eq!(bilinear.value(0.5, 0.75, &p), Color::new(0.5, 0.5, 0.5));
+        assert_eq!(bilinear.value(0.5, 0.5, &p), Color::new(0.5, 0.5, 0.25));
+    }
+}
@@ -0,0 +1,280 @@
+use crate::spectrum::Wavelengths;
+use crate::utils::PI;
+use crate::Color;
+use std::ops::{Add, Div, Mul, Sub};
+use std::sync::OnceLock;
+
+// wavelengths in nanometers the red, green and blue values of an index of refraction
+// given as a color stand for
+const CHANNEL_LAMBDA: [f64; 3] = [650.0, 550.0, 450.0];
+
+// sets of three wavelengths the reflectance of a film is averaged over in rgb, enough
+// for the bands of films up to a micrometer or so
+const RGB_SAMPLES: usize = 16;
+
+// a thin transparent coating, such as the wall of a soap bubble or the oxide layer of
+// anodised metal. the light it reflects off its top and off the surface below
+// interferes, strengthening some wavelengths and cancelling others depending on the
+// thickness and the angle, and colors the reflection
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ThinFilm {
+    pub thickness: f64, // in nanometers
+    pub ior: f64,
+}
+
+impl ThinFilm {
+    pub fn new(thickness: f64, ior: f64) -> Self {
+        Self { thickness, ior }
+    }
+
+    // fraction of the light of lambda nanometers reflected, for light arriving at cos_i
+    // to the normal from a medium of index near onto the film over a medium of index
+    // far, eta + i k for a metal. sums the waves bouncing back and forth inside the
+    // film with the airy formula, for unpolarized light
+    pub fn reflectance(&self, cos_i: f64, lambda: f64, near: f64, far: (f64, f64)) -> f64 {
+        let cos_i = cos_i.clamp(0.0, 1.0);
+        let n1 = Complex::real(near);
+        let n2 = Complex::real(self.ior);
+        let n3 = Complex::new(far.0, far.1);
+        // n cos of the angle in each medium, complex beyond the critical angle and in
+        // metals, where the wave dies away
+        let sin2 = near * near * (1.0 - cos_i * cos_i);
+        let q1 = Complex::real(near * cos_i);
+        let q2 = (n2 * n2 - Complex::real(sin2)).sqrt();
+        let q3 = (n3 * n3 - Complex::real(sin2)).sqrt();
+        // phase the wave gains going down through the film and back up
+        let phase = Complex::new(0.0, 4.0 * PI * self.thickness / lambda) * q2;
+        let delay = phase.exp();
+        let airy = |r12: Complex, r23: Complex| {
+            let r = (r12 + r23 * delay) / (Complex::real(1.0) + r12 * r23 * delay);
+            r.norm_sqr()
+        };
+        let perpendicular = airy((q1 - q2) / (q1 + q2), (q2 - q3) / (q2 + q3));
+        let parallel = |ni: Complex, qi: Complex, nj: Complex, qj: Complex| {
+            (nj * nj * qi - ni * ni * qj) / (nj * nj * qi + ni * ni * qj)
+        };
+        let parallel = airy(parallel(n1, q1, n2, q2), parallel(n2, q2, n3, q3));
+        ((perpendicular + parallel) / 2.0).clamp(0.0, 1.0)
+    }
+
+    // reflectance at the wavelengths in spectral mode, and in rgb the color the film
+    // reflects of white light. far gives the index behind the film at a wavelength
+    pub fn reflectance_color(
+        &self,
+        cos_i: f64,
+        wavelengths: Option<&Wavelengths>,
+        near: f64,
+        far: impl Fn(f64) -> (f64, f64),
+    ) -> Color {
+        match wavelengths {
+            Some(wavelengths) => self.reflectance_spectral(cos_i, wavelengths, near, &far),
+            None => self.reflectance_rgb(cos_i, near, &far),
+        }
+    }
+
+    fn reflectance_spectral(
+        &self,
+        cos_i: f64,
+        wavelengths: &Wavelengths,
+        near: f64,
+        far: impl Fn(f64) -> (f64, f64),
+    ) -> Color {
+        wavelengths.map(|lambda| self.reflectance(cos_i, lambda, near, far(lambda)))
+    }
+
+    // the reflectance averaged over the visible wavelengths, brought into the range of
+    // colors a surface can reflect
+    fn reflectance_rgb(&self, cos_i: f64, near: f64, far: impl Fn(f64) -> (f64, f64)) -> Color {
+        let sum = rgb_weights()
+            .iter()
+            .fold(Color::default(), |sum, (lambda, weight)| {
+                let reflectance = self.reflectance(cos_i, *lambda, near, far(*lambda));
+                sum + weight * reflectance
+            });
+        Color::new(
+            sum.x().clamp(0.0, 1.0),
+            sum.y().clamp(0.0, 1.0),
+            sum.z().clamp(0.0, 1.0),
+        )
+    }
+}
+
+// the wavelengths the reflectance is averaged over in rgb, with the color each adds to
+// the average. they add up to white, so that a gray reflectance stays gray
+fn rgb_weights() -> &'static [(f64, Color)] {
+    static WEIGHTS: OnceLock<Vec<(f64, Color)>> = OnceLock::new();
+    WEIGHTS.get_or_init(|| {
+        let mut weights = Vec::new();
+        for i in 0..RGB_SAMPLES {
+            let wavelengths = Wavelengths::sample((i as f64 + 0.5) / RGB_SAMPLES as f64);
+            let lambda = wavelengths.map(|lambda| lambda);
+            for (lambda, value) in [
+                (lambda.x(), Color::new(1.0, 0.0, 0.0)),
+                (lambda.y(), Color::new(0.0, 1.0, 0.0)),
+                (lambda.z(), Color::new(0.0, 0.0, 1.0)),
+            ] {
+                weights.push((lambda, wavelengths.rgb_of(&value)));
+            }
+        }
+        let white = weights
+            .iter()
+            .fold(Color::default(), |white, (_, weight)| &white + weight);
+        for (_, weight) in &mut weights {
+            *weight = Color::new(
+                weight.x() / white.x(),
+                weight.y() / white.y(),
+                weight.z() / white.z(),
+            );
+        }
+        weights
+    })
+}
+
+// value at lambda nanometers of a quantity given for red, green and blue, interpolated
+// between the wavelengths the channels stand for and held flat beyond
+pub fn channel_value(color: &Color, lambda: f64) -> f64 {
+    let [red, green, blue] = CHANNEL_LAMBDA;
+    if lambda >= red {
+        color.x()
+    } else if lambda >= green {
+        let t = (lambda - green) / (red - green);
+        color.y() * (1.0 - t) + color.x() * t
+    } else if lambda >= blue {
+        let t = (lambda - blue) / (green - blue);
+        color.z() * (1.0 - t) + color.y() * t
+    } else {
+        color.z()
+    }
+}
+
+// just enough complex arithmetic for the amplitudes of waves
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Complex {
+    re: f64,
+    im: f64,
+}
+
+impl Complex {
+    fn new(re: f64, im: f64) -> Self {
+        Self { re, im }
+    }
+
+    fn real(re: f64) -> Self {
+        Self::new(re, 0.0)
+    }
+
+    fn norm_sqr(&self) -> f64 {
+        self.re * self.re + self.im * self.im
+    }
+
+    // principal square root, with a real part of at least 0
+    fn sqrt(&self) -> Self {
+        let norm = self.norm_sqr().sqrt();
+        let re = ((norm + self.re) / 2.0).max(0.0).sqrt();
+        let im = ((norm - self.re) / 2.0).max(0.0).sqrt();
+        Self::new(re, if self.im < 0.0 { -im } else { im })
+    }
+
+    fn exp(&self) -> Self {
+        let length = self.re.exp();
+        Self::new(length * self.im.cos(), length * self.im.sin())
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+impl Div for Complex {
+    type Output = Complex;
+
+    fn div(self, other: Complex) -> Complex {
+        let norm = other.norm_sqr();
+        Complex::new(
+            (self.re * other.re + self.im * other.im) / norm,
+            (self.im * other.re - self.re * other.im) / norm,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::microfacet::{fresnel_conductor, fresnel_dielectric};
+
+    #[test]
+    fn test_no_film() {
+        // a film of no thickness, or of the index above it, leaves the bare fresnel
+        // reflectance of the surface below
+        for cos_i in [1.0, 0.7, 0.3, 0.05] {
+            let film = ThinFilm::new(0.0, 1.8);
+            let glass = fresnel_dielectric(cos_i, 1.5);
+            assert!((film.reflectance(cos_i, 500.0, 1.0, (1.5, 0.0)) - glass).abs() < 1e-9);
+            let film = ThinFilm::new(300.0, 1.0);
+            assert!((film.reflectance(cos_i, 500.0, 1.0, (1.5, 0.0)) - glass).abs() < 1e-9);
+            let gold = fresnel_conductor(cos_i, 0.374, 2.385);
+            let film = ThinFilm::new(0.0, 1.5);
+            assert!((film.reflectance(cos_i, 500.0, 1.0, (0.374, 2.385)) - gold).abs() < 1e-9);
+        }
+        // seen from inside the glass, light beyond the critical angle is all reflected
+        let film = ThinFilm::new(0.0, 1.3);
+        assert!((film.reflectance(0.5, 500.0, 1.5, (1.0, 0.0)) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_interference() {
+        // a quarter wave coating with the square root of the glass' index cancels the
+        // reflection of its wavelength at normal incidence, but not of others
+        let ior = 1.5_f64.sqrt();
+        let film = ThinFilm::new(550.0 / (4.0 * ior), ior);
+        assert!(film.reflectance(1.0, 550.0, 1.0, (1.5, 0.0)) < 1e-9);
+        assert!(film.reflectance(1.0, 400.0, 1.0, (1.5, 0.0)) > 0.005);
+        // the wall of a soap bubble reflects the light of one wavelength and lets
+        // through that of another
+        let soap = ThinFilm::new(300.0, 1.33);
+        let bright = soap.reflectance(1.0, 4.0 * 1.33 * 300.0 / 3.0, 1.0, (1.0, 0.0));
+        let dark = soap.reflectance(1.0, 2.0 * 1.33 * 300.0 / 2.0, 1.0, (1.0, 0.0));
+        assert!(bright > 0.05);
+        assert!(dark < 1e-9);
+        // so it is colored, green for this one, while a film of no thickness is not
+        let color = soap.reflectance_rgb(1.0, 1.0, |_| (1.0, 0.0));
+        assert!(color.y() > color.x() + 0.02 && color.y() > color.z() + 0.02);
+        let gray = ThinFilm::new(0.0, 1.33).reflectance_rgb(1.0, 1.0, |_| (1.5, 0.0));
+        let glass = fresnel_dielectric(1.0, 1.5);
+        for channel in [gray.x(), gray.y(), gray.z()] {
+            assert!((channel - glass).abs() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_channel_value() {
+        let color = Color::new(3.0, 2.0, 1.0);
+        assert_eq!(channel_value(&color, 700.0), 3.0);
+        assert_eq!(channel_value(&color, 600.0), 2.5);
+        assert_eq!(channel_value(&color, 550.0), 2.0);
+        assert_eq!(channel_value(&color, 400.0), 1.0);
+    }
+}
@@ -1,17 +1,111 @@
+use crate::aabb::Aabb;
+use crate::image::Image;
 use crate::interval::Interval;
+use crate::material::ScatterRecord;
+use crate::sampler::Sampler;
+use crate::spectrum::Wavelengths;
 use crate::Color;
 use crate::HitRecord;
 use crate::Ray;
-pub trait Hittable {
+use crate::Result;
+use crate::{Point3, Vec3};
+use std::io::Write;
+pub trait Hittable: Send + Sync {
     fn hit(&self, ray: &Ray, ray_t: &mut Interval) -> Option<HitRecord>;
+    // box enclosing the whole object, used to build the bounding volume hierarchy
+    fn bounding_box(&self) -> Aabb;
+
+    // objects used as lights for direct light sampling implement the two methods below,
+    // moving objects as they are at time, that of the ray being shaded.
+    // probability density (per unit solid angle) of random choosing direction from origin
+    fn pdf_value(&self, _origin: &Point3, _direction: &Vec3, _time: f64) -> f64 {
+        0.0
+    }
+    // random direction from origin towards the object
+    fn random(&self, _origin: &Point3, _time: f64, _sampler: &mut Sampler) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
+
+    // shadow rays pass through media, which only weaken the light they carry. the two
+    // methods below let them find the surface they reach behind any media, and how much
+    // light the media between let through.
+    // hit, leaving out media
+    fn hit_surface(&self, ray: &Ray, ray_t: &mut Interval) -> Option<HitRecord> {
+        self.hit(ray, ray_t)
+    }
+    // fraction of the light travelling along the ray within ray_t that media let
+    // through, estimated without bias, surfaces let everything through
+    fn transmittance(&self, _ray: &Ray, _ray_t: &Interval, _sampler: &mut Sampler) -> f64 {
+        1.0
+    }
 }
 
-pub trait Scatter {
+pub trait Scatter: Send + Sync {
+    // sample the direction the ray continues in after hitting the surface,
+    // None when the ray is absorbed
     fn scatter(
         &self,
         ray_in: &Ray,
         rec: &HitRecord,
-        attenuation: &mut Color,
-        ray_scattered: &mut Ray,
-    ) -> bool;
+        sampler: &mut Sampler,
+    ) -> Option<ScatterRecord>;
+
+    // scatter in spectral mode, where the ray carries wavelengths and the attenuation
+    // holds the factors at them. materials bending each wavelength their own way follow
+    // the hero wavelength and terminate the others, the rest scatter as in rgb
+    fn scatter_spectral(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        wavelengths: &mut Wavelengths,
+        sampler: &mut Sampler,
+    ) -> Option<ScatterRecord> {
+        let mut srec = self.scatter(ray_in, rec, sampler)?;
+        srec.attenuation = wavelengths.spectrum_of(&srec.attenuation);
+        Some(srec)
+    }
+
+    // bsdf times the cosine between the normal and direction, i.e. the fraction of the
+    // light arriving from direction that leaves along the reversed ray_in.
+    // specular materials, which scatter into single directions, leave it at zero
+    fn eval(&self, _ray_in: &Ray, _rec: &HitRecord, _direction: &Vec3) -> Color {
+        Color::default()
+    }
+
+    // eval at the wavelengths in spectral mode
+    fn eval_spectral(
+        &self,
+        ray_in: &Ray,
+        rec: &HitRecord,
+        direction: &Vec3,
+        wavelengths: &Wavelengths,
+    ) -> Color {
+        wavelengths.spectrum_of(&self.eval(ray_in, rec, direction))
+    }
+
+    // probability density (per unit solid angle) of scatter choosing direction
+    fn scattering_pdf(&self, _ray_in: &Ray, _rec: &HitRecord, _direction: &Vec3) -> f64 {
+        0.0
+    }
+
+    // light given off by the surface at the hit point, most materials emit nothing
+    fn emitted(&self, _ray_in: &Ray, _rec: &HitRecord) -> Color {
+        Color::default()
+    }
+
+    // whether the material emits light, objects made of it are sampled as lights
+    fn is_emissive(&self) -> bool {
+        false
+    }
+}
+
+// color varying over a surface, looked up by the surface coordinates u and v of a hit
+// or by the hit point itself
+pub trait Texture: Send + Sync {
+    fn value(&self, u: f64, v: f64, point: &Point3) -> Color;
+}
+
+// writes a rendered image in some file format
+pub trait ImageEncoder {
+    fn encode(&self, image: &Image, writer: &mut dyn Write) -> Result<()>;
 }
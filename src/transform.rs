@@ -0,0 +1,366 @@
+use crate::aabb::Aabb;
+use crate::interval::Interval;
+use crate::sampler::Sampler;
+use crate::utils::degrees_to_radians;
+use crate::vec3::Mat4;
+use crate::HitRecord;
+use crate::Hittable;
+use crate::Point3;
+use crate::Ray;
+use crate::Result;
+use crate::Vec3;
+use std::sync::Arc;
+
+// number of steps the bounding box of a moving transform follows the motion in
+const MOTION_STEPS: usize = 32;
+
+// an object placed in the world by an affine transform. rays are moved into the space
+// of the object and the hits moved back out, so any number of instances can share
+// one object, however large
+pub struct Transform {
+    object: Arc<dyn Hittable>,
+    // object to world, at time 0
+    matrix: Mat4,
+    // world to object, at time 0
+    inverse: Mat4,
+    // the placements at times 0 and 1 of a moving transform, in between it is
+    // interpolated
+    motion: Option<(Placement, Placement)>,
+    bbox: Aabb,
+}
+
+// scaling along the axes, then rotation by degrees around the x, y and z axes in turn,
+// then translation. unlike matrices, placements blend into each other smoothly
+#[derive(Debug, Clone, PartialEq)]
+pub struct Placement {
+    pub scale: Vec3,
+    pub rotate: Vec3,
+    pub translate: Vec3,
+}
+
+impl Default for Placement {
+    fn default() -> Self {
+        Placement {
+            scale: Vec3::new(1.0, 1.0, 1.0),
+            rotate: Vec3::default(),
+            translate: Vec3::default(),
+        }
+    }
+}
+
+impl Placement {
+    pub fn matrix(&self) -> Mat4 {
+        let mut matrix = Mat4::scaling(&self.scale);
+        for axis in 0..3 {
+            let mut direction = [0.0; 3];
+            direction[axis] = 1.0;
+            let [x, y, z] = direction;
+            matrix = &Mat4::rotation(&Vec3::new(x, y, z), self.rotate[axis]) * &matrix;
+        }
+        &Mat4::translation(&self.translate) * &matrix
+    }
+
+    // the placement a fraction t of the way to other
+    fn lerp(&self, other: &Placement, t: f64) -> Placement {
+        let mix = |a: &Vec3, b: &Vec3| a * (1.0 - t) + b * t;
+        Placement {
+            scale: mix(&self.scale, &other.scale),
+            rotate: mix(&self.rotate, &other.rotate),
+            translate: mix(&self.translate, &other.translate),
+        }
+    }
+}
+
+// the box around the eight corners of bbox moved by matrix
+fn transform_box(bbox: &Aabb, matrix: &Mat4) -> Aabb {
+    let mut result = Aabb::default();
+    for corner in 0..8 {
+        let coordinate = |axis: usize| {
+            let interval = bbox.axis_interval(axis);
+            if corner >> axis & 1 == 0 {
+                interval.min
+            } else {
+                interval.max
+            }
+        };
+        let p = matrix.transform_point(&Point3::new(coordinate(0), coordinate(1), coordinate(2)));
+        result = Aabb::enclosing(&result, &Aabb::from_points(&p, &p));
+    }
+    result
+}
+
+impl Transform {
+    pub fn new(object: Arc<dyn Hittable>, matrix: Mat4) -> Result<Self> {
+        let inverse = matrix
+            .inverse()
+            .ok_or("transform squashes the object flat, it has no inverse")?;
+        let bbox = transform_box(&object.bounding_box(), &matrix).pad_to_minimums();
+        Ok(Transform {
+            object,
+            matrix,
+            inverse,
+            motion: None,
+            bbox,
+        })
+    }
+
+    // object moving from placement start at time 0 to end at time 1
+    pub fn moving(object: Arc<dyn Hittable>, start: Placement, end: Placement) -> Result<Self> {
+        // a scale crossing zero would squash the object flat on the way
+        for axis in 0..3 {
+            if start.scale[axis] * end.scale[axis] <= 0.0 {
+                return Err("moving transform squashes the object flat, it has no inverse".into());
+            }
+        }
+        let mut transform = Transform::new(object, start.matrix())?;
+
+        // the boxes at evenly spaced times. between two of them a point turns along an
+        // arc, which bulges past the straight line by up to r (1 - cos(angle / 2)) for a
+        // point at distance r from the center of rotation
+        let object_box = transform.object.bounding_box();
+        let radius = (0..3)
+            .map(|axis| {
+                let interval = object_box.axis_interval(axis);
+                let scale = start.scale[axis].abs().max(end.scale[axis].abs());
+                scale * interval.min.abs().max(interval.max.abs())
+            })
+            .map(|x| x * x)
+            .sum::<f64>()
+            .sqrt();
+        let rotation = &end.rotate - &start.rotate;
+        let step_angle = degrees_to_radians(
+            (rotation.x().abs() + rotation.y().abs() + rotation.z().abs()) / MOTION_STEPS as f64,
+        );
+        let bulge = radius * (1.0 - (step_angle / 2.0).cos());
+        let mut bbox = Aabb::default();
+        for step in 0..=MOTION_STEPS {
+            let placement = start.lerp(&end, step as f64 / MOTION_STEPS as f64);
+            bbox = Aabb::enclosing(&bbox, &transform_box(&object_box, &placement.matrix()));
+        }
+        let grow = |interval: &Interval| interval.expand(2.0 * bulge);
+        transform.bbox = Aabb {
+            x: grow(&bbox.x),
+            y: grow(&bbox.y),
+            z: grow(&bbox.z),
+        }
+        .pad_to_minimums();
+        transform.motion = Some((start, end));
+        Ok(transform)
+    }
+
+    // object to world and world to object matrices at time
+    fn matrices(&self, time: f64) -> (Mat4, Mat4) {
+        match &self.motion {
+            Some((start, end)) => {
+                let matrix = start.lerp(end, time).matrix();
+                // the scale never crosses zero, so the matrix has an inverse
+                let inverse = matrix.inverse().unwrap_or(self.inverse);
+                (matrix, inverse)
+            }
+            None => (self.matrix, self.inverse),
+        }
+    }
+}
+
+impl Transform {
+    // the ray in object space at its time, with the matrices that move it there and
+    // back. the direction is not normalized, so distances along the ray are the same in
+    // both spaces
+    fn to_object(&self, ray: &Ray) -> (Ray, Mat4, Mat4) {
+        let (matrix, inverse) = self.matrices(ray.time);
+        let object_ray = Ray {
+            orig: inverse.transform_point(&ray.orig),
+            dir: inverse.transform_vector(&ray.dir),
+            time: ray.time,
+        };
+        (object_ray, matrix, inverse)
+    }
+
+    // a hit in object space moved out to the world
+    fn to_world(rec: HitRecord, matrix: &Mat4, inverse: &Mat4) -> HitRecord {
+        let mut rec = rec;
+        rec.point = matrix.transform_point(&rec.point);
+        // normals go through the inverse transpose, which keeps them perpendicular to
+        // the surface, and on the same side of it as the ray
+        rec.normal = inverse
+            .transpose()
+            .transform_vector(&rec.normal)
+            .make_unit_vector();
+        rec
+    }
+}
+
+impl Hittable for Transform {
+    fn hit(&self, ray: &Ray, ray_t: &mut Interval) -> Option<HitRecord> {
+        let (object_ray, matrix, inverse) = self.to_object(ray);
+        let rec = self.object.hit(&object_ray, ray_t)?;
+        Some(Self::to_world(rec, &matrix, &inverse))
+    }
+
+    fn hit_surface(&self, ray: &Ray, ray_t: &mut Interval) -> Option<HitRecord> {
+        let (object_ray, matrix, inverse) = self.to_object(ray);
+        let rec = self.object.hit_surface(&object_ray, ray_t)?;
+        Some(Self::to_world(rec, &matrix, &inverse))
+    }
+
+    fn transmittance(&self, ray: &Ray, ray_t: &Interval, sampler: &mut Sampler) -> f64 {
+        let (object_ray, _, _) = self.to_object(ray);
+        self.object.transmittance(&object_ray, ray_t, sampler)
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        self.bbox
+    }
+
+    // the object's density of the direction in object space, changed by how much the
+    // transform stretches solid angles around the direction
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        let (matrix, inverse) = self.matrices(time);
+        let object_direction = inverse.transform_vector(direction).make_unit_vector();
+        let pdf = self
+            .object
+            .pdf_value(&inverse.transform_point(origin), &object_direction, time);
+        let stretch = matrix.transform_vector(&object_direction).length();
+        pdf * stretch.powi(3) / matrix.determinant3().abs()
+    }
+
+    fn random(&self, origin: &Point3, time: f64, sampler: &mut Sampler) -> Vec3 {
+        let (matrix, inverse) = self.matrices(time);
+        let direction = self
+            .object
+            .random(&inverse.transform_point(origin), time, sampler);
+        matrix.transform_vector(&direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::quad::Quad;
+    use crate::utils::INFINITY;
+    use crate::{Lambertian, Sphere};
+
+    #[test]
+    fn test_hit_matches_moved_sphere() {
+        let material = Arc::new(Lambertian::default());
+        let sphere = Arc::new(Sphere::new(Point3::default(), 1.0, material.clone()));
+        let matrix = &Mat4::translation(&Vec3::new(3.0, 0.0, 0.0))
+            * &Mat4::rotation(&Vec3::new(0.0, 1.0, 0.0), 40.0);
+        let transform = Transform::new(sphere, matrix).unwrap();
+        let moved = Sphere::new(Point3::new(3.0, 0.0, 0.0), 1.0, material);
+        // the rotated box around the sphere is wider than the sphere
+        let bbox = transform.bounding_box();
+        assert!(bbox.x.min < 2.0 && bbox.x.min > 1.5);
+        assert!(bbox.x.max > 4.0 && bbox.x.max < 4.5);
+
+        let mut sampler = Sampler::new(2);
+        for _ in 0..200 {
+            let ray = Ray {
+                orig: Point3::new(3.0, 0.0, 5.0),
+                dir: &Vec3::random(-0.3, 0.3, &mut sampler) - &Vec3::new(0.0, 0.0, 1.0),
+                time: 0.0,
+            };
+            let expected = moved.hit(&ray, &mut Interval::new(0.001, INFINITY));
+            let actual = transform.hit(&ray, &mut Interval::new(0.001, INFINITY));
+            assert_eq!(expected.is_some(), actual.is_some());
+            if let (Some(expected), Some(actual)) = (expected, actual) {
+                assert!((expected.t - actual.t).abs() < 1e-9);
+                assert!((&expected.normal - &actual.normal).length() < 1e-9);
+                assert_eq!(expected.front_face, actual.front_face);
+            }
+        }
+    }
+
+    #[test]
+    fn test_pdf_matches_transformed_light() {
+        // a unit square light scaled unevenly and tilted, against the same light built
+        // in place
+        let material = Arc::new(Lambertian::default());
+        let unit = Arc::new(Quad::new(
+            Point3::new(0.0, 0.0, 0.0),
+            Vec3::new(1.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 1.0),
+            material.clone(),
+        ));
+        let matrix = &Mat4::translation(&Vec3::new(0.0, 2.0, 0.0))
+            * &(&Mat4::rotation(&Vec3::new(1.0, 0.0, 0.0), 20.0)
+                * &Mat4::scaling(&Vec3::new(2.0, 1.0, 0.5)));
+        let transform = Transform::new(unit, matrix).unwrap();
+        let direct = Quad::new(
+            matrix.transform_point(&Point3::new(0.0, 0.0, 0.0)),
+            matrix.transform_vector(&Vec3::new(1.0, 0.0, 0.0)),
+            matrix.transform_vector(&Vec3::new(0.0, 0.0, 1.0)),
+            material,
+        );
+        let origin = Point3::new(0.5, 0.0, 0.3);
+        let mut sampler = Sampler::new(8);
+        for _ in 0..50 {
+            let direction = transform.random(&origin, 0.0, &mut sampler);
+            let expected = direct.pdf_value(&origin, &direction, 0.0);
+            let actual = transform.pdf_value(&origin, &direction, 0.0);
+            assert!(expected > 0.0);
+            assert!(
+                (expected - actual).abs() < 1e-6 * expected,
+                "{} {}",
+                expected,
+                actual
+            );
+        }
+    }
+
+    #[test]
+    fn test_moving_transform() {
+        // the top of a long thin bar spinning a quarter turn around y while it moves up
+        let material = Arc::new(Lambertian::default());
+        let top = Quad::new(
+            Point3::new(-2.0, 0.1, -0.1),
+            Vec3::new(4.0, 0.0, 0.0),
+            Vec3::new(0.0, 0.0, 0.2),
+            material,
+        );
+        let start = Placement::default();
+        let end = Placement {
+            rotate: Vec3::new(0.0, 90.0, 0.0),
+            translate: Vec3::new(0.0, 1.0, 0.0),
+            ..Placement::default()
+        };
+        let transform = Transform::moving(Arc::new(top), start.clone(), end.clone()).unwrap();
+
+        // every point on the path stays inside the box, including the ends of the bar
+        // turning through 45 degrees
+        let bbox = transform.bounding_box();
+        for step in 0..=100 {
+            let matrix = start.lerp(&end, step as f64 / 100.0).matrix();
+            for (x, z) in [(-2.0, -0.1), (-2.0, 0.1), (2.0, -0.1), (2.0, 0.1)] {
+                let p = matrix.transform_point(&Point3::new(x, 0.1, z));
+                assert!(bbox.x.contains(p.x()) && bbox.y.contains(p.y()) && bbox.z.contains(p.z()));
+            }
+        }
+
+        // a ray straight down at z = 1.5 meets the bar once it has turned far enough
+        let hit_at = |time: f64| {
+            let ray = Ray {
+                orig: Point3::new(0.0, 5.0, 1.5),
+                dir: Vec3::new(0.0, -1.0, 0.0),
+                time,
+            };
+            transform.hit(&ray, &mut Interval::new(0.001, INFINITY))
+        };
+        assert!(hit_at(0.0).is_none());
+        let rec = hit_at(1.0).unwrap();
+        assert!((rec.point.y() - 1.1).abs() < 1e-9);
+        assert!((&rec.normal - &Vec3::new(0.0, 1.0, 0.0)).length() < 1e-9);
+
+        // a scale passing through zero flattens the object on the way
+        let flipped = Placement {
+            scale: Vec3::new(-1.0, 1.0, 1.0),
+            ..Placement::default()
+        };
+        let sphere = Arc::new(Sphere::new(
+            Point3::default(),
+            1.0,
+            Arc::new(Lambertian::default()),
+        ));
+        assert!(Transform::moving(sphere, start, flipped).is_err());
+    }
+}
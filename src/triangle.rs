@@ -0,0 +1,264 @@
+use crate::aabb::Aabb;
+use crate::interval::Interval;
+use crate::sampler::Sampler;
+use crate::utils::INFINITY;
+use crate::HitRecord;
+use crate::Hittable;
+use crate::Point3;
+use crate::Ray;
+use crate::Scatter;
+use crate::Vec3;
+use std::sync::Arc;
+
+// triangle with corners a, b and c, the front face is the side (b - a) x (c - a)
+// points to, i.e. the one from which the corners are seen counterclockwise
+#[derive(Clone)]
+pub struct Triangle {
+    pub vertices: [Point3; 3],
+    // normals at the corners, interpolated over the triangle for smooth shading
+    pub normals: Option<[Vec3; 3]>,
+    // texture coordinates at the corners, without them the hit record gets the
+    // barycentric coordinates of b and c
+    pub uvs: Option<[(f64, f64); 3]>,
+    material: Arc<dyn Scatter>,
+    normal: Vec3,
+    area: f64,
+}
+
+impl Triangle {
+    pub fn new(vertices: [Point3; 3], material: Arc<dyn Scatter>) -> Self {
+        let n = (&vertices[1] - &vertices[0]).cross(&(&vertices[2] - &vertices[0]));
+        Triangle {
+            normal: n.make_unit_vector(),
+            area: n.length() / 2.0,
+            vertices,
+            normals: None,
+            uvs: None,
+            material,
+        }
+    }
+
+    pub fn with_normals(mut self, normals: [Vec3; 3]) -> Self {
+        self.normals = Some(normals);
+        self
+    }
+
+    pub fn with_uvs(mut self, uvs: [(f64, f64); 3]) -> Self {
+        self.uvs = Some(uvs);
+        self
+    }
+}
+
+// moller-trumbore intersection of the ray with the triangle with the given corners,
+// the distance along the ray and the barycentric coordinates of the second and third
+// corner at the hit point
+pub fn intersect(vertices: [&Point3; 3], ray: &Ray, ray_t: &Interval) -> Option<(f64, f64, f64)> {
+    let [a, b, c] = vertices;
+    let edge1 = b - a;
+    let edge2 = c - a;
+    let p = ray.dir.cross(&edge2);
+    let det = edge1.dot(&p);
+    // no hit if the ray is parallel to the triangle
+    if det.abs() < 1e-12 {
+        return None;
+    }
+    let inv_det = 1.0 / det;
+    let s = &ray.orig - a;
+    let beta = s.dot(&p) * inv_det;
+    if !(0.0..=1.0).contains(&beta) {
+        return None;
+    }
+    let q = s.cross(&edge1);
+    let gamma = ray.dir.dot(&q) * inv_det;
+    if gamma < 0.0 || beta + gamma > 1.0 {
+        return None;
+    }
+    let t = edge2.dot(&q) * inv_det;
+    if !ray_t.surrounds(t) {
+        return None;
+    }
+    Some((t, beta, gamma))
+}
+
+// hit record of a hit found by intersect. normal is the unit geometric normal, the
+// corner normals and uvs are interpolated when given
+pub fn hit_record(
+    ray: &Ray,
+    (t, beta, gamma): (f64, f64, f64),
+    normal: &Vec3,
+    normals: Option<[&Vec3; 3]>,
+    uvs: Option<[(f64, f64); 3]>,
+    material: &Arc<dyn Scatter>,
+) -> HitRecord {
+    let alpha = 1.0 - beta - gamma;
+    let mut hit_record = HitRecord::new();
+    hit_record.t = t;
+    hit_record.point = ray.at(t);
+    hit_record.set_face_normal(ray, normal);
+    if let Some([na, nb, nc]) = normals {
+        let shading = alpha * na + beta * nb + gamma * nc;
+        // corner normals cancelling out give no direction, the face stays flat
+        let length = shading.length();
+        if length.is_finite() && length > 1e-8 {
+            // the shading normal is turned to the side of the geometric one, whatever the
+            // winding of the corner normals, then to the side of the ray like it
+            let shading = shading / length;
+            let shading = if shading.dot(normal) < 0.0 {
+                &shading * -1.0
+            } else {
+                shading
+            };
+            hit_record.normal = if hit_record.front_face {
+                shading
+            } else {
+                &shading * -1.0
+            };
+        }
+    }
+    (hit_record.u, hit_record.v) = match uvs {
+        Some([ta, tb, tc]) => (
+            alpha * ta.0 + beta * tb.0 + gamma * tc.0,
+            alpha * ta.1 + beta * tb.1 + gamma * tc.1,
+        ),
+        None => (beta, gamma),
+    };
+    hit_record.material = material.clone();
+    hit_record
+}
+
+// uniformly chosen point of the triangle with the given corners
+pub fn random_point(vertices: [&Point3; 3], sampler: &mut Sampler) -> Point3 {
+    let r1 = sampler.random_f64().sqrt();
+    let r2 = sampler.random_f64();
+    let [a, b, c] = vertices;
+    (1.0 - r1) * a + (r1 * (1.0 - r2)) * b + (r1 * r2) * c
+}
+
+impl Hittable for Triangle {
+    fn hit(&self, ray: &Ray, ray_t: &mut Interval) -> Option<HitRecord> {
+        let [a, b, c] = &self.vertices;
+        let hit = intersect([a, b, c], ray, ray_t)?;
+        let normals = self.normals.as_ref().map(|[na, nb, nc]| [na, nb, nc]);
+        Some(hit_record(
+            ray,
+            hit,
+            &self.normal,
+            normals,
+            self.uvs,
+            &self.material,
+        ))
+    }
+
+    fn bounding_box(&self) -> Aabb {
+        let [a, b, c] = &self.vertices;
+        Aabb::enclosing(&Aabb::from_points(a, b), &Aabb::from_points(b, c)).pad_to_minimums()
+    }
+
+    fn pdf_value(&self, origin: &Point3, direction: &Vec3, time: f64) -> f64 {
+        let ray = Ray {
+            orig: origin.clone(),
+            dir: direction.clone(),
+            time,
+        };
+        if self
+            .hit(&ray, &mut Interval::new(0.001, INFINITY))
+            .is_none()
+        {
+            return 0.0;
+        }
+        // with the geometric normal, the shading normal has nothing to do with the area
+        let t = (&self.vertices[0] - origin).dot(&self.normal) / direction.dot(&self.normal);
+        let distance_squared = t * t * direction.length_squared();
+        let cosine = (direction.dot(&self.normal) / direction.length()).abs();
+        distance_squared / (cosine * self.area)
+    }
+
+    // direction towards a uniformly chosen point of the triangle
+    fn random(&self, origin: &Point3, _time: f64, sampler: &mut Sampler) -> Vec3 {
+        let [a, b, c] = &self.vertices;
+        &random_point([a, b, c], sampler) - origin
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Lambertian;
+
+    fn triangle() -> Triangle {
+        Triangle::new(
+            [
+                Point3::new(0.0, 0.0, 0.0),
+                Point3::new(1.0, 0.0, 0.0),
+                Point3::new(0.0, 1.0, 0.0),
+            ],
+            Arc::new(Lambertian::default()),
+        )
+    }
+
+    fn ray_at(x: f64, y: f64) -> Ray {
+        Ray {
+            orig: Point3::new(x, y, 2.0),
+            dir: Vec3::new(0.0, 0.0, -1.0),
+            time: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_hit() {
+        let rec = triangle()
+            .hit(&ray_at(0.25, 0.5), &mut Interval::new(0.001, INFINITY))
+            .unwrap();
+        assert_eq!(rec.t, 2.0);
+        assert!(rec.front_face);
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!((rec.u, rec.v), (0.25, 0.5));
+        assert!(triangle()
+            .hit(&ray_at(0.6, 0.6), &mut Interval::new(0.001, INFINITY))
+            .is_none());
+    }
+
+    #[test]
+    fn test_interpolated_normals_and_uvs() {
+        let tilted = Vec3::new(1.0, 0.0, 1.0).make_unit_vector();
+        let triangle = triangle()
+            .with_normals([Vec3::new(0.0, 0.0, 1.0), tilted.clone(), tilted.clone()])
+            .with_uvs([(0.0, 0.0), (2.0, 0.0), (0.0, 4.0)]);
+        let rec = triangle
+            .hit(&ray_at(0.25, 0.25), &mut Interval::new(0.001, INFINITY))
+            .unwrap();
+        assert!((rec.normal.length() - 1.0).abs() < 1e-12);
+        assert!(rec.normal.x() > 0.0 && rec.normal.z() > 0.0);
+        assert_eq!((rec.u, rec.v), (0.5, 1.0));
+    }
+
+    #[test]
+    fn test_bad_corner_normals() {
+        // corner normals facing away from the winding are turned to the geometric side
+        let down = Vec3::new(0.0, 0.0, -1.0);
+        let rec = triangle()
+            .with_normals([down.clone(), down.clone(), down])
+            .hit(&ray_at(0.25, 0.25), &mut Interval::new(0.001, INFINITY))
+            .unwrap();
+        assert!(rec.front_face);
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+        // zero corner normals leave the geometric normal
+        let rec = triangle()
+            .with_normals([Vec3::default(), Vec3::default(), Vec3::default()])
+            .hit(&ray_at(0.25, 0.25), &mut Interval::new(0.001, INFINITY))
+            .unwrap();
+        assert_eq!(rec.normal, Vec3::new(0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn test_random_hits_triangle() {
+        let triangle = triangle();
+        let origin = Point3::new(0.2, 0.2, 1.0);
+        let mut sampler = Sampler::new(3);
+        for _ in 0..100 {
+            let direction = triangle.random(&origin, 0.0, &mut sampler);
+            let pdf = triangle.pdf_value(&origin, &direction, 0.0);
+            assert!(pdf > 0.0 && pdf.is_finite());
+        }
+    }
+}
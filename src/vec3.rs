@@ -1,4 +1,5 @@
-use crate::utils::random_f64_range;
+use crate::sampler::Sampler;
+use crate::utils::degrees_to_radians;
 use std::fmt;
 use std::ops;
 
@@ -67,12 +68,12 @@ impl Vec3 {
     }
 
     // generate a random Vec3 that is within unit disk, i.e., with length < 1
-    pub fn random_in_unit_disk() -> Self {
+    pub fn random_in_unit_disk(sampler: &mut Sampler) -> Self {
         loop {
             let p = Vec3 {
-                x: random_f64_range(-1.0, 1.0),
-                y: random_f64_range(-1.0, 1.0),
-                z: random_f64_range(-1.0, 1.0),
+                x: sampler.random_f64_range(-1.0, 1.0),
+                y: sampler.random_f64_range(-1.0, 1.0),
+                z: sampler.random_f64_range(-1.0, 1.0),
             };
             if p.length() < 1.0 {
                 return p;
@@ -80,32 +81,32 @@ impl Vec3 {
         }
     }
     // generate a random Vec3 where each element is between min and max
-    pub fn random(min: f64, max: f64) -> Self {
+    pub fn random(min: f64, max: f64, sampler: &mut Sampler) -> Self {
         Vec3 {
-            x: random_f64_range(min, max),
-            y: random_f64_range(min, max),
-            z: random_f64_range(min, max),
+            x: sampler.random_f64_range(min, max),
+            y: sampler.random_f64_range(min, max),
+            z: sampler.random_f64_range(min, max),
         }
     }
 
-    pub fn random_in_unit_sphere() -> Self {
+    pub fn random_in_unit_sphere(sampler: &mut Sampler) -> Self {
         loop {
-            let p = Self::random(-1.0, 1.0);
+            let p = Self::random(-1.0, 1.0, sampler);
             if p.length_squared() < 1.0 {
                 return p;
             }
         }
     }
-    pub fn random_unit_vec3() -> Self {
-        return Self::unit_vector(&Self::random_in_unit_sphere());
+    pub fn random_unit_vec3(sampler: &mut Sampler) -> Self {
+        Self::unit_vector(&Self::random_in_unit_sphere(sampler))
     }
-    pub fn random_unit_on_hemisphere(normal: &Vec3) -> Self {
-        let on_unit_sphere = Self::random_unit_vec3();
+    pub fn random_unit_on_hemisphere(normal: &Vec3, sampler: &mut Sampler) -> Self {
+        let on_unit_sphere = Self::random_unit_vec3(sampler);
         if on_unit_sphere.dot(normal) > 0.0 {
             // in the same hemisphere as the normal
-            return on_unit_sphere;
+            on_unit_sphere
         } else {
-            return on_unit_sphere * -1.0;
+            on_unit_sphere * -1.0
         }
     }
 
@@ -116,7 +117,7 @@ impl Vec3 {
 
     // reflection of light
     pub fn reflect(v: &Vec3, n: &Vec3) -> Self {
-        let tmplength = 2.0 * v.dot(&n);
+        let tmplength = 2.0 * v.dot(n);
         v - &(n * tmplength)
     }
 
@@ -142,6 +143,20 @@ impl fmt::Display for Vec3 {
     }
 }
 
+// access the components by axis number, 0 for x, 1 for y and 2 for z
+impl ops::Index<usize> for Vec3 {
+    type Output = f64;
+
+    fn index(&self, axis: usize) -> &Self::Output {
+        match axis {
+            0 => &self.x,
+            1 => &self.y,
+            2 => &self.z,
+            _ => panic!("Vec3 axis out of range: {}", axis),
+        }
+    }
+}
+
 impl ops::Add<Vec3> for Vec3 {
     type Output = Vec3;
 
@@ -332,6 +347,154 @@ impl ops::MulAssign<f64> for Vec3 {
     }
 }
 
+// 4x4 matrix of an affine transform, stored row by row. points are transformed with a
+// fourth coordinate of 1, so they move with the translation, and vectors with 0
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mat4 {
+    rows: [[f64; 4]; 4],
+}
+
+impl Mat4 {
+    pub fn new(rows: [[f64; 4]; 4]) -> Self {
+        Mat4 { rows }
+    }
+
+    // matrix stored column by column, as in gltf files
+    pub fn from_columns(columns: [[f64; 4]; 4]) -> Self {
+        Mat4 { rows: columns }.transpose()
+    }
+
+    pub fn identity() -> Self {
+        Self::scaling(&Vec3::new(1.0, 1.0, 1.0))
+    }
+
+    pub fn translation(offset: &Vec3) -> Self {
+        let mut m = Self::identity();
+        for axis in 0..3 {
+            m.rows[axis][3] = offset[axis];
+        }
+        m
+    }
+
+    // scaling by factor[axis] along each axis
+    pub fn scaling(factor: &Vec3) -> Self {
+        let mut rows = [[0.0; 4]; 4];
+        for axis in 0..3 {
+            rows[axis][axis] = factor[axis];
+        }
+        rows[3][3] = 1.0;
+        Mat4 { rows }
+    }
+
+    // counterclockwise rotation by degrees around axis, looking from its tip
+    pub fn rotation(axis: &Vec3, degrees: f64) -> Self {
+        let a = axis.make_unit_vector();
+        let (sin, cos) = degrees_to_radians(degrees).sin_cos();
+        let t = 1.0 - cos;
+        let (x, y, z) = (a.x, a.y, a.z);
+        Mat4::new([
+            [
+                t * x * x + cos,
+                t * x * y - sin * z,
+                t * x * z + sin * y,
+                0.0,
+            ],
+            [
+                t * x * y + sin * z,
+                t * y * y + cos,
+                t * y * z - sin * x,
+                0.0,
+            ],
+            [
+                t * x * z - sin * y,
+                t * y * z + sin * x,
+                t * z * z + cos,
+                0.0,
+            ],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut rows = [[0.0; 4]; 4];
+        for (i, row) in rows.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.rows[j][i];
+            }
+        }
+        Mat4 { rows }
+    }
+
+    // inverse by gauss-jordan elimination, None when the matrix is singular
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.rows;
+        let mut inv = Self::identity().rows;
+        for column in 0..4 {
+            // the row with the largest value in the column keeps the error small
+            let pivot =
+                (column..4).max_by(|&i, &j| a[i][column].abs().total_cmp(&a[j][column].abs()))?;
+            if a[pivot][column].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(column, pivot);
+            inv.swap(column, pivot);
+            let scale = 1.0 / a[column][column];
+            for k in 0..4 {
+                a[column][k] *= scale;
+                inv[column][k] *= scale;
+            }
+            for row in 0..4 {
+                if row != column {
+                    let factor = a[row][column];
+                    for k in 0..4 {
+                        a[row][k] -= factor * a[column][k];
+                        inv[row][k] -= factor * inv[column][k];
+                    }
+                }
+            }
+        }
+        Some(Mat4 { rows: inv })
+    }
+
+    // determinant of the upper left 3x3 part, negative for transforms that mirror
+    pub fn determinant3(&self) -> f64 {
+        let [a, b, c] =
+            [0, 1, 2].map(|i| Vec3::new(self.rows[0][i], self.rows[1][i], self.rows[2][i]));
+        a.dot(&b.cross(&c))
+    }
+
+    pub fn transform_point(&self, p: &Point3) -> Point3 {
+        let row = |r: &[f64; 4]| r[0] * p.x + r[1] * p.y + r[2] * p.z + r[3];
+        Vec3::new(row(&self.rows[0]), row(&self.rows[1]), row(&self.rows[2]))
+    }
+
+    pub fn transform_vector(&self, v: &Vec3) -> Vec3 {
+        let row = |r: &[f64; 4]| r[0] * v.x + r[1] * v.y + r[2] * v.z;
+        Vec3::new(row(&self.rows[0]), row(&self.rows[1]), row(&self.rows[2]))
+    }
+}
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+// the transform applying rhs first and then self
+impl ops::Mul for &Mat4 {
+    type Output = Mat4;
+
+    fn mul(self, rhs: &Mat4) -> Self::Output {
+        let mut rows = [[0.0; 4]; 4];
+        for (i, row) in rows.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.rows[i][k] * rhs.rows[k][j]).sum();
+            }
+        }
+        Mat4 { rows }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -459,6 +622,11 @@ mod tests {
         );
     }
     #[test]
+    fn test_index() {
+        let v = Vec3::new(1.0, 2.0, 3.0);
+        assert_eq!((v[0], v[1], v[2]), (1.0, 2.0, 3.0));
+    }
+    #[test]
     fn test_length() {
         let mut v1 = Vec3::new(3.0, 4.0, 0.0);
         assert_eq!(v1.length(), 5.0);
@@ -470,4 +638,42 @@ mod tests {
         let mut v2 = Vec3::new(2.0, 4.0, 0.0);
         assert_eq!(v1.dot(&v2), 22.0);
     }
+
+    fn assert_near(a: &Vec3, b: &Vec3) {
+        assert!((a - b).length() < 1e-9, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn test_mat4_transforms() {
+        let rotation = Mat4::rotation(&Vec3::new(0.0, 0.0, 2.0), 90.0);
+        assert_near(
+            &rotation.transform_vector(&Vec3::new(1.0, 0.0, 0.0)),
+            &Vec3::new(0.0, 1.0, 0.0),
+        );
+        let m = &Mat4::translation(&Vec3::new(1.0, 2.0, 3.0))
+            * &(&rotation * &Mat4::scaling(&Vec3::new(2.0, 2.0, 2.0)));
+        let p = Point3::new(1.0, 0.0, 0.0);
+        assert_near(&m.transform_point(&p), &Point3::new(1.0, 4.0, 3.0));
+        assert_near(&m.transform_vector(&p), &Vec3::new(0.0, 2.0, 0.0));
+        assert!((m.determinant3() - 8.0).abs() < 1e-9);
+        assert!(Mat4::scaling(&Vec3::new(-1.0, 1.0, 1.0)).determinant3() < 0.0);
+    }
+
+    #[test]
+    fn test_mat4_inverse() {
+        let m = &Mat4::translation(&Vec3::new(1.0, -2.0, 3.0))
+            * &(&Mat4::rotation(&Vec3::new(1.0, 1.0, 0.0), 33.0)
+                * &Mat4::scaling(&Vec3::new(2.0, 0.5, -1.0)));
+        let inverse = m.inverse().unwrap();
+        let p = Point3::new(0.3, -0.7, 2.0);
+        assert_near(&inverse.transform_point(&m.transform_point(&p)), &p);
+        let product = &m * &inverse;
+        for i in 0..4 {
+            for j in 0..4 {
+                let expected = if i == j { 1.0 } else { 0.0 };
+                assert!((product.rows[i][j] - expected).abs() < 1e-9);
+            }
+        }
+        assert_eq!(Mat4::scaling(&Vec3::new(1.0, 0.0, 1.0)).inverse(), None);
+    }
 }
@@ -0,0 +1,196 @@
+use crate::perlin::Perlin;
+use crate::Point3;
+use crate::Result;
+use std::fs;
+use std::path::Path;
+
+// number of octaves of the turbulence filling a grid with noise
+const TURBULENCE_DEPTH: u32 = 7;
+
+// densities on a regular 3d grid of voxels, stretched over the unit cube. between the
+// voxel centers the density is interpolated, beyond the outer ones it stays as it is
+pub struct VoxelGrid {
+    resolution: [usize; 3],
+    // x varies fastest, then y, then z
+    values: Vec<f32>,
+    max: f64,
+}
+
+// number of voxels of a grid of the given resolution
+fn voxel_count(resolution: [usize; 3]) -> Result<usize> {
+    let [nx, ny, nz] = resolution;
+    nx.checked_mul(ny)
+        .and_then(|count| count.checked_mul(nz))
+        .ok_or_else(|| format!("voxel grid resolution {:?} is too large", resolution).into())
+}
+
+impl VoxelGrid {
+    pub fn new(resolution: [usize; 3], values: Vec<f32>) -> Result<Self> {
+        if resolution.contains(&0) {
+            return Err(format!("voxel grid resolution {:?} has no voxels", resolution).into());
+        }
+        let count = voxel_count(resolution)?;
+        if values.len() != count {
+            return Err(format!(
+                "voxel grid of resolution {:?} needs {} values, not {}",
+                resolution,
+                count,
+                values.len()
+            )
+            .into());
+        }
+        if let Some(value) = values.iter().find(|v| !(v.is_finite() && **v >= 0.0)) {
+            return Err(format!("voxel density {} is not a non-negative number", value).into());
+        }
+        let max = values.iter().fold(0.0f32, |a, &b| a.max(b)) as f64;
+        Ok(VoxelGrid {
+            resolution,
+            values,
+            max,
+        })
+    }
+
+    // turbulence at frequency cells per side of the grid, faded out towards the sides of
+    // the grid so a medium made of it ends softly within its boundary
+    pub fn from_noise(resolution: [usize; 3], perlin: &Perlin, frequency: f64) -> Result<Self> {
+        let [nx, ny, nz] = resolution;
+        let mut values = Vec::with_capacity(voxel_count(resolution)?);
+        for k in 0..nz {
+            for j in 0..ny {
+                for i in 0..nx {
+                    // voxel center in [-1, 1] on each axis
+                    let center = [(i, nx), (j, ny), (k, nz)]
+                        .map(|(i, n)| 2.0 * (i as f64 + 0.5) / n as f64 - 1.0);
+                    let radius_squared: f64 = center.iter().map(|x| x * x).sum();
+                    let fade = (1.0 - radius_squared).max(0.0).powi(2);
+                    let p = Point3::new(center[0], center[1], center[2]) * (frequency / 2.0);
+                    values.push((fade * perlin.turbulence(&p, TURBULENCE_DEPTH)) as f32);
+                }
+            }
+        }
+        VoxelGrid::new(resolution, values)
+    }
+
+    // largest density anywhere in the grid
+    pub fn max(&self) -> f64 {
+        self.max
+    }
+
+    // density at p, given in coordinates from 0 to 1 across the grid
+    pub fn density(&self, p: [f64; 3]) -> f64 {
+        // the two voxels on either side of p on each axis, and how far p is between them
+        let mut lower = [0; 3];
+        let mut upper = [0; 3];
+        let mut weight = [0.0; 3];
+        for axis in 0..3 {
+            let n = self.resolution[axis];
+            let x = (p[axis] * n as f64 - 0.5).clamp(0.0, (n - 1) as f64);
+            lower[axis] = x as usize;
+            upper[axis] = (lower[axis] + 1).min(n - 1);
+            weight[axis] = x - lower[axis] as f64;
+        }
+        let [nx, ny, _] = self.resolution;
+        let mut density = 0.0;
+        for corner in 0..8 {
+            let mut index = [0; 3];
+            let mut corner_weight = 1.0;
+            for axis in 0..3 {
+                if corner >> axis & 1 == 0 {
+                    index[axis] = lower[axis];
+                    corner_weight *= 1.0 - weight[axis];
+                } else {
+                    index[axis] = upper[axis];
+                    corner_weight *= weight[axis];
+                }
+            }
+            let value = self.values[index[0] + nx * (index[1] + ny * index[2])];
+            density += corner_weight * value as f64;
+        }
+        density
+    }
+}
+
+// read a raw voxel file: the densities of a grid of the given resolution with x
+// varying fastest, then y, then z, and nothing else. the densities are either bytes,
+// 0 to 255 for densities from 0 to 1, or little-endian 32 bit floats, told apart by the
+// size of the file
+pub fn read_raw(path: &Path, resolution: [usize; 3]) -> Result<VoxelGrid> {
+    let data = fs::read(path)
+        .map_err(|err| format!("cannot read voxel file {}: {}", path.display(), err))?;
+    decode_raw(&data, resolution).map_err(|err| format!("{}: {}", path.display(), err).into())
+}
+
+pub fn decode_raw(data: &[u8], resolution: [usize; 3]) -> Result<VoxelGrid> {
+    let count = voxel_count(resolution)?;
+    let values = if data.len() == count {
+        data.iter().map(|&value| value as f32 / 255.0).collect()
+    } else if Some(data.len()) == count.checked_mul(4) {
+        data.chunks_exact(4)
+            .map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+            .collect()
+    } else {
+        return Err(format!(
+            "{} bytes are neither {} bytes nor {} floats of a {:?} voxel grid",
+            data.len(),
+            count,
+            count,
+            resolution
+        )
+        .into());
+    };
+    VoxelGrid::new(resolution, values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::Sampler;
+
+    #[test]
+    fn test_density_is_interpolated() {
+        // two voxels along x, one along y and z
+        let grid = VoxelGrid::new([2, 1, 1], vec![1.0, 3.0]).unwrap();
+        assert_eq!(grid.max(), 3.0);
+        assert_eq!(grid.density([0.25, 0.5, 0.5]), 1.0);
+        assert_eq!(grid.density([0.5, 0.5, 0.5]), 2.0);
+        assert_eq!(grid.density([0.75, 0.9, 0.1]), 3.0);
+        // beyond the voxel centers the density stays the same
+        assert_eq!(grid.density([0.0, 0.5, 0.5]), 1.0);
+        assert_eq!(grid.density([1.0, 0.5, 0.5]), 3.0);
+    }
+
+    #[test]
+    fn test_decode_raw() {
+        let bytes = decode_raw(&[0, 255, 51, 0, 0, 0, 0, 255], [2, 2, 2]).unwrap();
+        assert_eq!(bytes.max(), 1.0);
+        // the middle of the grid averages all eight voxels
+        let middle = bytes.density([0.5, 0.5, 0.5]);
+        assert!((middle - 2.2 / 8.0).abs() < 1e-6, "{}", middle);
+
+        let data: Vec<u8> = [0.5f32, 2.0].iter().flat_map(|v| v.to_le_bytes()).collect();
+        let floats = decode_raw(&data, [1, 2, 1]).unwrap();
+        assert_eq!(floats.density([0.5, 1.0, 0.5]), 2.0);
+
+        let err = decode_raw(&[0; 7], [2, 2, 2]).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "7 bytes are neither 8 bytes nor 8 floats of a [2, 2, 2] voxel grid"
+        );
+        let data: Vec<u8> = (-1.0f32).to_le_bytes().to_vec();
+        assert!(decode_raw(&data, [1, 1, 1]).is_err());
+        let err = decode_raw(&[0; 8], [1 << 32, 1 << 32, 2]).err().unwrap();
+        assert_eq!(
+            err.to_string(),
+            "voxel grid resolution [4294967296, 4294967296, 2] is too large"
+        );
+    }
+
+    #[test]
+    fn test_noise_fades_out() {
+        let perlin = Perlin::new(&mut Sampler::new(1));
+        let grid = VoxelGrid::from_noise([16, 16, 16], &perlin, 4.0).unwrap();
+        assert!(grid.max() > 0.0);
+        assert_eq!(grid.density([0.0, 0.0, 0.0]), 0.0);
+        assert_eq!(grid.density([1.0, 1.0, 0.5]), 0.0);
+    }
+}